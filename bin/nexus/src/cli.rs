@@ -25,7 +25,11 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
 
     tracing::info!("Starting Nexus with config: {:?}", config.clone());
 
-    let mut tasks = vec![ServerTask::new(server_config).boxed()];
+    let mut tasks = vec![
+        ServerTask::new(server_config)
+            .with_monitors(monitor_configs.clone())
+            .boxed(),
+    ];
 
     for monitor_config in monitor_configs {
         tasks.push(MonitorTask::new(monitor_config).boxed());
@@ -0,0 +1,300 @@
+use crate::{
+    calls::CallMonitorTable,
+    events::{
+        EventMonitorTable,
+        factory::FactoryMonitorTable,
+        handler::EventHandlers,
+        script::EventScript,
+        token::{TokenMonitorTable, TokenStandard},
+        wasm::WasmHandler,
+        wildcard::WildcardMonitorTable,
+    },
+    provider::RpcProvider,
+    storage::StorageMonitorTable,
+    tx::{
+        TransactionKind, TransactionMonitorTable, mempool::MempoolMonitorTable,
+        trace::TraceMonitorTable,
+    },
+};
+use alloy::primitives::{Address, B256};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use primitives::{
+    MonitorConfig, MonitorKind,
+    db::{create_schema_db_tables, create_script_stats_db_table},
+    schema::EntitySchema,
+    traits::{ChainAdapter, EventMonitor},
+};
+use std::{collections::HashMap, sync::Arc};
+use tracing::info;
+
+/// Ethereum and the chains sharing its JSON-RPC API, reached over a websocket
+#[derive(Debug, Default)]
+pub struct EvmAdapter {
+    handlers: HashMap<String, Arc<EventHandlers>>,
+}
+
+impl EvmAdapter {
+    /// Runs `handlers` on the events of the monitor `event_name`, which must index events
+    pub fn with_handlers(mut self, event_name: &str, handlers: EventHandlers) -> Self {
+        self.handlers
+            .insert(event_name.to_string(), Arc::new(handlers));
+        self
+    }
+}
+
+#[async_trait]
+impl ChainAdapter for EvmAdapter {
+    fn state_machine(&self) -> &'static str {
+        "EVM"
+    }
+
+    async fn run(
+        &self,
+        config: &MonitorConfig,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let provider = RpcProvider::connect_ws(&config.rpc_url, config.rpc.clone()).await?;
+
+        let chain_id = provider.get_chain_id().await?;
+        if let Some(expected) = config.chain_id
+            && expected != chain_id
+        {
+            bail!(
+                "{}: rpc_url serves chain {chain_id} but chain_id {expected} is configured",
+                config.event_name
+            );
+        }
+        info!("{}: indexing chain {chain_id}", config.event_name);
+
+        let schema = config
+            .schema
+            .as_deref()
+            .map(EntitySchema::load)
+            .transpose()?
+            .map(Arc::new);
+        let handlers = match (self.handlers.get(&config.event_name), &config.wasm) {
+            (Some(_), Some(_)) => bail!(
+                "{}: CONFIG wasm cannot be combined with registered event handlers",
+                config.event_name
+            ),
+            (Some(handlers), None) => Some(handlers.clone()),
+            (None, Some(wasm)) => Some(Arc::new(
+                EventHandlers::new(&wasm.event)?
+                    .with(WasmHandler::load(&config.event_name, wasm)?.with_schema(schema.clone())),
+            )),
+            (None, None) => None,
+        };
+        run_indexer(
+            config,
+            provider.with_chain_id(chain_id),
+            handlers,
+            schema,
+            db_client,
+        )
+        .await
+    }
+}
+
+/// Runs the indexer matching the kind of the monitor until it fails or its subscription ends
+pub async fn run_indexer(
+    config: &MonitorConfig,
+    provider: RpcProvider,
+    handlers: Option<Arc<EventHandlers>>,
+    schema: Option<Arc<EntitySchema>>,
+    client: &mut tokio_postgres::Client,
+) -> anyhow::Result<()> {
+    let name = &config.event_name;
+    let address = || -> anyhow::Result<Address> {
+        let address = config
+            .address
+            .as_ref()
+            .ok_or_else(|| anyhow!("{name}: CONFIG address is missing"))?;
+        address
+            .parse()
+            .map_err(|err| anyhow!("{name}: CONFIG address `{address}` could not be parsed: {err}"))
+    };
+    let event_signature = || -> anyhow::Result<B256> {
+        let signature = config
+            .event_signature
+            .as_ref()
+            .ok_or_else(|| anyhow!("{name}: CONFIG event signature is missing"))?;
+        signature.parse().map_err(|err| {
+            anyhow!("{name}: CONFIG event signature `{signature}` could not be parsed: {err}")
+        })
+    };
+
+    let topics = config.topic_filters()?;
+    let outbox = !config.sinks.is_empty();
+
+    // The entity tables exist before the handlers writing them are set up
+    if let Some(schema) = &schema {
+        create_schema_db_tables(client, &config.event_name, schema).await?;
+    }
+
+    if let Some(handlers) = &handlers {
+        if !matches!(config.kind, MonitorKind::Event | MonitorKind::Factory) {
+            bail!(
+                "{}: event handlers only run on event and factory monitors",
+                config.event_name
+            );
+        }
+        handlers.setup(client).await?;
+    }
+
+    let script = match &config.script {
+        Some(script) => {
+            if !matches!(config.kind, MonitorKind::Event | MonitorKind::Factory) {
+                bail!(
+                    "{}: scripts only run on event and factory monitors",
+                    config.event_name
+                );
+            }
+            create_script_stats_db_table(client, &config.event_name).await?;
+            Some(Arc::new(EventScript::new(script)?))
+        }
+        None => None,
+    };
+
+    match config.kind {
+        // Without an address the signature is indexed from every emitter
+        MonitorKind::Event if config.address.is_none() => {
+            if config.join_transactions {
+                bail!(
+                    "{}: join_transactions cannot be used on a monitor without an address",
+                    config.event_name
+                );
+            }
+            WildcardMonitorTable::new(config.event_name.clone(), config.wildcard.clone())
+                .with_topics(topics)
+                .with_outbox(outbox)
+                .with_notify(config.notify)
+                .with_handlers(handlers)
+                .with_script(script)
+                .query_and_subscribe_to_events(
+                    provider,
+                    event_signature()?,
+                    config.block_number,
+                    client,
+                )
+                .await
+        }
+        MonitorKind::Event => {
+            EventMonitorTable::new(config.event_name.clone())
+                .with_topics(topics)
+                .with_transactions(config.join_transactions)
+                .with_outbox(outbox)
+                .with_notify(config.notify)
+                .with_handlers(handlers)
+                .with_script(script)
+                .query_and_subscribe_to_events(
+                    provider,
+                    address()?,
+                    event_signature()?,
+                    config.block_number.into(),
+                    client,
+                )
+                .await
+        }
+        MonitorKind::Factory => {
+            let factory = config
+                .factory
+                .as_ref()
+                .ok_or_else(|| anyhow!("CONFIG factory section is missing"))?;
+            FactoryMonitorTable::new(config.event_name.clone(), factory)?
+                .with_topics(topics)
+                .with_outbox(outbox)
+                .with_notify(config.notify)
+                .with_handlers(handlers)
+                .with_script(script)
+                .query_and_subscribe_to_events(
+                    provider,
+                    address()?,
+                    event_signature()?,
+                    config.block_number.into(),
+                    client,
+                )
+                .await
+        }
+        MonitorKind::Erc20 | MonitorKind::Erc721 | MonitorKind::Erc1155 => {
+            let standard = TokenStandard::from_kind(config.kind).expect("token monitor kind");
+            // The balances need every transfer, a topic filter would only keep one side of them
+            if config.balances.is_some() && topics.iter().any(|topic| !topic.is_empty()) {
+                bail!(
+                    "{}: balances cannot be combined with topic1 to topic3 filters",
+                    config.event_name
+                );
+            }
+            TokenMonitorTable::new(config.event_name.clone(), standard)
+                .with_balances(config.balances.clone())
+                .with_topics(topics)
+                .with_outbox(outbox)
+                .query_and_subscribe_to_events(
+                    provider,
+                    address()?,
+                    standard.event_signatures(),
+                    config.block_number.into(),
+                    client,
+                )
+                .await
+        }
+        MonitorKind::Transfers | MonitorKind::Deployments => {
+            let kind = match config.kind {
+                MonitorKind::Transfers => TransactionKind::ValueTransfer,
+                _ => TransactionKind::Deployment,
+            };
+            let watched = config.watched_addresses()?;
+            if kind == TransactionKind::ValueTransfer && watched.is_empty() {
+                bail!("CONFIG address or addresses are required to index transfers");
+            }
+            TransactionMonitorTable::new(config.event_name.clone(), kind)
+                .with_blocks(config.blocks.clone())
+                .with_rollup(config.rollup)
+                .with_outbox(outbox)
+                .query_and_subscribe_transactions(provider, watched, config.block_number, client)
+                .await
+        }
+        MonitorKind::Traces => {
+            TraceMonitorTable::new(config.event_name.clone(), config.traces.format)
+                .with_rollup(config.rollup)
+                .with_outbox(outbox)
+                .query_and_subscribe_traces(
+                    provider,
+                    config.watched_addresses()?,
+                    config.block_number,
+                    client,
+                )
+                .await
+        }
+        MonitorKind::Mempool => {
+            let watched = config.watched_addresses()?;
+            if watched.is_empty() {
+                bail!("CONFIG address or addresses are required to follow the mempool");
+            }
+            MempoolMonitorTable::new(config.event_name.clone())
+                .with_rollup(config.rollup)
+                .with_outbox(outbox)
+                .subscribe_pending_transactions(provider, watched, client)
+                .await
+        }
+        MonitorKind::Polling => {
+            let polling = config
+                .polling
+                .as_ref()
+                .ok_or_else(|| anyhow!("CONFIG polling section is missing"))?;
+            let default_address = config.address.as_ref().map(|_| address()).transpose()?;
+            CallMonitorTable::new(config.event_name.clone(), polling, default_address)?
+                .query_and_subscribe_calls(provider, config.block_number, client)
+                .await
+        }
+        MonitorKind::Storage => {
+            let storage = config
+                .storage
+                .as_ref()
+                .ok_or_else(|| anyhow!("CONFIG storage section is missing"))?;
+            StorageMonitorTable::new(config.event_name.clone(), storage)?
+                .query_and_subscribe_slots(provider, address()?, config.block_number, client)
+                .await
+        }
+    }
+}
@@ -0,0 +1,71 @@
+pub mod evm;
+
+use anyhow::anyhow;
+use evm::EvmAdapter;
+use primitives::traits::ChainAdapter;
+use std::{collections::HashMap, sync::Arc};
+
+/// The chain adapters, keyed by the `state_machine` they serve.
+/// The default registry holds every adapter shipped with the indexer, more can be added with
+/// [`AdapterRegistry::register`] before the monitors are started.
+#[derive(Clone)]
+pub struct AdapterRegistry {
+    adapters: HashMap<String, Arc<dyn ChainAdapter>>,
+}
+
+impl AdapterRegistry {
+    /// A registry without any adapter
+    pub fn empty() -> Self {
+        Self {
+            adapters: HashMap::new(),
+        }
+    }
+
+    /// Adds an adapter, replacing the one registered for the same `state_machine`
+    pub fn register(mut self, adapter: impl ChainAdapter + 'static) -> Self {
+        self.adapters
+            .insert(adapter.state_machine().to_uppercase(), Arc::new(adapter));
+        self
+    }
+
+    /// The adapter serving `state_machine`
+    pub fn get(&self, state_machine: &str) -> Result<Arc<dyn ChainAdapter>, anyhow::Error> {
+        self.adapters
+            .get(&state_machine.to_uppercase())
+            .cloned()
+            .ok_or_else(|| {
+                let mut known: Vec<&str> = self.adapters.keys().map(String::as_str).collect();
+                known.sort();
+                anyhow!(
+                    "no chain adapter for state_machine `{state_machine}`, known: {}",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+impl Default for AdapterRegistry {
+    fn default() -> Self {
+        Self::empty().register(EvmAdapter::default())
+    }
+}
+
+impl std::fmt::Debug for AdapterRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.adapters.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_registry_matches_state_machine_case_insensitively() {
+        let registry = AdapterRegistry::default();
+
+        assert_eq!(registry.get("EVM").unwrap().state_machine(), "EVM");
+        assert_eq!(registry.get("evm").unwrap().state_machine(), "EVM");
+        assert!(registry.get("SVM").is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use crate::provider::RpcProvider;
+use alloy::{
+    dyn_abi::{DynSolType, DynSolValue, FunctionExt, JsonAbiExt, Specifier},
+    hex, json_abi,
+    primitives::{Address, Bytes},
+};
+use anyhow::{anyhow, bail};
+use futures::StreamExt;
+use primitives::{
+    PollingConfig, ViewCallConfig,
+    db::{
+        create_call_result_db_table, create_progress_db_table, get_progress, set_progress,
+        store_call_result_to_db,
+    },
+    monitor::{CallColumnType, CallResult},
+};
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// The column of an output, its name made a valid identifier or `output{index}` for unnamed outputs
+fn column_name(name: &str, index: usize) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.as_str() {
+        "" | "id" | "block_number" | "block_timestamp" => format!("output{index}"),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("output{index}"),
+        _ => name,
+    }
+}
+
+fn column_type(ty: &DynSolType) -> CallColumnType {
+    match ty {
+        DynSolType::Int(_) | DynSolType::Uint(_) => CallColumnType::Numeric,
+        DynSolType::Bool => CallColumnType::Boolean,
+        _ => CallColumnType::Text,
+    }
+}
+
+/// The textual form of a decoded value, arrays as `[a, b]` and tuples as `(a, b)`
+pub fn format_value(value: &DynSolValue) -> String {
+    let join = |values: &[DynSolValue]| {
+        values
+            .iter()
+            .map(format_value)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match value {
+        DynSolValue::Bool(value) => value.to_string(),
+        DynSolValue::Int(value, _) => value.to_string(),
+        DynSolValue::Uint(value, _) => value.to_string(),
+        DynSolValue::Address(value) => value.to_string(),
+        DynSolValue::Function(value) => value.to_string(),
+        DynSolValue::FixedBytes(value, size) => hex::encode_prefixed(&value[..*size]),
+        DynSolValue::Bytes(value) => hex::encode_prefixed(value),
+        DynSolValue::String(value) => value.clone(),
+        DynSolValue::Array(values) | DynSolValue::FixedArray(values) => {
+            format!("[{}]", join(values))
+        }
+        other => format!("({})", join(other.as_fixed_seq().unwrap_or_default())),
+    }
+}
+
+/// A view call with its encoded arguments and the columns of its results table
+pub struct ViewCall {
+    table: String,
+    address: Address,
+    function: json_abi::Function,
+    calldata: Bytes,
+    columns: Vec<(String, CallColumnType)>,
+}
+
+impl ViewCall {
+    /// `monitor` prefixes the results table, `default_address` is used when the call has none
+    pub fn new(
+        monitor: &str,
+        config: &ViewCallConfig,
+        default_address: Option<Address>,
+    ) -> Result<Self, anyhow::Error> {
+        let function = json_abi::Function::parse(&config.function)?;
+        if function.outputs.is_empty() {
+            bail!("`{}` has no outputs to index", config.function);
+        }
+        if function.inputs.len() != config.args.len() {
+            bail!(
+                "`{}` takes {} arguments, {} given",
+                config.function,
+                function.inputs.len(),
+                config.args.len()
+            );
+        }
+
+        let args = function
+            .inputs
+            .iter()
+            .zip(config.args.iter())
+            .map(|(input, arg)| Ok(input.resolve()?.coerce_str(arg)?))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let calldata = function.abi_encode_input(&args)?.into();
+
+        let columns = function
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(index, output)| {
+                Ok((
+                    column_name(&output.name, index),
+                    column_type(&output.resolve()?),
+                ))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let mut seen = HashSet::new();
+        if let Some((column, _)) = columns.iter().find(|(column, _)| !seen.insert(column)) {
+            bail!(
+                "`{}` has more than one output stored in the column `{column}`",
+                config.function
+            );
+        }
+
+        let address = match &config.address {
+            Some(address) => address.parse()?,
+            None => default_address
+                .ok_or_else(|| anyhow!("call `{}` has no address to call", config.name))?,
+        };
+
+        Ok(Self {
+            table: format!("{monitor}_{}", config.name),
+            address,
+            function,
+            calldata,
+            columns,
+        })
+    }
+
+    /// Decodes the returned data into a row of the results table
+    pub fn decode(
+        &self,
+        block_number: u64,
+        block_timestamp: u64,
+        output: &Bytes,
+    ) -> Result<CallResult, anyhow::Error> {
+        let values = self
+            .function
+            .abi_decode_output(output)?
+            .iter()
+            .map(format_value)
+            .collect();
+
+        Ok(CallResult {
+            block_number,
+            block_timestamp,
+            values,
+        })
+    }
+}
+
+/// Indexes the results of view calls every `interval` blocks, one time series table per call
+pub struct CallMonitorTable {
+    name: String,
+    interval: u64,
+    calls: Vec<ViewCall>,
+}
+
+impl CallMonitorTable {
+    pub fn new(
+        name: String,
+        config: &PollingConfig,
+        default_address: Option<Address>,
+    ) -> Result<Self, anyhow::Error> {
+        let calls = config
+            .calls
+            .iter()
+            .map(|call| ViewCall::new(&name, call, default_address))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        // The progress of the monitor is kept next to the results tables
+        let mut tables = HashSet::from([format!("{name}_progress")]);
+        if let Some(call) = calls.iter().find(|call| !tables.insert(call.table.clone())) {
+            bail!("{name}: more than one table is named `{}`", call.table);
+        }
+
+        Ok(Self {
+            name,
+            interval: config.interval.max(1),
+            calls,
+        })
+    }
+
+    /// Executes every call against the state of a block and stores the results in one db
+    /// transaction, which also records the block as the progress of the monitor.
+    /// A failing call (e.g. a contract not deployed yet at that block) is skipped for that block only.
+    async fn poll(
+        &self,
+        provider: &RpcProvider,
+        block_number: u64,
+        block_timestamp: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let mut results = Vec::with_capacity(self.calls.len());
+        for call in self.calls.iter() {
+            let result = match provider
+                .call(call.address, call.calldata.clone(), block_number)
+                .await
+            {
+                Ok(output) => call.decode(block_number, block_timestamp, &output),
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Ok(result) => results.push((call, result)),
+                Err(err) => warn!("{}: call failed at block {block_number}: {err}", call.table),
+            }
+        }
+
+        let db_tx = db_client.transaction().await?;
+        for (call, result) in results.iter() {
+            store_call_result_to_db(&db_tx, &call.table, &call.columns, result).await?;
+        }
+        set_progress(&db_tx, &self.name, block_number).await?;
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Backfills the polled blocks from `from_block`, or from the block after the stored progress,
+    /// up to the chain head, which needs an archive node for old blocks, then polls the new blocks
+    /// as they come
+    pub async fn query_and_subscribe_calls(
+        &self,
+        provider: RpcProvider,
+        from_block: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        for call in self.calls.iter() {
+            create_call_result_db_table(db_client, &call.table, &call.columns).await?;
+        }
+        create_progress_db_table(db_client, &self.name).await?;
+        let progress = get_progress(db_client, &self.name).await?;
+        let from_block = match progress {
+            Some(last) => last + 1,
+            None => from_block,
+        };
+
+        // Subscribe before reading the head so that no block falls between backfill and live blocks
+        let mut stream = provider.subscribe_blocks().await?;
+        let head = provider.get_block_number().await?;
+        let first = from_block.div_ceil(self.interval) * self.interval;
+        for block_number in (first..=head).step_by(self.interval as usize) {
+            let Some(block) = provider.get_block(block_number.into()).await? else {
+                continue;
+            };
+            self.poll(&provider, block_number, block.header.timestamp, db_client)
+                .await?;
+        }
+        info!("{}: polled up to block {head}", self.name);
+        let polled = progress.map_or(head, |last| last.max(head));
+
+        while let Some(header) = stream.next().await {
+            let header = header?;
+            if header.number > polled && header.number % self.interval == 0 {
+                self.poll(&provider, header.number, header.timestamp, db_client)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{U256, address};
+
+    #[test]
+    pub fn test_view_call_encodes_and_decodes() {
+        let config = ViewCallConfig {
+            name: "reserves".to_string(),
+            function: "getReserves() returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)"
+                .to_string(),
+            args: vec![],
+            address: None,
+        };
+        let pair = address!("b4e16d0168e52d35cacd2c6185b44281ec28c9dc");
+        let call = ViewCall::new("usdc_weth", &config, Some(pair)).unwrap();
+
+        assert_eq!(call.table, "usdc_weth_reserves");
+        assert_eq!(call.calldata, Bytes::from_static(&[0x09, 0x02, 0xf1, 0xac]));
+        assert_eq!(
+            call.columns,
+            vec![
+                ("reserve0".to_string(), CallColumnType::Numeric),
+                ("reserve1".to_string(), CallColumnType::Numeric),
+                ("blocktimestamplast".to_string(), CallColumnType::Numeric),
+            ]
+        );
+
+        let output = DynSolValue::Tuple(vec![
+            DynSolValue::Uint(U256::from(1_000_000u64), 112),
+            DynSolValue::Uint(U256::from(500u64), 112),
+            DynSolValue::Uint(U256::from(1_700_000_000u64), 32),
+        ])
+        .abi_encode_params();
+        let result = call.decode(10, 20, &output.into()).unwrap();
+        assert_eq!(result.values, vec!["1000000", "500", "1700000000"]);
+    }
+
+    #[test]
+    pub fn test_view_call_coerces_args() {
+        let config = ViewCallConfig {
+            name: "balance".to_string(),
+            function: "balanceOf(address) returns (uint256)".to_string(),
+            args: vec!["0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045".to_string()],
+            address: Some("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string()),
+        };
+        let call = ViewCall::new("usdc", &config, None).unwrap();
+
+        assert_eq!(call.calldata.len(), 4 + 32);
+        assert_eq!(
+            call.columns,
+            vec![("output0".to_string(), CallColumnType::Numeric)]
+        );
+    }
+
+    #[test]
+    pub fn test_columns_and_tables_must_be_unique() {
+        let call = |name: &str, function: &str| ViewCallConfig {
+            name: name.to_string(),
+            function: function.to_string(),
+            args: vec![],
+            address: Some("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string()),
+        };
+
+        // `Value` and `value` are both stored in the column `value`
+        let collide = call("values", "values() returns (uint256 Value, uint256 value)");
+        assert!(ViewCall::new("pool", &collide, None).is_err());
+
+        let polling = |calls| PollingConfig { interval: 1, calls };
+        let supply = call("supply", "totalSupply() returns (uint256)");
+        assert!(
+            CallMonitorTable::new("pool".to_string(), &polling(vec![supply.clone()]), None).is_ok()
+        );
+        assert!(
+            CallMonitorTable::new(
+                "pool".to_string(),
+                &polling(vec![supply.clone(), supply]),
+                None
+            )
+            .is_err()
+        );
+        let progress = call("progress", "totalSupply() returns (uint256)");
+        assert!(CallMonitorTable::new("pool".to_string(), &polling(vec![progress]), None).is_err());
+    }
+}
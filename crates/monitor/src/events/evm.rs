@@ -1,52 +1,102 @@
+use super::{CommitHooks, join::TransactionJoin, store_event_batch};
+use crate::provider::RpcProvider;
 use alloy::{
-    dyn_abi::{DecodedEvent, DynSolEvent, DynSolType},
-    network::Ethereum,
+    dyn_abi::{DecodedEvent, DynSolEvent, DynSolType, DynSolValue, EventExt},
+    json_abi,
     primitives::{Address, B256, Bytes, LogData},
-    providers::{Provider, RootProvider},
-    rpc::types::eth::{BlockNumberOrTag, Filter},
+    rpc::types::eth::{BlockNumberOrTag, Filter, Log, Topic},
+    transports::TransportResult,
 };
-use futures::StreamExt;
-use primitives::{db::store_event_to_db, monitor::Event};
+use anyhow::anyhow;
+use futures::{StreamExt, stream::BoxStream};
+use primitives::monitor::Event;
 
+/// Values accepted for topic1 to topic3, an empty list matches any value
+pub type TopicFilters = [Vec<B256>; 3];
+
+/// Restricts a filter to the configured topic1 to topic3 values
+pub fn with_topic_filters(filter: Filter, topics: &TopicFilters) -> Filter {
+    filter
+        .topic1(topics[0].clone())
+        .topic2(topics[1].clone())
+        .topic3(topics[2].clone())
+}
+
+/// The logs of `event_sig` emitted by `addr` from `block_number` up to `to_block`
 pub async fn query_events(
-    provider: RootProvider<Ethereum>,
+    provider: &RpcProvider,
     addr: Address,
-    event_sig: B256,
+    event_sig: impl Into<Topic>,
+    topics: &TopicFilters,
     block_number: BlockNumberOrTag,
+    to_block: u64,
 ) -> Result<Vec<Event>, anyhow::Error> {
     let filter = Filter::new()
         .address(addr)
         .event_signature(event_sig)
-        .from_block(block_number);
+        .from_block(block_number)
+        .to_block(to_block);
+    let filter = with_topic_filters(filter, topics);
     let log = provider.get_logs(&filter).await?;
-    let logs: Vec<Event> = log.into_iter().map(|log| log.into()).collect();
+    let logs: Vec<Event> = log
+        .into_iter()
+        .map(|log| Event::from(log).with_chain_id(provider.chain_id()))
+        .collect();
 
     Ok(logs)
 }
 
-pub async fn subscribe_to_events(
-    provider: RootProvider<Ethereum>,
-    addr: Vec<Address>,
-    event_sig: B256,
-    client: &mut tokio_postgres::Client,
-    name: &str,
-) {
+/// The filter of the live logs of `event_sig` emitted by `addr`
+pub fn live_filter(addr: Vec<Address>, event_sig: B256, topics: &TopicFilters) -> Filter {
     let filter = Filter::new()
         .address(addr)
         .event_signature(event_sig)
         .from_block(BlockNumberOrTag::Latest);
+    with_topic_filters(filter, topics)
+}
 
-    let sub = provider
-        .subscribe_logs(&filter)
-        .await
-        .expect("Failed to subscribe to logs");
-    let mut stream = sub.into_stream();
+pub async fn subscribe_to_events(
+    provider: &RpcProvider,
+    filter: Filter,
+    join: Option<TransactionJoin>,
+    hooks: &CommitHooks,
+    client: &mut tokio_postgres::Client,
+    name: &str,
+) -> Result<(), anyhow::Error> {
+    let stream = provider.subscribe_logs(&filter).await?;
 
+    follow_events(provider, stream, None, join, hooks, client, name).await
+}
+
+/// Stores the logs of `stream` as they arrive. The logs up to `head`, already backfilled, are
+/// skipped.
+pub async fn follow_events(
+    provider: &RpcProvider,
+    mut stream: BoxStream<'static, TransportResult<Log>>,
+    head: Option<u64>,
+    mut join: Option<TransactionJoin>,
+    hooks: &CommitHooks,
+    client: &mut tokio_postgres::Client,
+    name: &str,
+) -> Result<(), anyhow::Error> {
     while let Some(log) = stream.next().await {
-        store_event_to_db(&log.into(), client, name)
-            .await
-            .expect("Failed to store event to db");
+        let log = log?;
+        if is_backfilled(&log, head) {
+            continue;
+        }
+        let mut event = Event::from(log).with_chain_id(provider.chain_id());
+        if let Some(join) = join.as_mut() {
+            event = join.join(provider, event).await?;
+        }
+        store_event_batch(client, name, &[event], hooks).await?;
     }
+
+    Ok(())
+}
+
+/// Whether a live log was already indexed by the backfill up to `head`
+pub fn is_backfilled(log: &Log, head: Option<u64>) -> bool {
+    matches!((log.block_number, head), (Some(number), Some(head)) if number <= head)
 }
 
 pub fn decode_event(
@@ -61,3 +111,76 @@ pub fn decode_event(
 
     Ok(decoded_event)
 }
+
+/// Decodes a log against a human readable event ABI and pairs every parameter name with its value,
+/// in the order the parameters are declared.
+pub fn decode_event_params(
+    event: &json_abi::Event,
+    topics: &[B256],
+    data: &Bytes,
+) -> Result<Vec<(String, DynSolValue)>, anyhow::Error> {
+    let decoded = event.decode_log_parts(topics.iter().copied(), data)?;
+    let mut indexed = decoded.indexed.into_iter();
+    let mut body = decoded.body.into_iter();
+
+    event
+        .inputs
+        .iter()
+        .map(|input| {
+            let value = if input.indexed {
+                indexed.next()
+            } else {
+                body.next()
+            };
+            value
+                .map(|value| (input.name.clone(), value))
+                .ok_or_else(|| anyhow!("missing value for parameter `{}`", input.name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{I256, U256, address};
+
+    #[test]
+    pub fn test_decode_event_params_keeps_declaration_order() {
+        let event = json_abi::Event::parse(
+            "PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool)",
+        )
+        .unwrap();
+        let token0 = address!("1f9840a85d5af5bf1d1762f925bdaddc4201f984");
+        let token1 = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let pool = address!("1d42064fc4beb5f8aaf85f4617ae8b3b5b8bd801");
+
+        let topics = vec![
+            event.selector(),
+            token0.into_word(),
+            token1.into_word(),
+            B256::from(U256::from(3000)),
+        ];
+        let data = DynSolValue::Tuple(vec![
+            DynSolValue::Int(I256::try_from(60).unwrap(), 24),
+            DynSolValue::Address(pool),
+        ])
+        .abi_encode_params();
+
+        let params = decode_event_params(&event, &topics, &data.into()).unwrap();
+        let names: Vec<&str> = params.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["token0", "token1", "fee", "tickSpacing", "pool"]);
+        assert_eq!(params[4].1, DynSolValue::Address(pool));
+    }
+
+    #[test]
+    pub fn test_live_logs_up_to_the_head_are_backfilled() {
+        let log = |block_number| Log {
+            block_number,
+            ..Default::default()
+        };
+        assert!(is_backfilled(&log(Some(100)), Some(100)));
+        assert!(!is_backfilled(&log(Some(101)), Some(100)));
+        assert!(!is_backfilled(&log(Some(100)), None));
+        assert!(!is_backfilled(&log(None), Some(100)));
+    }
+}
@@ -0,0 +1,454 @@
+use super::{
+    BACKFILL_BATCH_SIZE, CommitHooks,
+    evm::{TopicFilters, decode_event_params, with_topic_filters},
+    handler::EventHandlers,
+    script::EventScript,
+    store_event_batch,
+};
+use crate::provider::RpcProvider;
+use alloy::{
+    dyn_abi::DynSolValue,
+    json_abi,
+    primitives::{Address, B256},
+    rpc::types::eth::{BlockNumberOrTag, Filter, Log},
+    transports::TransportResult,
+};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use futures::{FutureExt, StreamExt, stream::BoxStream};
+use primitives::{
+    FactoryConfig,
+    db::{
+        create_factory_children_db_table, create_new_event_db_table, get_factory_children,
+        remove_factory_child, store_factory_child, update_factory_child_progress,
+    },
+    monitor::{Event, FactoryChild},
+    traits::EventMonitor,
+};
+use std::sync::Arc;
+use tokio::select;
+use tracing::info;
+
+/// The live logs of a subscription
+type LogStream = BoxStream<'static, TransportResult<Log>>;
+
+/// How a factory log changes the watch set
+enum WatchChange {
+    /// A child was discovered
+    Added(FactoryChild),
+    /// The log creating a child was removed by a reorg
+    Removed(Address),
+}
+
+/// Indexes the events of every contract deployed by a factory.
+/// Children are discovered from the factory event, persisted in `{name}_children`
+/// and backfilled from their creation block up to the head read once they are added to the
+/// live subscription.
+pub struct FactoryMonitorTable {
+    name: String,
+    factory_event: json_abi::Event,
+    child_param: String,
+    topics: TopicFilters,
+    hooks: CommitHooks,
+}
+
+impl FactoryMonitorTable {
+    pub fn new(name: String, config: &FactoryConfig) -> Result<Self, anyhow::Error> {
+        let factory_event = json_abi::Event::parse(&config.event)?;
+        if !factory_event
+            .inputs
+            .iter()
+            .any(|input| input.name == config.child_param)
+        {
+            bail!(
+                "factory event `{}` has no parameter named `{}`",
+                config.event,
+                config.child_param
+            );
+        }
+
+        Ok(Self {
+            name,
+            factory_event,
+            child_param: config.child_param.clone(),
+            topics: TopicFilters::default(),
+            hooks: CommitHooks::default(),
+        })
+    }
+
+    /// Only index the child logs whose topic1 to topic3 match the filters
+    pub fn with_topics(mut self, topics: TopicFilters) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Queues every committed batch of child events in the outbox of the monitor, for its sinks
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.hooks.outbox = outbox;
+        self
+    }
+
+    /// Announces every committed batch of child events on the change channel of the monitor
+    pub fn with_notify(mut self, notify: bool) -> Self {
+        self.hooks.notify = notify;
+        self
+    }
+
+    /// Runs `handlers` on every stored child event, in the db transaction storing it
+    pub fn with_handlers(mut self, handlers: Option<Arc<EventHandlers>>) -> Self {
+        self.hooks.handlers = handlers;
+        self
+    }
+
+    /// Runs `script` on every child event before it is stored
+    pub fn with_script(mut self, script: Option<Arc<EventScript>>) -> Self {
+        self.hooks.script = script;
+        self
+    }
+
+    /// Extracts the child contract from a factory log
+    fn decode_child(&self, log: &Log) -> Result<FactoryChild, anyhow::Error> {
+        let params = decode_event_params(&self.factory_event, log.topics(), &log.inner.data.data)?;
+        let address = params
+            .into_iter()
+            .find_map(|(name, value)| match value {
+                DynSolValue::Address(address) if name == self.child_param => Some(address),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("`{}` is not an address parameter", self.child_param))?;
+        let creation_block = log.block_number.unwrap_or(0);
+
+        Ok(FactoryChild {
+            address,
+            creation_block,
+            transaction_hash: log.transaction_hash.unwrap_or_default(),
+            last_block: creation_block,
+        })
+    }
+
+    /// Stores a factory log, returning the child when it was not known before. A log removed by
+    /// a reorg unregisters the child it created, returned when it was known.
+    async fn handle_factory_log(
+        &self,
+        log: &Log,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<Option<WatchChange>, anyhow::Error> {
+        let child = self.decode_child(log)?;
+        if log.removed {
+            if !remove_factory_child(db_client, &self.name, &child).await? {
+                return Ok(None);
+            }
+            info!(
+                "{}: child {} was removed by a reorg at block {}",
+                self.name, child.address, child.creation_block
+            );
+            return Ok(Some(WatchChange::Removed(child.address)));
+        }
+        if !store_factory_child(db_client, &self.name, &child).await? {
+            return Ok(None);
+        }
+        info!(
+            "{}: discovered child {} at block {}",
+            self.name, child.address, child.creation_block
+        );
+
+        Ok(Some(WatchChange::Added(child)))
+    }
+
+    /// Indexes the events of a child from `from_block` to `to_block`, the head read before the
+    /// backfill, so that the live subscription takes over from there
+    async fn backfill_child(
+        &self,
+        provider: &RpcProvider,
+        child: Address,
+        event_sig: B256,
+        from_block: u64,
+        to_block: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        if from_block > to_block {
+            return Ok(());
+        }
+        let filter = Filter::new()
+            .address(child)
+            .event_signature(event_sig)
+            .from_block(from_block)
+            .to_block(to_block);
+        let filter = with_topic_filters(filter, &self.topics);
+        let events: Vec<Event> = provider
+            .get_logs(&filter)
+            .await?
+            .into_iter()
+            .map(|log| Event::from(log).with_chain_id(provider.chain_id()))
+            .collect();
+        for batch in events.chunks(BACKFILL_BATCH_SIZE) {
+            store_event_batch(db_client, &self.name, batch, &self.hooks).await?;
+        }
+        update_factory_child_progress(db_client, &self.name, &child, to_block).await?;
+
+        Ok(())
+    }
+
+    /// Stores a live log of a child and records the progress of the child
+    async fn store_child_log(
+        &self,
+        provider: &RpcProvider,
+        log: Log,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let event = Event::from(log).with_chain_id(provider.chain_id());
+        store_event_batch(
+            db_client,
+            &self.name,
+            std::slice::from_ref(&event),
+            &self.hooks,
+        )
+        .await?;
+        update_factory_child_progress(db_client, &self.name, &event.address, event.block_number)
+            .await
+    }
+
+    /// The filter of the factory logs of `factories`
+    fn factory_filter(&self, factories: Vec<Address>) -> Filter {
+        Filter::new()
+            .address(factories)
+            .event_signature(self.factory_event.selector())
+    }
+
+    /// Opens a subscription covering `children`, in place of `previous`. The logs `previous` has
+    /// already received are stored before it is dropped, the new subscription is open by then.
+    async fn resubscribe_children(
+        &self,
+        provider: &RpcProvider,
+        children: &[Address],
+        event_sig: B256,
+        previous: Option<LogStream>,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<Option<LogStream>, anyhow::Error> {
+        let next = self
+            .subscribe_children(provider, children, event_sig)
+            .await?;
+        if let Some(mut previous) = previous {
+            while let Some(Some(log)) = previous.next().now_or_never() {
+                self.store_child_log(provider, log?, db_client).await?;
+            }
+        }
+
+        Ok(next)
+    }
+
+    /// Indexes the live logs of the children, and the children the factories create from now on
+    async fn follow(
+        &self,
+        provider: RpcProvider,
+        mut factory_stream: LogStream,
+        mut children: Vec<Address>,
+        mut child_stream: Option<LogStream>,
+        event_sig: B256,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        loop {
+            let mut change = None;
+            select! {
+                log = factory_stream.next() => {
+                    let Some(log) = log else { break };
+                    change = self.handle_factory_log(&log?, db_client).await?;
+                }
+                log = async { child_stream.as_mut()?.next().await }, if child_stream.is_some() => {
+                    let Some(log) = log else { break };
+                    self.store_child_log(&provider, log?, db_client).await?;
+                }
+            }
+
+            match change {
+                Some(WatchChange::Added(child)) => {
+                    // Subscribe before reading the head, the backfill then ends where the live logs
+                    // of the child start
+                    children.push(child.address);
+                    child_stream = self
+                        .resubscribe_children(
+                            &provider,
+                            &children,
+                            event_sig,
+                            child_stream,
+                            db_client,
+                        )
+                        .await?;
+                    let head = provider.get_block_number().await?;
+                    self.backfill_child(
+                        &provider,
+                        child.address,
+                        event_sig,
+                        child.creation_block,
+                        head,
+                        db_client,
+                    )
+                    .await?;
+                }
+                Some(WatchChange::Removed(address)) => {
+                    children.retain(|child| *child != address);
+                    child_stream = self
+                        .resubscribe_children(
+                            &provider,
+                            &children,
+                            event_sig,
+                            child_stream,
+                            db_client,
+                        )
+                        .await?;
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn subscribe_children(
+        &self,
+        provider: &RpcProvider,
+        children: &[Address],
+        event_sig: B256,
+    ) -> Result<Option<LogStream>, anyhow::Error> {
+        // An empty address list would match every emitter on the chain
+        if children.is_empty() {
+            return Ok(None);
+        }
+        let filter = Filter::new()
+            .address(children.to_vec())
+            .event_signature(event_sig)
+            .from_block(BlockNumberOrTag::Latest);
+        let filter = with_topic_filters(filter, &self.topics);
+
+        Ok(Some(provider.subscribe_logs(&filter).await?))
+    }
+}
+
+#[async_trait]
+impl EventMonitor for FactoryMonitorTable {
+    type SubProvider = RpcProvider;
+    type ContractAddress = Address;
+    type EventSignature = B256;
+    type BlockNumber = BlockNumberOrTag;
+
+    /// `addr` is the factory and `event_sig` the event indexed on the children
+    async fn query_and_subscribe_to_events(
+        &self,
+        provider: Self::SubProvider,
+        addr: Self::ContractAddress,
+        event_sig: Self::EventSignature,
+        block_number: Self::BlockNumber,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_new_event_db_table(db_client, &self.name).await?;
+        create_factory_children_db_table(db_client, &self.name).await?;
+
+        // Subscribe before reading the head, the backfill then ends where the live logs start.
+        // Logs received by both are stored once, they are keyed by transaction hash and log index.
+        let factory_stream = provider
+            .subscribe_logs(
+                &self
+                    .factory_filter(vec![addr])
+                    .from_block(BlockNumberOrTag::Latest),
+            )
+            .await?;
+        let known = get_factory_children(db_client, &self.name).await?;
+        let mut children: Vec<Address> = known.iter().map(|child| child.address).collect();
+        let child_stream = self
+            .subscribe_children(&provider, &children, event_sig)
+            .await?;
+        let head = provider.get_block_number().await?;
+
+        // Catch up the children that were already known from a previous run. The block of
+        // their last progress is indexed again, as its other logs may not have been stored.
+        for child in known.iter() {
+            self.backfill_child(
+                &provider,
+                child.address,
+                event_sig,
+                child.last_block,
+                head,
+                db_client,
+            )
+            .await?;
+        }
+
+        // Discover the children created since the configured block
+        let factory_logs = provider
+            .get_logs(
+                &self
+                    .factory_filter(vec![addr])
+                    .from_block(block_number)
+                    .to_block(head),
+            )
+            .await?;
+        for log in factory_logs.iter() {
+            if let Some(WatchChange::Added(child)) = self.handle_factory_log(log, db_client).await?
+            {
+                self.backfill_child(
+                    &provider,
+                    child.address,
+                    event_sig,
+                    child.creation_block,
+                    head,
+                    db_client,
+                )
+                .await?;
+                children.push(child.address);
+            }
+        }
+
+        // The logs of the children discovered above arrive on a new subscription
+        let child_stream = if children.len() > known.len() {
+            self.resubscribe_children(&provider, &children, event_sig, child_stream, db_client)
+                .await?
+        } else {
+            child_stream
+        };
+
+        self.follow(
+            provider,
+            factory_stream,
+            children,
+            child_stream,
+            event_sig,
+            db_client,
+        )
+        .await
+    }
+
+    /// `addr` holds the factories, the children are read from the persisted watch set
+    async fn subscribe_to_events(
+        &self,
+        provider: Self::SubProvider,
+        addr: Vec<Self::ContractAddress>,
+        event_sig: Self::EventSignature,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let factory_stream = provider
+            .subscribe_logs(
+                &self
+                    .factory_filter(addr)
+                    .from_block(BlockNumberOrTag::Latest),
+            )
+            .await?;
+        let children: Vec<Address> = get_factory_children(db_client, &self.name)
+            .await?
+            .into_iter()
+            .map(|child| child.address)
+            .collect();
+        let child_stream = self
+            .subscribe_children(&provider, &children, event_sig)
+            .await?;
+
+        self.follow(
+            provider,
+            factory_stream,
+            children,
+            child_stream,
+            event_sig,
+            db_client,
+        )
+        .await
+    }
+}
@@ -0,0 +1,150 @@
+use super::evm::decode_event_params;
+use alloy::json_abi;
+use anyhow::anyhow;
+use primitives::{
+    monitor::{Event, EventContext},
+    traits::EventHandler,
+};
+use std::sync::Arc;
+
+/// The handlers of a monitor and the event ABI their events are decoded with
+#[derive(Clone)]
+pub struct EventHandlers {
+    abi: json_abi::Event,
+    handlers: Vec<Arc<dyn EventHandler>>,
+}
+
+impl EventHandlers {
+    /// Handlers for the events of the human readable `abi`,
+    /// e.g. `Transfer(address indexed from, address indexed to, uint256 value)`
+    pub fn new(abi: &str) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            abi: json_abi::Event::parse(abi)?,
+            handlers: Vec::new(),
+        })
+    }
+
+    /// Adds a handler, called after the ones added before it
+    pub fn with(mut self, handler: impl EventHandler + 'static) -> Self {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    pub fn abi(&self) -> &json_abi::Event {
+        &self.abi
+    }
+
+    /// Lets every handler create its tables
+    pub async fn setup(&self, db_client: &tokio_postgres::Client) -> Result<(), anyhow::Error> {
+        for handler in self.handlers.iter() {
+            handler.setup(db_client).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes `events`, stored in the rows `ids`, for the handlers
+    pub fn contexts(
+        &self,
+        name: &str,
+        events: &[Event],
+        ids: &[i64],
+    ) -> Result<Vec<EventContext>, anyhow::Error> {
+        events
+            .iter()
+            .zip(ids.iter())
+            .map(|(event, row_id)| {
+                let params =
+                    decode_event_params(&self.abi, &event.topics, &event.data).map_err(|err| {
+                        anyhow!(
+                            "{name}: log {}:{} does not match `{}`: {err}",
+                            event.transaction_hash,
+                            event.log_index,
+                            self.abi.signature()
+                        )
+                    })?;
+
+                Ok(EventContext {
+                    monitor: name.to_string(),
+                    row_id: *row_id,
+                    event: event.clone(),
+                    params,
+                })
+            })
+            .collect()
+    }
+
+    /// Hands `events` to the handlers before `db_tx` commits. A removed event is reverted by the
+    /// handlers in the reverse order they were added, so that each one sees the state it left.
+    pub async fn run(
+        &self,
+        db_tx: &tokio_postgres::Transaction<'_>,
+        name: &str,
+        events: &[Event],
+        ids: &[i64],
+    ) -> Result<(), anyhow::Error> {
+        for ctx in self.contexts(name, events, ids)?.iter() {
+            if ctx.event.removed {
+                for handler in self.handlers.iter().rev() {
+                    handler.revert(ctx, db_tx).await?;
+                }
+            } else {
+                for handler in self.handlers.iter() {
+                    handler.handle(ctx, db_tx).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for EventHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventHandlers")
+            .field("abi", &self.abi.signature())
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::{
+        dyn_abi::DynSolValue,
+        primitives::{B256, U256, address},
+    };
+
+    #[test]
+    pub fn test_contexts_decode_events_with_their_rows() {
+        let handlers =
+            EventHandlers::new("Transfer(address indexed from, address indexed to, uint256 value)")
+                .unwrap();
+        let from = address!("1f9840a85d5af5bf1d1762f925bdaddc4201f984");
+        let to = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let event = Event {
+            topics: vec![handlers.abi().selector(), from.into_word(), to.into_word()],
+            data: U256::from(7).to_be_bytes_vec().into(),
+            ..Default::default()
+        };
+
+        let contexts = handlers
+            .contexts("uni", std::slice::from_ref(&event), &[42])
+            .unwrap();
+        assert_eq!(contexts.len(), 1);
+        assert_eq!(contexts[0].row_id, 42);
+        assert_eq!(contexts[0].param("to"), Some(&DynSolValue::Address(to)));
+        assert_eq!(
+            contexts[0].param("value"),
+            Some(&DynSolValue::Uint(U256::from(7), 256))
+        );
+
+        // A log of another shape fails the batch instead of reaching the handlers
+        let other = Event {
+            topics: vec![B256::ZERO],
+            ..event
+        };
+        assert!(handlers.contexts("uni", &[other], &[43]).is_err());
+    }
+}
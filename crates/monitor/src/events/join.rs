@@ -0,0 +1,112 @@
+use crate::provider::RpcProvider;
+use alloy::{
+    network::{AnyRpcTransaction, AnyTransactionReceipt, TransactionResponse},
+    primitives::{B256, FixedBytes},
+    rpc::types::TransactionTrait,
+};
+use primitives::monitor::{Event, EventTx};
+use std::collections::HashMap;
+
+/// What an event keeps of its transaction, the receipt only gives the gas used
+pub fn event_tx(tx: &AnyRpcTransaction, receipt: Option<&AnyTransactionReceipt>) -> EventTx {
+    EventTx {
+        from: tx.from(),
+        to: TransactionTrait::to(tx),
+        gas_used: receipt.map(|receipt| receipt.gas_used),
+        method_selector: tx
+            .input()
+            .get(..4)
+            .map(|selector| FixedBytes::from_slice(selector)),
+    }
+}
+
+/// Joins events with the transactions that emitted them.
+/// The transactions of the block being indexed are cached, so that the logs of a single
+/// transaction cost one transaction and one receipt request in total.
+#[derive(Debug, Default)]
+pub struct TransactionJoin {
+    block_number: u64,
+    txs: HashMap<B256, EventTx>,
+}
+
+impl TransactionJoin {
+    pub async fn join(
+        &mut self,
+        provider: &RpcProvider,
+        event: Event,
+    ) -> Result<Event, anyhow::Error> {
+        if event.block_number != self.block_number {
+            self.block_number = event.block_number;
+            self.txs.clear();
+        }
+
+        let hash = event.transaction_hash;
+        let transaction = match self.txs.get(&hash) {
+            Some(transaction) => transaction.clone(),
+            None => {
+                let Some(tx) = provider.get_transaction_by_hash(hash).await? else {
+                    return Ok(event);
+                };
+                let receipt = provider.get_transaction_receipt(hash).await?;
+                let transaction = event_tx(&tx, receipt.as_ref());
+                self.txs.insert(hash, transaction.clone());
+                transaction
+            }
+        };
+
+        Ok(Event {
+            transaction: Some(transaction),
+            ..event
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::address;
+
+    #[test]
+    pub fn test_event_tx_keeps_sender_and_selector() {
+        let tx: AnyRpcTransaction = serde_json::from_str(
+            r#"{
+                "type": "0x2",
+                "chainId": "0x1",
+                "nonce": "0x5",
+                "from": "0x00000000000000000000000000000000000000aa",
+                "to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                "value": "0x0",
+                "gas": "0x186a0",
+                "maxFeePerGas": "0x3b9aca00",
+                "maxPriorityFeePerGas": "0x1",
+                "accessList": [],
+                "input": "0xa9059cbb00000000000000000000000000000000000000000000000000000000000000bb",
+                "r": "0x1",
+                "s": "0x1",
+                "yParity": "0x0",
+                "v": "0x0",
+                "hash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "blockNumber": "0x7",
+                "transactionIndex": "0x0",
+                "gasPrice": "0x3b9aca00"
+            }"#,
+        )
+        .unwrap();
+
+        let joined = event_tx(&tx, None);
+        assert_eq!(
+            joined.from,
+            address!("00000000000000000000000000000000000000aa")
+        );
+        assert_eq!(
+            joined.to,
+            Some(address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"))
+        );
+        assert_eq!(joined.gas_used, None);
+        assert_eq!(
+            joined.method_selector,
+            Some(FixedBytes::from([0xa9, 0x05, 0x9c, 0xbb]))
+        );
+    }
+}
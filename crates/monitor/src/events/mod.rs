@@ -1,30 +1,191 @@
 pub mod evm;
+pub mod factory;
+pub mod handler;
+pub mod join;
+pub mod script;
+pub mod token;
+pub mod wasm;
+pub mod wildcard;
 
+use crate::provider::RpcProvider;
 use alloy::{
     primitives::{Address, B256},
-    providers::RootProvider,
     rpc::types::eth::BlockNumberOrTag,
 };
 use async_trait::async_trait;
-use evm::{query_events, subscribe_to_events};
+use evm::{TopicFilters, follow_events, live_filter, query_events, subscribe_to_events};
+use handler::EventHandlers;
+use join::TransactionJoin;
 use primitives::{
-    db::{create_new_event_db_table, store_event_to_db},
+    db::{
+        add_script_stats, create_new_event_db_table, create_script_record_db_table,
+        enqueue_events_to_outbox, notify_changes, store_event_to_db, store_script_record,
+    },
+    monitor::Event,
+    notify::ChangeNotification,
     traits::EventMonitor,
 };
+use script::{EventScript, PreparedBatch};
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Backfilled events stored per db transaction
+const BACKFILL_BATCH_SIZE: usize = 1000;
+
+/// What else happens in the db transaction storing a batch of events
+#[derive(Debug, Clone, Default)]
+pub struct CommitHooks {
+    /// Queues the batch in the outbox of the monitor, for its sinks
+    pub outbox: bool,
+    /// Announces the batch on the change channel of the monitor
+    pub notify: bool,
+    /// Hands the events to the user defined handlers of the monitor
+    pub handlers: Option<Arc<EventHandlers>>,
+    /// Filters and transforms the events before they are stored
+    pub script: Option<Arc<EventScript>>,
+}
+
+impl CommitHooks {
+    /// Runs the script of the monitor, if any, on a batch about to be stored
+    pub fn prepare<'a>(&self, name: &str, events: &'a [Event]) -> PreparedBatch<'a> {
+        match &self.script {
+            Some(script) => script.apply(name, events),
+            None => PreparedBatch::unscripted(events),
+        }
+    }
+
+    /// Runs the hooks for the events of `batch`, stored in the rows `ids`, before `db_tx` commits
+    pub async fn run(
+        &self,
+        db_tx: &tokio_postgres::Transaction<'_>,
+        name: &str,
+        batch: &PreparedBatch<'_>,
+        ids: &[i64],
+    ) -> Result<(), anyhow::Error> {
+        if let Some(stats) = &batch.stats {
+            add_script_stats(db_tx, name, stats).await?;
+        }
+        let events = batch.events.as_ref();
+        if events.is_empty() {
+            return Ok(());
+        }
+        let tables: BTreeSet<&str> = batch
+            .records
+            .iter()
+            .flatten()
+            .map(|record| record.table.as_str())
+            .collect();
+        for table in tables {
+            create_script_record_db_table(db_tx, table).await?;
+        }
+        for ((event, id), record) in events.iter().zip(ids.iter()).zip(batch.records.iter()) {
+            if let Some(record) = record {
+                store_script_record(db_tx, &record.table, *id, event, &record.fields).await?;
+            }
+        }
+        if let Some(handlers) = &self.handlers {
+            handlers.run(db_tx, name, events, ids).await?;
+        }
+        if self.outbox {
+            enqueue_events_to_outbox(db_tx, name, events).await?;
+        }
+        if self.notify
+            && let Some(change) = ChangeNotification::new(name, events, ids)
+        {
+            notify_changes(db_tx, &change).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Stores a batch of events in one db transaction with its hooks, so that the sinks and the
+/// listeners see exactly the committed batches. Logs that are already stored are skipped, the
+/// hooks only run for the new ones.
+pub async fn store_event_batch(
+    db_client: &mut tokio_postgres::Client,
+    name: &str,
+    events: &[Event],
+    hooks: &CommitHooks,
+) -> Result<(), anyhow::Error> {
+    let mut batch = hooks.prepare(name, events);
+    let db_tx = db_client.transaction().await?;
+    let mut ids = Vec::with_capacity(batch.events.len());
+    let mut stored = Vec::with_capacity(batch.events.len());
+    for event in batch.events.iter() {
+        let id = store_event_to_db(event, &db_tx, name).await?;
+        stored.push(id.is_some());
+        ids.extend(id);
+    }
+    if ids.len() < stored.len() {
+        batch.retain(&stored);
+    }
+    hooks.run(&db_tx, name, &batch, &ids).await?;
+    db_tx.commit().await?;
+
+    Ok(())
+}
 
 pub struct EventMonitorTable {
     name: String,
+    topics: TopicFilters,
+    join_transactions: bool,
+    hooks: CommitHooks,
 }
 
 impl EventMonitorTable {
     pub fn new(name: String) -> Self {
-        Self { name }
+        Self {
+            name,
+            topics: TopicFilters::default(),
+            join_transactions: false,
+            hooks: CommitHooks::default(),
+        }
+    }
+
+    /// Queues every committed batch in the outbox of the monitor, for its sinks
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.hooks.outbox = outbox;
+        self
+    }
+
+    /// Announces every committed batch on the change channel of the monitor
+    pub fn with_notify(mut self, notify: bool) -> Self {
+        self.hooks.notify = notify;
+        self
+    }
+
+    /// Runs `handlers` on every stored event, in the db transaction storing it
+    pub fn with_handlers(mut self, handlers: Option<Arc<EventHandlers>>) -> Self {
+        self.hooks.handlers = handlers;
+        self
+    }
+
+    /// Runs `script` on every event before it is stored
+    pub fn with_script(mut self, script: Option<Arc<EventScript>>) -> Self {
+        self.hooks.script = script;
+        self
+    }
+
+    /// Stores the sender, recipient, gas used and method selector of the transaction of every log
+    pub fn with_transactions(mut self, join_transactions: bool) -> Self {
+        self.join_transactions = join_transactions;
+        self
+    }
+
+    fn transaction_join(&self) -> Option<TransactionJoin> {
+        self.join_transactions.then(TransactionJoin::default)
+    }
+
+    /// Only index the logs whose topic1 to topic3 match the filters
+    pub fn with_topics(mut self, topics: TopicFilters) -> Self {
+        self.topics = topics;
+        self
     }
 }
 
 #[async_trait]
 impl EventMonitor for EventMonitorTable {
-    type SubProvider = RootProvider;
+    type SubProvider = RpcProvider;
     type ContractAddress = Address;
     type EventSignature = B256;
     type BlockNumber = BlockNumberOrTag;
@@ -38,19 +199,40 @@ impl EventMonitor for EventMonitorTable {
         db_client: &mut tokio_postgres::Client,
     ) -> Result<(), anyhow::Error> {
         create_new_event_db_table(db_client, &self.name).await?;
+        // Subscribe before reading the head, the backfill then ends where the live logs start
+        let stream = provider
+            .subscribe_logs(&live_filter(vec![addr], event_sig, &self.topics))
+            .await?;
+        let head = provider.get_block_number().await?;
+
         // Query existing events from the specified block number
-        let events = query_events(provider.clone(), addr, event_sig, block_number).await?;
+        let events =
+            query_events(&provider, addr, event_sig, &self.topics, block_number, head).await?;
 
         // Store all this event is the database
+        let mut join = self.transaction_join();
+        let mut joined = Vec::with_capacity(events.len());
         for event in events {
-            store_event_to_db(&event, db_client, &self.name).await?;
+            joined.push(match join.as_mut() {
+                Some(join) => join.join(&provider, event).await?,
+                None => event,
+            });
+        }
+        for batch in joined.chunks(BACKFILL_BATCH_SIZE) {
+            store_event_batch(db_client, &self.name, batch, &self.hooks).await?;
         }
 
-        // Now subsbribing the events
-        self.subscribe_to_events(provider, vec![addr], event_sig, db_client)
-            .await?;
-
-        Ok(())
+        // Now following the events after the head
+        follow_events(
+            &provider,
+            stream,
+            Some(head),
+            self.transaction_join(),
+            &self.hooks,
+            db_client,
+            &self.name,
+        )
+        .await
     }
 
     async fn subscribe_to_events(
@@ -60,8 +242,14 @@ impl EventMonitor for EventMonitorTable {
         event_sig: Self::EventSignature,
         db_client: &mut tokio_postgres::Client,
     ) -> Result<(), anyhow::Error> {
-        subscribe_to_events(provider, addr, event_sig, db_client, &self.name).await;
-
-        Ok(())
+        subscribe_to_events(
+            &provider,
+            live_filter(addr, event_sig, &self.topics),
+            self.transaction_join(),
+            &self.hooks,
+            db_client,
+            &self.name,
+        )
+        .await
     }
 }
@@ -0,0 +1,286 @@
+use super::evm::decode_event_params;
+use alloy::json_abi;
+use anyhow::{anyhow, bail};
+use primitives::{
+    ScriptConfig,
+    monitor::{Event, ScriptStats, event_json},
+};
+use rhai::{AST, Dynamic, Engine, Scope};
+use std::borrow::Cow;
+use tracing::{info, warn};
+
+/// What a script made of an event that it kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptRecord {
+    /// The full name of the record table, `<monitor>_<event.table>`
+    pub table: String,
+    /// `event.params` once the script ran
+    pub fields: serde_json::Value,
+}
+
+/// A batch about to be stored, with what the script of the monitor made of it
+#[derive(Debug)]
+pub struct PreparedBatch<'a> {
+    /// The events the script kept, all of them without a script
+    pub events: Cow<'a, [Event]>,
+    /// The record of every kept event, `None` where the script failed
+    pub records: Vec<Option<ScriptRecord>>,
+    /// `None` without a script
+    pub stats: Option<ScriptStats>,
+}
+
+impl<'a> PreparedBatch<'a> {
+    pub fn unscripted(events: &'a [Event]) -> Self {
+        Self {
+            events: Cow::Borrowed(events),
+            records: Vec::new(),
+            stats: None,
+        }
+    }
+
+    /// Keeps the events, and their records, whose flag in `keep` is set
+    pub fn retain(&mut self, keep: &[bool]) {
+        let mut flags = keep.iter().copied();
+        self.events.to_mut().retain(|_| flags.next().unwrap_or(true));
+        if !self.records.is_empty() {
+            let mut flags = keep.iter().copied();
+            self.records.retain(|_| flags.next().unwrap_or(true));
+        }
+    }
+}
+
+/// A Rhai script run on every event of a monitor before it is stored.
+/// The script sees the event as the map `event` (see `event_json`) and may change it freely:
+/// evaluating to `false` drops the event, otherwise the event is stored along with `event.params`
+/// as its record in `<monitor>_<event.table>`. A script error keeps the event without a record,
+/// and is logged and counted in `<monitor>_script_stats` rather than failing the monitor.
+pub struct EventScript {
+    engine: Engine,
+    ast: AST,
+    abi: json_abi::Event,
+    table: String,
+}
+
+impl EventScript {
+    pub fn new(config: &ScriptConfig) -> Result<Self, anyhow::Error> {
+        let source = match (&config.source, &config.path) {
+            (Some(source), None) => source.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|err| anyhow!("could not read the script {path}: {err}"))?,
+            _ => bail!("CONFIG script needs one of source or path"),
+        };
+        if !is_identifier(&config.table) {
+            bail!("CONFIG script table `{}` is not a valid name", config.table);
+        }
+
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(config.max_operations)
+            .set_max_expr_depths(64, 32)
+            .set_max_call_levels(32)
+            .set_max_string_size(64 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .on_print(|text| info!("script: {text}"))
+            .on_debug(|text, _, _| info!("script: {text}"));
+        let ast = engine
+            .compile(source)
+            .map_err(|err| anyhow!("could not compile the script: {err}"))?;
+
+        Ok(Self {
+            engine,
+            ast,
+            abi: json_abi::Event::parse(&config.event)?,
+            table: config.table.clone(),
+        })
+    }
+
+    /// Runs the script on every event of a batch of the monitor `name`
+    pub fn apply<'a>(&self, name: &str, events: &'a [Event]) -> PreparedBatch<'a> {
+        let mut stats = ScriptStats::default();
+        let mut kept = Vec::with_capacity(events.len());
+        let mut records = Vec::with_capacity(events.len());
+
+        for event in events.iter() {
+            match self.eval(name, event) {
+                Ok(Some(record)) => {
+                    stats.kept += 1;
+                    kept.push(event.clone());
+                    records.push(Some(record));
+                }
+                Ok(None) => stats.dropped += 1,
+                Err(err) => {
+                    let err = format!("log {}:{}: {err}", event.transaction_hash, event.log_index);
+                    warn!("{name}: script failed on {err}");
+                    stats.failed += 1;
+                    stats.last_error = Some(err);
+                    kept.push(event.clone());
+                    records.push(None);
+                }
+            }
+        }
+
+        PreparedBatch {
+            events: Cow::Owned(kept),
+            records,
+            stats: Some(stats),
+        }
+    }
+
+    /// The record of an event, `None` when the script drops it
+    fn eval(&self, name: &str, event: &Event) -> Result<Option<ScriptRecord>, anyhow::Error> {
+        let params = decode_event_params(&self.abi, &event.topics, &event.data)?;
+        let json = event_json(name, event, &params);
+
+        let mut scope = Scope::new();
+        scope.push("event", rhai::serde::to_dynamic(json)?);
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|err| anyhow!("{err}"))?;
+        if result.as_bool() == Ok(false) {
+            return Ok(None);
+        }
+
+        let event: serde_json::Value = rhai::serde::from_dynamic(
+            &scope
+                .get_value::<Dynamic>("event")
+                .ok_or_else(|| anyhow!("the script removed `event`"))?,
+        )?;
+        let table = match event.get("table") {
+            None | Some(serde_json::Value::Null) => self.table.as_str(),
+            Some(serde_json::Value::String(table)) if is_identifier(table) => table,
+            Some(table) => bail!("`event.table` {table} is not a valid name"),
+        };
+
+        Ok(Some(ScriptRecord {
+            table: format!("{name}_{table}"),
+            fields: event
+                .get("params")
+                .cloned()
+                .unwrap_or(serde_json::Value::Null),
+        }))
+    }
+}
+
+/// Record table names end up in SQL, only lowercase letters, digits and underscores are allowed
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl std::fmt::Debug for EventScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventScript")
+            .field("abi", &self.abi.signature())
+            .field("table", &self.table)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{U256, address};
+
+    fn transfer(value: u64) -> Event {
+        let from = address!("1f9840a85d5af5bf1d1762f925bdaddc4201f984");
+        let to = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let abi = json_abi::Event::parse(
+            "Transfer(address indexed from, address indexed to, uint256 value)",
+        )
+        .unwrap();
+        Event {
+            topics: vec![abi.selector(), from.into_word(), to.into_word()],
+            data: U256::from(value).to_be_bytes_vec().into(),
+            block_number: value,
+            ..Default::default()
+        }
+    }
+
+    fn script(source: &str) -> EventScript {
+        EventScript::new(&ScriptConfig {
+            event: "Transfer(address indexed from, address indexed to, uint256 value)".to_string(),
+            source: Some(source.to_string()),
+            path: None,
+            table: "records".to_string(),
+            max_operations: 10_000,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    pub fn test_batches_skip_stored_events() {
+        let events = [transfer(5), transfer(50), transfer(5000)];
+        let mut batch = PreparedBatch::unscripted(&events);
+        batch.retain(&[true, false, true]);
+        let blocks: Vec<u64> = batch.events.iter().map(|event| event.block_number).collect();
+        assert_eq!(blocks, [5, 5000]);
+
+        let mut batch = script("true").apply("uni", &events);
+        batch.retain(&[false, true, true]);
+        let blocks: Vec<u64> = batch.events.iter().map(|event| event.block_number).collect();
+        assert_eq!(blocks, [50, 5000]);
+        assert_eq!(batch.records.len(), 2);
+        assert_eq!(batch.records[0].as_ref().unwrap().fields["value"], "50");
+    }
+
+    #[test]
+    pub fn test_script_filters_enriches_and_routes_events() {
+        let script = script(
+            r#"
+                let value = parse_int(event.params.value);
+                if value < 10 { return false; }
+                event.params.amount = event.params.value;
+                event.params.remove("value");
+                event.params.large = value >= 1000;
+                if value >= 1000 { event.table = "large"; }
+            "#,
+        );
+        let events = [transfer(5), transfer(50), transfer(5000)];
+
+        let batch = script.apply("uni", &events);
+        let stats = batch.stats.unwrap();
+        assert_eq!((stats.kept, stats.dropped, stats.failed), (2, 1, 0));
+        assert_eq!(batch.events.len(), 2);
+        assert_eq!(
+            batch.records,
+            vec![
+                Some(ScriptRecord {
+                    table: "uni_records".to_string(),
+                    fields: serde_json::json!({
+                        "from": "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984",
+                        "to": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+                        "amount": "50",
+                        "large": false,
+                    }),
+                }),
+                Some(ScriptRecord {
+                    table: "uni_large".to_string(),
+                    fields: serde_json::json!({
+                        "from": "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984",
+                        "to": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+                        "amount": "5000",
+                        "large": true,
+                    }),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_script_errors_keep_the_event() {
+        let script = script("loop {}");
+
+        let events = [transfer(5)];
+        let batch = script.apply("uni", &events);
+        let stats = batch.stats.unwrap();
+        assert_eq!(stats.failed, 1);
+        assert!(stats.last_error.is_some());
+        assert_eq!(batch.events.len(), 1);
+        assert_eq!(batch.records, vec![None]);
+    }
+}
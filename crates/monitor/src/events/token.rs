@@ -0,0 +1,537 @@
+use super::evm::{
+    TopicFilters, decode_event_params, is_backfilled, query_events, with_topic_filters,
+};
+use crate::provider::RpcProvider;
+use alloy::{
+    dyn_abi::DynSolValue,
+    json_abi,
+    primitives::{Address, B256, U256},
+    rpc::types::eth::{BlockNumberOrTag, Filter, Log},
+    transports::TransportResult,
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::{StreamExt, stream::BoxStream};
+use primitives::{
+    BalancesConfig, MonitorKind,
+    db::{
+        apply_token_balance_delta, create_token_balance_db_tables, create_token_db_tables,
+        delete_token_approval_from_db, delete_token_transfer_from_db, enqueue_events_to_outbox,
+        revert_token_balance_history, store_token_approval_to_db, store_token_transfer_to_db,
+    },
+    monitor::{Event, TokenApproval, TokenTransfer},
+    traits::EventMonitor,
+};
+use tokio_postgres::GenericClient;
+use tracing::warn;
+
+const ERC20_TRANSFER: &str = "Transfer(address indexed from, address indexed to, uint256 value)";
+const ERC20_APPROVAL: &str =
+    "Approval(address indexed owner, address indexed spender, uint256 value)";
+const ERC721_TRANSFER: &str =
+    "Transfer(address indexed from, address indexed to, uint256 indexed tokenId)";
+const ERC721_APPROVAL: &str =
+    "Approval(address indexed owner, address indexed approved, uint256 indexed tokenId)";
+const APPROVAL_FOR_ALL: &str =
+    "ApprovalForAll(address indexed owner, address indexed operator, bool approved)";
+const ERC1155_TRANSFER_SINGLE: &str = "TransferSingle(address indexed operator, address indexed from, address indexed to, uint256 id, uint256 value)";
+const ERC1155_TRANSFER_BATCH: &str = "TransferBatch(address indexed operator, address indexed from, address indexed to, uint256[] ids, uint256[] values)";
+
+/// The token standards the presets know the event ABIs of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenStandard {
+    Erc20,
+    Erc721,
+    Erc1155,
+}
+
+/// A log decoded against one of the standard token ABIs
+#[derive(Debug, Clone)]
+pub enum TokenEvent {
+    Transfers(Vec<TokenTransfer>),
+    Approval(TokenApproval),
+}
+
+impl TokenStandard {
+    pub fn from_kind(kind: MonitorKind) -> Option<Self> {
+        match kind {
+            MonitorKind::Erc20 => Some(Self::Erc20),
+            MonitorKind::Erc721 => Some(Self::Erc721),
+            MonitorKind::Erc1155 => Some(Self::Erc1155),
+            _ => None,
+        }
+    }
+
+    /// The standard events, as human readable ABIs
+    pub fn events(&self) -> Vec<json_abi::Event> {
+        let signatures: &[&str] = match self {
+            Self::Erc20 => &[ERC20_TRANSFER, ERC20_APPROVAL],
+            Self::Erc721 => &[ERC721_TRANSFER, ERC721_APPROVAL, APPROVAL_FOR_ALL],
+            Self::Erc1155 => &[
+                ERC1155_TRANSFER_SINGLE,
+                ERC1155_TRANSFER_BATCH,
+                APPROVAL_FOR_ALL,
+            ],
+        };
+        signatures
+            .iter()
+            .map(|signature| json_abi::Event::parse(signature).expect("valid standard ABI"))
+            .collect()
+    }
+
+    /// The topic0 of every standard event, used to filter the logs
+    pub fn event_signatures(&self) -> Vec<B256> {
+        self.events().iter().map(|event| event.selector()).collect()
+    }
+
+    /// Decodes a log of this standard.
+    /// ERC-20 and ERC-721 share the `Transfer` and `Approval` selectors, they are told apart
+    /// by the number of indexed topics.
+    pub fn decode(&self, event: &Event) -> Result<TokenEvent, anyhow::Error> {
+        let abi = self
+            .events()
+            .into_iter()
+            .find(|abi| {
+                event.topics.first() == Some(&abi.selector())
+                    && event.topics.len()
+                        == 1 + abi.inputs.iter().filter(|input| input.indexed).count()
+            })
+            .ok_or_else(|| anyhow!("log is not a standard {self:?} event"))?;
+        let params = Params(decode_event_params(&abi, &event.topics, &event.data)?);
+
+        let transfer = TokenTransfer {
+            address: event.address,
+            block_number: event.block_number,
+            transaction_hash: event.transaction_hash,
+            log_index: event.log_index,
+            chain_id: event.chain_id,
+            ..Default::default()
+        };
+        let approval = TokenApproval {
+            address: event.address,
+            block_number: event.block_number,
+            transaction_hash: event.transaction_hash,
+            log_index: event.log_index,
+            chain_id: event.chain_id,
+            ..Default::default()
+        };
+
+        let decoded = match abi.signature().as_str() {
+            "Transfer(address,address,uint256)" if *self == Self::Erc20 => {
+                TokenEvent::Transfers(vec![TokenTransfer {
+                    from: params.address("from")?,
+                    to: params.address("to")?,
+                    value: Some(params.uint("value")?),
+                    ..transfer
+                }])
+            }
+            "Transfer(address,address,uint256)" => TokenEvent::Transfers(vec![TokenTransfer {
+                from: params.address("from")?,
+                to: params.address("to")?,
+                token_id: Some(params.uint("tokenId")?),
+                ..transfer
+            }]),
+            "Approval(address,address,uint256)" if *self == Self::Erc20 => {
+                TokenEvent::Approval(TokenApproval {
+                    owner: params.address("owner")?,
+                    spender: params.address("spender")?,
+                    value: Some(params.uint("value")?),
+                    ..approval
+                })
+            }
+            "Approval(address,address,uint256)" => TokenEvent::Approval(TokenApproval {
+                owner: params.address("owner")?,
+                spender: params.address("approved")?,
+                token_id: Some(params.uint("tokenId")?),
+                ..approval
+            }),
+            "ApprovalForAll(address,address,bool)" => TokenEvent::Approval(TokenApproval {
+                owner: params.address("owner")?,
+                spender: params.address("operator")?,
+                approved: Some(params.bool("approved")?),
+                ..approval
+            }),
+            "TransferSingle(address,address,address,uint256,uint256)" => {
+                TokenEvent::Transfers(vec![TokenTransfer {
+                    operator: Some(params.address("operator")?),
+                    from: params.address("from")?,
+                    to: params.address("to")?,
+                    token_id: Some(params.uint("id")?),
+                    value: Some(params.uint("value")?),
+                    ..transfer
+                }])
+            }
+            _ => {
+                let ids = params.uints("ids")?;
+                let values = params.uints("values")?;
+                let operator = params.address("operator")?;
+                let from = params.address("from")?;
+                let to = params.address("to")?;
+                TokenEvent::Transfers(
+                    ids.into_iter()
+                        .zip(values)
+                        .enumerate()
+                        .map(|(batch_index, (id, value))| TokenTransfer {
+                            batch_index: batch_index as u32,
+                            operator: Some(operator),
+                            from,
+                            to,
+                            token_id: Some(id),
+                            value: Some(value),
+                            ..transfer.clone()
+                        })
+                        .collect(),
+                )
+            }
+        };
+
+        Ok(decoded)
+    }
+}
+
+/// Decoded parameters looked up by name
+struct Params(Vec<(String, DynSolValue)>);
+
+impl Params {
+    fn get(&self, name: &str) -> Result<&DynSolValue, anyhow::Error> {
+        self.0
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| anyhow!("missing parameter `{name}`"))
+    }
+
+    fn address(&self, name: &str) -> Result<Address, anyhow::Error> {
+        self.get(name)?
+            .as_address()
+            .ok_or_else(|| anyhow!("`{name}` is not an address"))
+    }
+
+    fn uint(&self, name: &str) -> Result<U256, anyhow::Error> {
+        self.get(name)?
+            .as_uint()
+            .map(|(value, _)| value)
+            .ok_or_else(|| anyhow!("`{name}` is not an unsigned integer"))
+    }
+
+    fn uints(&self, name: &str) -> Result<Vec<U256>, anyhow::Error> {
+        self.get(name)?
+            .as_array()
+            .ok_or_else(|| anyhow!("`{name}` is not an array"))?
+            .iter()
+            .map(|value| {
+                value
+                    .as_uint()
+                    .map(|(value, _)| value)
+                    .ok_or_else(|| anyhow!("`{name}` holds a non integer value"))
+            })
+            .collect()
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, anyhow::Error> {
+        self.get(name)?
+            .as_bool()
+            .ok_or_else(|| anyhow!("`{name}` is not a boolean"))
+    }
+}
+
+/// Indexes the standard events of a token into decoded `{name}_transfers` and
+/// `{name}_approvals` tables.
+/// When balances are enabled, `{name}_balances` moves in the same db transaction as the
+/// transfers, and logs removed by a reorg are deleted and their balance changes reverted.
+pub struct TokenMonitorTable {
+    name: String,
+    standard: TokenStandard,
+    balances: Option<BalancesConfig>,
+    topics: TopicFilters,
+    outbox: bool,
+}
+
+impl TokenMonitorTable {
+    pub fn new(name: String, standard: TokenStandard) -> Self {
+        Self {
+            name,
+            standard,
+            balances: None,
+            topics: TopicFilters::default(),
+            outbox: false,
+        }
+    }
+
+    /// Only index the logs whose topic1 to topic3 match the filters,
+    /// e.g. a topic2 filter on `Transfer` only keeps the transfers to these addresses
+    pub fn with_topics(mut self, topics: TopicFilters) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Maintains the derived balance tables from the transfers
+    pub fn with_balances(mut self, balances: Option<BalancesConfig>) -> Self {
+        self.balances = balances;
+        self
+    }
+
+    /// Queues every stored log in the outbox of the monitor, for its sinks
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// The holder balance changes of a transfer, mints and burns only move one side.
+    /// ERC-721 balances count tokens, ERC-1155 balances are kept per token id.
+    fn balance_deltas(&self, transfer: &TokenTransfer) -> Vec<(Address, String, U256, bool)> {
+        let (token_id, amount) = match self.standard {
+            TokenStandard::Erc20 => (String::new(), transfer.value.unwrap_or_default()),
+            TokenStandard::Erc721 => (String::new(), U256::from(1)),
+            TokenStandard::Erc1155 => (
+                transfer.token_id.unwrap_or_default().to_string(),
+                transfer.value.unwrap_or_default(),
+            ),
+        };
+
+        [(transfer.from, false), (transfer.to, true)]
+            .into_iter()
+            .filter(|(holder, _)| *holder != Address::ZERO)
+            .map(|(holder, credit)| (holder, token_id.clone(), amount, credit))
+            .collect()
+    }
+
+    async fn apply_balances(
+        &self,
+        db_client: &impl GenericClient,
+        transfer: &TokenTransfer,
+        revert: bool,
+    ) -> Result<(), anyhow::Error> {
+        let Some(balances) = &self.balances else {
+            return Ok(());
+        };
+        for (holder, token_id, amount, credit) in self.balance_deltas(transfer) {
+            // The balance at the end of the block before is known from the history, whatever the
+            // blocks after the reorged one changed
+            if revert && balances.history {
+                revert_token_balance_history(
+                    db_client,
+                    &self.name,
+                    &holder,
+                    &token_id,
+                    transfer.block_number,
+                )
+                .await?;
+                continue;
+            }
+            let delta = if credit != revert {
+                amount.to_string()
+            } else {
+                format!("-{amount}")
+            };
+            apply_token_balance_delta(
+                db_client,
+                &self.name,
+                &holder,
+                &token_id,
+                &delta,
+                transfer.block_number,
+                balances.history,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The filter of the live logs of the standard events emitted by `addr`
+    fn live_filter(&self, addr: Vec<Address>, event_sig: Vec<B256>) -> Filter {
+        let filter = Filter::new()
+            .address(addr)
+            .event_signature(event_sig)
+            .from_block(BlockNumberOrTag::Latest);
+        with_topic_filters(filter, &self.topics)
+    }
+
+    /// Stores the logs of `stream` as they arrive, skipping the logs up to `head` that were
+    /// already backfilled
+    async fn follow(
+        &self,
+        provider: &RpcProvider,
+        mut stream: BoxStream<'static, TransportResult<Log>>,
+        head: Option<u64>,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        while let Some(log) = stream.next().await {
+            let log = log?;
+            if is_backfilled(&log, head) {
+                continue;
+            }
+            let event = Event::from(log).with_chain_id(provider.chain_id());
+            self.store_event(&event, db_client).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes and stores one log, logs that do not match the standard are skipped.
+    /// A removed log deletes what it stored before and reverts its balance changes.
+    async fn store_event(
+        &self,
+        event: &Event,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let decoded = match self.standard.decode(event) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                warn!(
+                    "{}: skipping log {}:{}, {err}",
+                    self.name, event.transaction_hash, event.log_index
+                );
+                return Ok(());
+            }
+        };
+
+        let db_tx = db_client.transaction().await?;
+        match decoded {
+            TokenEvent::Transfers(transfers) => {
+                for transfer in transfers.iter() {
+                    // Transfers stored by a previous run are not applied to the balances twice
+                    let changed = if event.removed {
+                        delete_token_transfer_from_db(&db_tx, &self.name, transfer).await?
+                    } else {
+                        store_token_transfer_to_db(&db_tx, &self.name, transfer).await?
+                    };
+                    if changed {
+                        self.apply_balances(&db_tx, transfer, event.removed).await?;
+                    }
+                }
+            }
+            TokenEvent::Approval(approval) if event.removed => {
+                delete_token_approval_from_db(&db_tx, &self.name, &approval).await?;
+            }
+            TokenEvent::Approval(approval) => {
+                store_token_approval_to_db(&db_tx, &self.name, &approval).await?;
+            }
+        }
+        if self.outbox {
+            enqueue_events_to_outbox(&db_tx, &self.name, std::slice::from_ref(event)).await?;
+        }
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventMonitor for TokenMonitorTable {
+    type SubProvider = RpcProvider;
+    type ContractAddress = Address;
+    type EventSignature = Vec<B256>;
+    type BlockNumber = BlockNumberOrTag;
+
+    async fn query_and_subscribe_to_events(
+        &self,
+        provider: Self::SubProvider,
+        addr: Self::ContractAddress,
+        event_sig: Self::EventSignature,
+        block_number: Self::BlockNumber,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_token_db_tables(db_client, &self.name).await?;
+        if let Some(balances) = &self.balances {
+            create_token_balance_db_tables(db_client, &self.name, balances.history).await?;
+        }
+
+        // Subscribe before reading the head, the backfill then ends where the live logs start
+        let stream = provider
+            .subscribe_logs(&self.live_filter(vec![addr], event_sig.clone()))
+            .await?;
+        let head = provider.get_block_number().await?;
+
+        let events =
+            query_events(&provider, addr, event_sig, &self.topics, block_number, head).await?;
+        for event in events.iter() {
+            self.store_event(event, db_client).await?;
+        }
+
+        self.follow(&provider, stream, Some(head), db_client).await
+    }
+
+    async fn subscribe_to_events(
+        &self,
+        provider: Self::SubProvider,
+        addr: Vec<Self::ContractAddress>,
+        event_sig: Self::EventSignature,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let stream = provider
+            .subscribe_logs(&self.live_filter(addr, event_sig))
+            .await?;
+
+        self.follow(&provider, stream, None, db_client).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{Bytes, address};
+
+    #[test]
+    pub fn test_decode_erc20_and_erc721_transfers() {
+        let from = address!("d8da6bf26964af9d7eed9e03e53415d37aa96045");
+        let to = address!("88da6bf26964af9d7eed9e03e53415d37aa96045");
+        let selector = TokenStandard::Erc20.event_signatures()[0];
+
+        let erc20 = Event {
+            topics: vec![selector, from.into_word(), to.into_word()],
+            data: Bytes::from(U256::from(1000).to_be_bytes_vec()),
+            ..Default::default()
+        };
+        let TokenEvent::Transfers(transfers) = TokenStandard::Erc20.decode(&erc20).unwrap() else {
+            panic!("expected a transfer");
+        };
+        assert_eq!(transfers[0].from, from);
+        assert_eq!(transfers[0].to, to);
+        assert_eq!(transfers[0].value, Some(U256::from(1000)));
+        assert_eq!(transfers[0].token_id, None);
+
+        let erc721 = Event {
+            topics: vec![
+                selector,
+                from.into_word(),
+                to.into_word(),
+                B256::from(U256::from(7)),
+            ],
+            ..Default::default()
+        };
+        // An ERC-721 transfer has one more indexed topic and is rejected by the ERC-20 preset
+        assert!(TokenStandard::Erc20.decode(&erc721).is_err());
+        let TokenEvent::Transfers(transfers) = TokenStandard::Erc721.decode(&erc721).unwrap()
+        else {
+            panic!("expected a transfer");
+        };
+        assert_eq!(transfers[0].token_id, Some(U256::from(7)));
+        assert_eq!(transfers[0].value, None);
+    }
+
+    #[test]
+    pub fn test_balance_deltas_skip_the_zero_address() {
+        let holder = address!("d8da6bf26964af9d7eed9e03e53415d37aa96045");
+        let mint = TokenTransfer {
+            from: Address::ZERO,
+            to: holder,
+            token_id: Some(U256::from(7)),
+            value: Some(U256::from(5)),
+            ..Default::default()
+        };
+
+        let erc1155 = TokenMonitorTable::new("t".into(), TokenStandard::Erc1155);
+        assert_eq!(
+            erc1155.balance_deltas(&mint),
+            vec![(holder, "7".to_string(), U256::from(5), true)]
+        );
+
+        let erc721 = TokenMonitorTable::new("t".into(), TokenStandard::Erc721);
+        assert_eq!(
+            erc721.balance_deltas(&mint),
+            vec![(holder, String::new(), U256::from(1), true)]
+        );
+    }
+}
@@ -0,0 +1,507 @@
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use primitives::{
+    WasmConfig,
+    db::{
+        create_entity_db_table, get_entity, get_schema_entity, remove_entity, remove_schema_entity,
+        store_entity, store_schema_entity,
+    },
+    monitor::EventContext,
+    schema::EntitySchema,
+    traits::EventHandler,
+};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+/// The import module of the host functions
+const HOST_MODULE: &str = "nexus";
+
+/// An entity operation of a running module, applied to the db transaction of the batch
+#[derive(Debug, Clone, PartialEq)]
+enum EntityOp {
+    Set {
+        entity: String,
+        id: String,
+        data: serde_json::Value,
+    },
+    Get {
+        entity: String,
+        id: String,
+    },
+    Remove {
+        entity: String,
+        id: String,
+    },
+}
+
+struct EntityRequest {
+    op: EntityOp,
+    /// The entity read by a `Get`, errors are passed as text to become a trap
+    reply: oneshot::Sender<Result<Option<serde_json::Value>, String>>,
+}
+
+struct HostState {
+    monitor: String,
+    limits: StoreLimits,
+    requests: mpsc::UnboundedSender<EntityRequest>,
+}
+
+fn trap(message: impl std::fmt::Display) -> wasmi::Error {
+    wasmi::Error::new(message.to_string())
+}
+
+fn memory(caller: &Caller<'_, HostState>) -> Result<Memory, wasmi::Error> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| trap("the module does not export `memory`"))
+}
+
+fn read_bytes(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Result<Vec<u8>, wasmi::Error> {
+    let start = ptr as u32 as usize;
+    let end = start.saturating_add(len as u32 as usize);
+    memory(caller)?
+        .data(caller)
+        .get(start..end)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| trap(format!("{start}..{end} is out of the module memory")))
+}
+
+fn read_str(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Result<String, wasmi::Error> {
+    String::from_utf8(read_bytes(caller, ptr, len)?).map_err(trap)
+}
+
+/// Hands an entity operation to the db transaction and waits for it to be applied
+fn request(
+    caller: &Caller<'_, HostState>,
+    op: EntityOp,
+) -> Result<Option<serde_json::Value>, wasmi::Error> {
+    let (reply, receiver) = oneshot::channel();
+    caller
+        .data()
+        .requests
+        .send(EntityRequest { op, reply })
+        .map_err(|_| trap("the db transaction is gone"))?;
+
+    receiver
+        .blocking_recv()
+        .map_err(|_| trap("the db transaction is gone"))?
+        .map_err(trap)
+}
+
+/// Defines the host functions, every pointer and length refers to the memory of the module:
+/// - `entity_set(entity, entity_len, id, id_len, json, json_len)` creates or replaces an entity
+/// - `entity_get(entity, entity_len, id, id_len, out, out_len) -> i32` is `-1` for a missing
+///   entity, otherwise the length of its JSON, which is only written to `out` when it fits
+/// - `entity_remove(entity, entity_len, id, id_len)` deletes an entity
+/// - `log(level, message, message_len)` logs at debug, info, warn or error for levels 0 to 3
+fn host_functions(engine: &Engine) -> Result<Linker<HostState>, anyhow::Error> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        HOST_MODULE,
+        "entity_set",
+        |caller: Caller<'_, HostState>,
+         entity: i32,
+         entity_len: i32,
+         id: i32,
+         id_len: i32,
+         json: i32,
+         json_len: i32|
+         -> Result<(), wasmi::Error> {
+            let op = EntityOp::Set {
+                entity: read_str(&caller, entity, entity_len)?,
+                id: read_str(&caller, id, id_len)?,
+                data: serde_json::from_slice(&read_bytes(&caller, json, json_len)?)
+                    .map_err(trap)?,
+            };
+            request(&caller, op)?;
+
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "entity_get",
+        |mut caller: Caller<'_, HostState>,
+         entity: i32,
+         entity_len: i32,
+         id: i32,
+         id_len: i32,
+         out: i32,
+         out_len: i32|
+         -> Result<i32, wasmi::Error> {
+            let op = EntityOp::Get {
+                entity: read_str(&caller, entity, entity_len)?,
+                id: read_str(&caller, id, id_len)?,
+            };
+            let Some(data) = request(&caller, op)? else {
+                return Ok(-1);
+            };
+
+            let json = data.to_string().into_bytes();
+            if json.len() <= out_len as u32 as usize {
+                memory(&caller)?
+                    .write(&mut caller, out as u32 as usize, &json)
+                    .map_err(trap)?;
+            }
+            i32::try_from(json.len()).map_err(trap)
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "entity_remove",
+        |caller: Caller<'_, HostState>,
+         entity: i32,
+         entity_len: i32,
+         id: i32,
+         id_len: i32|
+         -> Result<(), wasmi::Error> {
+            let op = EntityOp::Remove {
+                entity: read_str(&caller, entity, entity_len)?,
+                id: read_str(&caller, id, id_len)?,
+            };
+            request(&caller, op)?;
+
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |caller: Caller<'_, HostState>,
+         level: i32,
+         message: i32,
+         message_len: i32|
+         -> Result<(), wasmi::Error> {
+            let message = read_str(&caller, message, message_len)?;
+            let monitor = &caller.data().monitor;
+            match level {
+                0 => debug!("{monitor}: {message}"),
+                1 => info!("{monitor}: {message}"),
+                2 => warn!("{monitor}: {message}"),
+                _ => error!("{monitor}: {message}"),
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(linker)
+}
+
+/// A compiled module, instantiated afresh for every call
+struct WasmRuntime {
+    config: WasmConfig,
+    engine: Engine,
+    module: Module,
+    linker: Linker<HostState>,
+    has_revert: bool,
+}
+
+impl WasmRuntime {
+    fn new(config: &WasmConfig, wasm: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(true).floats(false);
+        let engine = Engine::new(&engine_config);
+        let module = Module::new(&engine, wasm)?;
+
+        for export in ["memory", "alloc", config.handler.as_str()] {
+            if module.get_export(export).is_none() {
+                return Err(anyhow!("the module does not export `{export}`"));
+            }
+        }
+        let has_revert = module.get_export(&config.revert).is_some();
+        if !has_revert {
+            warn!(
+                "the module does not export `{}`, reorgs are not reverted",
+                config.revert
+            );
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            linker: host_functions(&engine)?,
+            engine,
+            module,
+            has_revert,
+        })
+    }
+
+    /// Calls `export` with `payload` written to memory obtained from the `alloc` export.
+    /// Blocks on every entity operation until it is applied, so it must not run on the runtime.
+    fn invoke(
+        &self,
+        monitor: &str,
+        export: &str,
+        payload: &[u8],
+        requests: mpsc::UnboundedSender<EntityRequest>,
+    ) -> Result<(), anyhow::Error> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.config.max_memory_bytes)
+            .build();
+        let mut store = Store::new(
+            &self.engine,
+            HostState {
+                monitor: monitor.to_string(),
+                limits,
+                requests,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store
+            .set_fuel(self.config.fuel)
+            .map_err(|err| anyhow!("{err}"))?;
+
+        let instance = self
+            .linker
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| anyhow!("the module does not export `memory`"))?;
+        let len = i32::try_from(payload.len())?;
+        let ptr = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")?
+            .call(&mut store, len)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, payload)
+            .map_err(|err| anyhow!("{err}"))?;
+
+        instance
+            .get_typed_func::<(i32, i32), ()>(&store, export)?
+            .call(&mut store, (ptr, len))?;
+
+        Ok(())
+    }
+}
+
+/// Runs a WebAssembly module on the events of a monitor.
+/// The module exports `memory`, `alloc(len) -> ptr` and the handler and revert functions, which
+/// take the pointer and length of the event as JSON (see `EventContext::to_json`). Entities are
+/// kept through the host functions of the `nexus` module, in the tables of the entity schema of
+/// the monitor when it has one and in `{name}_entities` otherwise. Every call gets a new instance
+/// with its own fuel and memory limits, and floating point code is rejected.
+pub struct WasmHandler {
+    name: String,
+    runtime: Arc<WasmRuntime>,
+    schema: Option<Arc<EntitySchema>>,
+}
+
+impl WasmHandler {
+    pub fn new(name: &str, config: &WasmConfig, wasm: &[u8]) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            name: name.to_string(),
+            runtime: Arc::new(WasmRuntime::new(config, wasm)?),
+            schema: None,
+        })
+    }
+
+    /// Writes the entities to the tables of `schema`, an entity it does not declare fails the call
+    pub fn with_schema(mut self, schema: Option<Arc<EntitySchema>>) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Compiles the module at `config.module`
+    pub fn load(name: &str, config: &WasmConfig) -> Result<Self, anyhow::Error> {
+        let wasm = std::fs::read(&config.module)
+            .with_context(|| format!("could not read the wasm module {}", config.module))?;
+        Self::new(name, config, &wasm)
+            .with_context(|| format!("could not load the wasm module {}", config.module))
+    }
+
+    async fn call(
+        &self,
+        export: &str,
+        ctx: &EventContext,
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<(), anyhow::Error> {
+        let payload = serde_json::to_vec(&ctx.to_json())?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let runtime = self.runtime.clone();
+        let monitor = self.name.clone();
+        let function = export.to_string();
+        let invocation = tokio::task::spawn_blocking(move || {
+            runtime.invoke(&monitor, &function, &payload, sender)
+        });
+
+        // The channel closes once the call is over and its store dropped
+        while let Some(request) = receiver.recv().await {
+            let result = self.apply(request.op, ctx.event.block_number, db_tx).await;
+            let _ = request.reply.send(result.map_err(|err| err.to_string()));
+        }
+
+        invocation.await?.with_context(|| {
+            format!(
+                "{}: `{export}` failed on log {}:{}",
+                self.name, ctx.event.transaction_hash, ctx.event.log_index
+            )
+        })
+    }
+
+    async fn apply(
+        &self,
+        op: EntityOp,
+        block_number: u64,
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<Option<serde_json::Value>, anyhow::Error> {
+        let name = &self.name;
+        let Some(schema) = &self.schema else {
+            return match op {
+                EntityOp::Set { entity, id, data } => {
+                    store_entity(db_tx, name, &entity, &id, &data, block_number)
+                        .await
+                        .map(|_| None)
+                }
+                EntityOp::Get { entity, id } => get_entity(db_tx, name, &entity, &id).await,
+                EntityOp::Remove { entity, id } => {
+                    remove_entity(db_tx, name, &entity, &id).await.map(|_| None)
+                }
+            };
+        };
+
+        let declared = |entity: &str| {
+            schema
+                .entity(entity)
+                .ok_or_else(|| anyhow!("the schema declares no entity `{entity}`"))
+        };
+        match op {
+            EntityOp::Set { entity, id, data } => {
+                store_schema_entity(db_tx, name, declared(&entity)?, &id, &data, block_number)
+                    .await
+                    .map(|_| None)
+            }
+            EntityOp::Get { entity, id } => {
+                get_schema_entity(db_tx, name, declared(&entity)?, &id).await
+            }
+            EntityOp::Remove { entity, id } => {
+                remove_schema_entity(db_tx, name, declared(&entity)?, &id)
+                    .await
+                    .map(|_| None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for WasmHandler {
+    /// The tables of a schema are created with the monitor
+    async fn setup(&self, db_client: &tokio_postgres::Client) -> Result<(), anyhow::Error> {
+        if self.schema.is_some() {
+            return Ok(());
+        }
+        create_entity_db_table(db_client, &self.name).await
+    }
+
+    async fn handle(
+        &self,
+        ctx: &EventContext,
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<(), anyhow::Error> {
+        self.call(&self.runtime.config.handler, ctx, db_tx).await
+    }
+
+    async fn revert(
+        &self,
+        ctx: &EventContext,
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<(), anyhow::Error> {
+        if !self.runtime.has_revert {
+            return Ok(());
+        }
+        self.call(&self.runtime.config.revert, ctx, db_tx).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"
+        (module
+          (import "nexus" "entity_get" (func $get (param i32 i32 i32 i32 i32 i32) (result i32)))
+          (import "nexus" "entity_set" (func $set (param i32 i32 i32 i32 i32 i32)))
+          (import "nexus" "log" (func $log (param i32 i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "Lastlast")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "handle") (param $ptr i32) (param $len i32)
+            (drop (call $get (i32.const 0) (i32.const 4) (i32.const 4) (i32.const 4) (i32.const 512) (i32.const 256)))
+            (call $set (i32.const 0) (i32.const 4) (i32.const 4) (i32.const 4) (local.get $ptr) (local.get $len))
+            (call $log (i32.const 1) (i32.const 0) (i32.const 4)))
+          (func (export "spin") (param i32 i32) (loop $spin (br $spin))))
+    "#;
+
+    fn config() -> WasmConfig {
+        WasmConfig {
+            module: "handler.wasm".to_string(),
+            event: "Transfer(address indexed from, address indexed to, uint256 value)".to_string(),
+            handler: "handle".to_string(),
+            revert: "revert".to_string(),
+            fuel: 100_000,
+            max_memory_bytes: 1024 * 1024,
+        }
+    }
+
+    /// Runs `export` against an entity store that has nothing stored, returning its operations
+    fn invoke(runtime: &WasmRuntime, export: &str) -> (Result<(), anyhow::Error>, Vec<EntityOp>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<EntityRequest>();
+        let store = std::thread::spawn(move || {
+            let mut ops = Vec::new();
+            while let Some(request) = receiver.blocking_recv() {
+                ops.push(request.op);
+                let _ = request.reply.send(Ok(None));
+            }
+            ops
+        });
+        let result = runtime.invoke("uni", export, br#"{"row_id":1}"#, sender);
+
+        (result, store.join().unwrap())
+    }
+
+    #[test]
+    pub fn test_module_writes_entities_through_the_host() {
+        let runtime = WasmRuntime::new(&config(), &wat::parse_str(MODULE).unwrap()).unwrap();
+        assert!(!runtime.has_revert);
+
+        let (result, ops) = invoke(&runtime, "handle");
+        result.unwrap();
+        assert_eq!(
+            ops,
+            vec![
+                EntityOp::Get {
+                    entity: "Last".to_string(),
+                    id: "last".to_string(),
+                },
+                EntityOp::Set {
+                    entity: "Last".to_string(),
+                    id: "last".to_string(),
+                    data: serde_json::json!({"row_id": 1}),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_fuel_bounds_every_call() {
+        let runtime = WasmRuntime::new(&config(), &wat::parse_str(MODULE).unwrap()).unwrap();
+
+        let (result, ops) = invoke(&runtime, "spin");
+        assert!(result.is_err());
+        assert!(ops.is_empty());
+    }
+
+    #[test]
+    pub fn test_floating_point_modules_are_rejected() {
+        let module = MODULE.replace(
+            "(i32.const 1024))",
+            "(drop (f32.add (f32.const 1) (f32.const 2))) (i32.const 1024))",
+        );
+
+        assert!(WasmRuntime::new(&config(), &wat::parse_str(module).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use super::{
+    CommitHooks,
+    evm::{TopicFilters, with_topic_filters},
+    handler::EventHandlers,
+    script::EventScript,
+};
+use crate::provider::RpcProvider;
+use alloy::{primitives::B256, rpc::types::eth::Filter};
+use primitives::{
+    WildcardConfig,
+    db::{
+        create_partitioned_event_db_table, create_progress_db_table, get_progress, set_progress,
+        store_events_to_db,
+    },
+    monitor::Event,
+};
+use std::sync::Arc;
+use tracing::info;
+
+/// Indexes every log matching a signature, whatever contract emitted it.
+/// Such a table grows with the whole chain, so it is hash partitioned by emitter and filled by
+/// querying the logs of block ranges, each range written in multi row batches in one db
+/// transaction. The logs of a block are only queried once it is `confirmations` blocks behind the
+/// head, as the logs of a block past the stored progress are never indexed again.
+pub struct WildcardMonitorTable {
+    name: String,
+    config: WildcardConfig,
+    topics: TopicFilters,
+    hooks: CommitHooks,
+}
+
+impl WildcardMonitorTable {
+    pub fn new(name: String, config: WildcardConfig) -> Self {
+        Self {
+            name,
+            config,
+            topics: TopicFilters::default(),
+            hooks: CommitHooks::default(),
+        }
+    }
+
+    /// Only index the logs whose topic1 to topic3 match the filters
+    pub fn with_topics(mut self, topics: TopicFilters) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Queues every committed batch in the outbox of the monitor, for its sinks
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.hooks.outbox = outbox;
+        self
+    }
+
+    /// Announces every committed batch on the change channel of the monitor
+    pub fn with_notify(mut self, notify: bool) -> Self {
+        self.hooks.notify = notify;
+        self
+    }
+
+    /// Runs `handlers` on every stored event, in the db transaction storing it
+    pub fn with_handlers(mut self, handlers: Option<Arc<EventHandlers>>) -> Self {
+        self.hooks.handlers = handlers;
+        self
+    }
+
+    /// Runs `script` on every event before it is stored
+    pub fn with_script(mut self, script: Option<Arc<EventScript>>) -> Self {
+        self.hooks.script = script;
+        self
+    }
+
+    fn filter(&self, event_sig: B256) -> Filter {
+        with_topic_filters(Filter::new().event_signature(event_sig), &self.topics)
+    }
+
+    /// The batches the logs of a block range are handed to the hooks in
+    fn batches<'a>(&self, events: &'a [Event]) -> std::slice::Chunks<'a, Event> {
+        events.chunks(self.config.batch_size.max(1))
+    }
+
+    async fn store_batch(
+        &self,
+        events: &[Event],
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<(), anyhow::Error> {
+        let batch = self.hooks.prepare(&self.name, events);
+        let ids = store_events_to_db(db_tx, &batch.events, &self.name).await?;
+        self.hooks.run(db_tx, &self.name, &batch, &ids).await
+    }
+
+    /// Indexes the logs of the blocks `from..=to` in one db transaction, which also records the
+    /// progress of the monitor, so that a restart neither skips nor repeats a block
+    async fn index_range(
+        &self,
+        provider: &RpcProvider,
+        event_sig: B256,
+        from: u64,
+        to: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let events: Vec<Event> = provider
+            .get_logs_split(&self.filter(event_sig), from, to)
+            .await?
+            .into_iter()
+            .map(|log| Event::from(log).with_chain_id(provider.chain_id()))
+            .collect();
+
+        let db_tx = db_client.transaction().await?;
+        for batch in self.batches(&events) {
+            self.store_batch(batch, &db_tx).await?;
+        }
+        set_progress(&db_tx, &self.name, to).await?;
+        db_tx.commit().await?;
+        info!(
+            "{}: indexed {} logs of blocks {from}..={to}",
+            self.name,
+            events.len()
+        );
+
+        Ok(())
+    }
+
+    /// Indexes the logs from `from_block`, or from the block after the stored progress, up to
+    /// the confirmed head one `block_range` at a time, then the blocks of every new head once
+    /// they are confirmed
+    pub async fn query_and_subscribe_to_events(
+        &self,
+        provider: RpcProvider,
+        event_sig: B256,
+        from_block: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_partitioned_event_db_table(db_client, &self.name, self.config.partitions).await?;
+        create_progress_db_table(db_client, &self.name).await?;
+
+        let from_block = match get_progress(db_client, &self.name).await? {
+            Some(last) => last + 1,
+            None => from_block,
+        };
+        let mut ranges = provider
+            .block_ranges(
+                from_block,
+                self.config.block_range,
+                self.config.confirmations,
+            )
+            .await?;
+        loop {
+            let (from, to) = ranges.next_range().await?;
+            self.index_range(&provider, event_sig, from, to, db_client)
+                .await?;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::Address;
+    use primitives::db::create_db_instance;
+    use std::collections::HashMap;
+
+    fn wildcard(batch_size: usize) -> WildcardMonitorTable {
+        WildcardMonitorTable::new(
+            "all_transfers".to_string(),
+            WildcardConfig {
+                partitions: 4,
+                batch_size,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn events(count: u64) -> Vec<Event> {
+        (0..count)
+            .map(|i| Event {
+                address: Address::with_last_byte(i as u8),
+                block_number: 100 + i,
+                log_index: i,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_batches_split_block_ranges() {
+        let events = events(5);
+        let sizes: Vec<usize> = wildcard(2).batches(&events).map(<[Event]>::len).collect();
+        assert_eq!(sizes, [2, 2, 1]);
+
+        let sizes: Vec<usize> = wildcard(5).batches(&events).map(<[Event]>::len).collect();
+        assert_eq!(sizes, [5]);
+        // A batch size of 0 is read as 1
+        assert_eq!(wildcard(0).batches(&events).count(), 5);
+        assert_eq!(wildcard(2).batches(&[]).count(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    pub async fn test_logs_are_routed_to_the_partition_of_their_emitter() {
+        let mut client = create_db_instance("host=localhost user=postgres")
+            .await
+            .expect("Could not create db instance");
+        let monitor = wildcard(3);
+        client
+            .batch_execute("DROP TABLE IF EXISTS all_transfers CASCADE")
+            .await
+            .unwrap();
+        create_partitioned_event_db_table(&mut client, &monitor.name, 4)
+            .await
+            .unwrap();
+
+        // Every emitter twice, in separate batches
+        let mut events = events(8);
+        events.extend(events.clone());
+        let db_tx = client.transaction().await.unwrap();
+        for batch in monitor.batches(&events) {
+            monitor.store_batch(batch, &db_tx).await.unwrap();
+        }
+        db_tx.commit().await.unwrap();
+
+        let rows = client
+            .query(
+                "SELECT address, tableoid::regclass::TEXT FROM all_transfers",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 16);
+        let mut partitions: HashMap<String, String> = HashMap::new();
+        for row in rows.iter() {
+            let partition: String = row.get(1);
+            assert!(partition.starts_with("all_transfers_p"));
+            let known = partitions.entry(row.get(0)).or_insert(partition.clone());
+            assert_eq!(*known, partition);
+        }
+        assert_eq!(partitions.len(), 8);
+    }
+}
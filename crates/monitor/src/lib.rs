@@ -1,4 +1,12 @@
+/// The chain adapters selected by the `state_machine` of a monitor
+pub mod adapter;
+/// The view calls polled per block
+pub mod calls;
 /// The mod index for events
 pub mod events;
 /// The mod index for tx
 pub mod tx;
+/// The rate limited and retrying provider used for all RPC calls
+pub mod provider;
+/// The storage slots watched per block
+pub mod storage;
@@ -0,0 +1,884 @@
+use alloy::{
+    network::{AnyNetwork, AnyRpcBlock, AnyRpcHeader, AnyRpcTransaction, AnyTransactionReceipt},
+    primitives::{Address, B256, Bytes, TxHash, U256},
+    providers::{
+        Provider, ProviderBuilder, RootProvider,
+        ext::{DebugApi, TraceApi},
+    },
+    pubsub::Subscription,
+    rpc::{
+        client::WsConnect,
+        types::{
+            eth::{BlockNumberOrTag, Filter, Log, TransactionRequest},
+            trace::{
+                geth::{CallConfig, GethDebugTracingOptions, TraceResult},
+                parity::LocalizedTransactionTrace,
+            },
+        },
+    },
+    transports::{
+        RpcError, TransportError, TransportErrorKind, TransportResult,
+        layers::{RateLimitRetryPolicy, RetryPolicy},
+    },
+};
+use futures::{
+    FutureExt, StreamExt,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+};
+use primitives::RpcConfig;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use std::{collections::VecDeque, future::Future, io::ErrorKind, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, broadcast::error::RecvError},
+    time::{Instant, error::Elapsed},
+};
+use tracing::warn;
+
+/// Compute units charged for a method when it has no entry in [`compute_units`]
+const DEFAULT_COMPUTE_UNITS: u64 = 17;
+
+/// How an RPC error should be handled by the retry loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The endpoint asked us to slow down (HTTP 429, provider specific limit codes)
+    RateLimited,
+    /// A temporary failure (dropped connection, 5xx, null response) worth retrying
+    Transient,
+    /// The request can never succeed (bad params, unknown method, reverted call)
+    Permanent,
+    /// A log query matched more logs than the endpoint returns at once, only a narrower block
+    /// range can succeed
+    TooManyResults,
+}
+
+impl ErrorClass {
+    /// Whether the same request may succeed later
+    pub fn is_retryable(self) -> bool {
+        matches!(self, Self::RateLimited | Self::Transient)
+    }
+}
+
+/// Classifies an RPC error so that the retry loop knows whether to try again.
+pub fn classify_error(error: &TransportError) -> ErrorClass {
+    if is_result_limit(error) {
+        return ErrorClass::TooManyResults;
+    }
+    if RateLimitRetryPolicy::default().should_retry(error) {
+        return ErrorClass::RateLimited;
+    }
+
+    match error {
+        RpcError::Transport(kind) => match kind {
+            TransportErrorKind::BackendGone | TransportErrorKind::PubsubUnavailable => {
+                ErrorClass::Transient
+            }
+            TransportErrorKind::HttpError(err) if err.status >= 500 => ErrorClass::Transient,
+            TransportErrorKind::MissingBatchResponse(_) => ErrorClass::Transient,
+            TransportErrorKind::Custom(err) if is_connection_error(err.as_ref()) => {
+                ErrorClass::Transient
+            }
+            _ => ErrorClass::Permanent,
+        },
+        RpcError::ErrorResp(payload) => match payload.code {
+            // Internal error, resource unavailable and limit exceeded are server side conditions
+            -32603 | -32002 | -32005 => ErrorClass::Transient,
+            _ => ErrorClass::Permanent,
+        },
+        RpcError::NullResp => ErrorClass::Transient,
+        _ => ErrorClass::Permanent,
+    }
+}
+
+/// Whether an error response refuses a log query for its result size. Providers share the code of
+/// this limit with their rate limits (-32005) or invalid params (-32602), only the message tells it
+/// apart.
+fn is_result_limit(error: &TransportError) -> bool {
+    let RpcError::ErrorResp(payload) = error else {
+        return false;
+    };
+    let message = payload.message.to_lowercase();
+    matches!(payload.code, -32005 | -32602)
+        && (message.contains("more than 10000 results")
+            || message.contains("query returned more than")
+            || message.contains("response size exceeded"))
+}
+
+/// Whether a custom transport error comes from a timeout or a failed connection, found by
+/// following its sources down to the io error of the socket
+fn is_connection_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if error.is::<Elapsed>() {
+            return true;
+        }
+        if let Some(error) = error.downcast_ref::<std::io::Error>() {
+            return matches!(
+                error.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::NotConnected
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+        source = error.source();
+    }
+
+    false
+}
+
+/// The compute unit cost of a method, following the weights most hosted providers publish.
+pub fn compute_units(method: &str) -> u64 {
+    match method {
+        "eth_chainId" => 0,
+        "eth_blockNumber" | "eth_subscribe" => 10,
+        "eth_getTransactionReceipt" => 15,
+        "eth_getBlockByNumber" => 16,
+        "eth_getStorageAt" => 17,
+        "eth_call" => 26,
+        "eth_getLogs" => 75,
+        "trace_block" => 24,
+        "debug_traceBlockByNumber" => 309,
+        "eth_getBlockReceipts" => 500,
+        _ => DEFAULT_COMPUTE_UNITS,
+    }
+}
+
+/// A token bucket refilled continuously at `rate` tokens per second.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            state: Mutex::new((rate, Instant::now())),
+        }
+    }
+
+    /// Waits until `cost` tokens are available and takes them.
+    /// A cost larger than the bucket is allowed to drive the balance negative, so that
+    /// expensive calls are still possible but delay the ones that follow.
+    async fn acquire(&self, cost: f64) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.1).as_secs_f64();
+                state.0 = (state.0 + elapsed * self.rate).min(self.rate);
+                state.1 = now;
+
+                if state.0 >= cost.min(self.rate) {
+                    state.0 -= cost;
+                    return;
+                }
+                (cost.min(self.rate) - state.0) / self.rate
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+/// Queries the blocks `from..=to` with `query`, halving every range the endpoint refuses for
+/// [`ErrorClass::TooManyResults`] until each part succeeds. The results are in block order.
+pub async fn query_split_ranges<T, F, Fut>(from: u64, to: u64, query: F) -> TransportResult<Vec<T>>
+where
+    F: Fn(u64, u64) -> Fut,
+    Fut: Future<Output = TransportResult<Vec<T>>>,
+{
+    let mut results = Vec::new();
+    // The lower half is pushed last, so that it is queried first
+    let mut pending = vec![(from, to)];
+    while let Some((from, to)) = pending.pop() {
+        match query(from, to).await {
+            Ok(part) => results.extend(part),
+            Err(error) if from < to && classify_error(&error) == ErrorClass::TooManyResults => {
+                let mid = from + (to - from) / 2;
+                warn!("too many logs in blocks {from}..={to}, splitting the range at {mid}");
+                pending.push((mid + 1, to));
+                pending.push((from, mid));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(results)
+}
+
+/// The range of at most `max_range` blocks starting at `next`, `None` while `next` is past `head`
+pub fn next_block_range(next: u64, head: u64, max_range: u64) -> Option<(u64, u64)> {
+    (next <= head).then(|| (next, head.min(next.saturating_add(max_range.max(1) - 1))))
+}
+
+/// Consecutive block ranges, up to the chain head and then up to every new head.
+/// A range always starts where the previous one ended, the new headers only tell how far to go,
+/// so headers skipped by a subscription falling behind or missed while it reconnects leave no
+/// gap. The caller is expected to store the end of a range as its progress, along with what it
+/// indexed, before asking for the next one.
+/// Ranges stay `confirmations` blocks behind the head, so that they only cover blocks deep enough
+/// not to be reorged.
+pub struct BlockRanges {
+    heads: BoxStream<'static, TransportResult<AnyRpcHeader>>,
+    next: u64,
+    /// The last block a range may cover
+    head: u64,
+    max_range: u64,
+    confirmations: u64,
+}
+
+impl BlockRanges {
+    /// The next range, waiting for a new head once caught up
+    pub async fn next_range(&mut self) -> TransportResult<(u64, u64)> {
+        loop {
+            if let Some((from, to)) = next_block_range(self.next, self.head, self.max_range) {
+                self.next = to + 1;
+                return Ok((from, to));
+            }
+            match self.heads.next().await {
+                Some(header) => {
+                    let confirmed = header?.number.saturating_sub(self.confirmations);
+                    self.head = self.head.max(confirmed);
+                }
+                None => {
+                    return Err(TransportErrorKind::custom_str(
+                        "the block subscription ended",
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Per endpoint request and compute unit budget.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    requests: Option<TokenBucket>,
+    compute_units: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RpcConfig) -> Self {
+        Self {
+            requests: config
+                .requests_per_second
+                .map(|rps| TokenBucket::new(rps.get() as f64)),
+            compute_units: config
+                .compute_units_per_second
+                .map(|cups| TokenBucket::new(cups.get() as f64)),
+        }
+    }
+
+    /// Waits until the budget allows one more call of `method`.
+    pub async fn acquire(&self, method: &str) {
+        if let Some(requests) = &self.requests {
+            requests.acquire(1.0).await;
+        }
+        if let Some(bucket) = &self.compute_units {
+            bucket.acquire(compute_units(method) as f64).await;
+        }
+    }
+}
+
+/// Runs `call` until it succeeds, or until it fails with an error whose class `retry` does not
+/// accept or `max_retries` times in a row, waiting between attempts for the backoff hint of the
+/// endpoint or a jittered exponential backoff.
+async fn retrying<T, F, Fut>(
+    config: &RpcConfig,
+    what: &str,
+    retry: fn(ErrorClass) -> bool,
+    mut call: F,
+) -> TransportResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = TransportResult<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        let error = match call().await {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+
+        let class = classify_error(&error);
+        if !retry(class) || attempt >= config.max_retries {
+            return Err(error);
+        }
+
+        let delay = RateLimitRetryPolicy::default()
+            .backoff_hint(&error)
+            .unwrap_or_else(|| backoff(config, attempt));
+        warn!(
+            "{what} failed ({class:?}), retrying in {delay:?} ({}/{}): {error}",
+            attempt + 1,
+            config.max_retries
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Exponential backoff with equal jitter: half of the delay is fixed, the other half random.
+fn backoff(config: &RpcConfig, attempt: u32) -> Duration {
+    let exp = config
+        .initial_backoff_ms
+        .saturating_mul(1u64 << attempt.min(32))
+        .min(config.max_backoff_ms);
+    let half = exp / 2;
+    Duration::from_millis(half + rand::rng().random_range(0..=half))
+}
+
+/// Provider wrapper used for every RPC call of the monitors.
+/// It speaks [`AnyNetwork`], so that the transaction types and receipt fields of the rollups
+/// survive deserialization instead of failing as unknown Ethereum transactions.
+/// Calls wait for the endpoint budget and transient failures are retried with a jittered
+/// exponential backoff, permanent failures are returned straight away.
+#[derive(Debug, Clone)]
+pub struct RpcProvider {
+    provider: RootProvider<AnyNetwork>,
+    limiter: Arc<RateLimiter>,
+    config: RpcConfig,
+    chain_id: u64,
+}
+
+impl RpcProvider {
+    pub fn new(provider: RootProvider<AnyNetwork>, config: RpcConfig) -> Self {
+        Self {
+            provider,
+            limiter: Arc::new(RateLimiter::new(&config)),
+            config,
+            chain_id: 0,
+        }
+    }
+
+    /// Connects to the websocket endpoint at `url`. The handshake is retried with the backoff of
+    /// the calls whatever the error, the endpoint may be restarting or briefly unreachable.
+    pub async fn connect_ws(url: &str, config: RpcConfig) -> TransportResult<Self> {
+        let provider = retrying(
+            &config,
+            "websocket handshake",
+            |_| true,
+            || ProviderBuilder::new_with_network::<AnyNetwork>().connect_ws(WsConnect::new(url)),
+        )
+        .await?;
+
+        Ok(Self::new(provider.root().clone(), config))
+    }
+
+    /// Sets the chain id of the endpoint, recorded on every row the monitors store
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// The chain id of the endpoint, 0 until set with [`RpcProvider::with_chain_id`]
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// The underlying provider, calls made on it bypass the budget and the retries
+    pub fn root(&self) -> &RootProvider<AnyNetwork> {
+        &self.provider
+    }
+
+    /// Runs `call` against the provider, respecting the budget of the endpoint and retrying
+    /// rate limited and transient errors.
+    pub async fn request<T, F, Fut>(&self, method: &'static str, call: F) -> TransportResult<T>
+    where
+        F: Fn(RootProvider<AnyNetwork>) -> Fut,
+        Fut: Future<Output = TransportResult<T>>,
+    {
+        retrying(&self.config, method, ErrorClass::is_retryable, || async {
+            self.limiter.acquire(method).await;
+            call(self.provider.clone()).await
+        })
+        .await
+    }
+
+    pub async fn get_logs(&self, filter: &Filter) -> TransportResult<Vec<Log>> {
+        self.request("eth_getLogs", |provider| async move {
+            provider.get_logs(filter).await
+        })
+        .await
+    }
+
+    /// The logs of `filter` in the blocks `from..=to`, split in narrower ranges when the endpoint
+    /// refuses to return that many logs at once
+    pub async fn get_logs_split(
+        &self,
+        filter: &Filter,
+        from: u64,
+        to: u64,
+    ) -> TransportResult<Vec<Log>> {
+        query_split_ranges(from, to, |from, to| {
+            let filter = filter.clone().from_block(from).to_block(to);
+            async move { self.get_logs(&filter).await }
+        })
+        .await
+    }
+
+    pub async fn get_chain_id(&self) -> TransportResult<u64> {
+        self.request("eth_chainId", |provider| async move {
+            provider.get_chain_id().await
+        })
+        .await
+    }
+
+    pub async fn get_block_number(&self) -> TransportResult<u64> {
+        self.request("eth_blockNumber", |provider| async move {
+            provider.get_block_number().await
+        })
+        .await
+    }
+
+    /// Fetches a block with the hashes of its transactions only
+    pub async fn get_block(
+        &self,
+        number: BlockNumberOrTag,
+    ) -> TransportResult<Option<AnyRpcBlock>> {
+        self.request("eth_getBlockByNumber", |provider| async move {
+            provider.get_block_by_number(number).await
+        })
+        .await
+    }
+
+    /// Fetches a block together with its full transactions
+    pub async fn get_full_block(
+        &self,
+        number: BlockNumberOrTag,
+    ) -> TransportResult<Option<AnyRpcBlock>> {
+        self.request("eth_getBlockByNumber", |provider| async move {
+            provider.get_block_by_number(number).full().await
+        })
+        .await
+    }
+
+    /// Executes a call against the state of `block_number` without sending a transaction
+    pub async fn call(
+        &self,
+        to: Address,
+        input: Bytes,
+        block_number: u64,
+    ) -> TransportResult<Bytes> {
+        self.request("eth_call", |provider| {
+            let request = TransactionRequest::default()
+                .to(to)
+                .input(input.clone().into())
+                .into();
+            async move { provider.call(request).block(block_number.into()).await }
+        })
+        .await
+    }
+
+    /// Reads a storage slot of `address` at `block_number`
+    pub async fn get_storage_at(
+        &self,
+        address: Address,
+        slot: B256,
+        block_number: u64,
+    ) -> TransportResult<U256> {
+        self.request("eth_getStorageAt", |provider| async move {
+            provider
+                .get_storage_at(address, U256::from_be_bytes(slot.0))
+                .number(block_number)
+                .await
+        })
+        .await
+    }
+
+    pub async fn get_transaction_by_hash(
+        &self,
+        hash: TxHash,
+    ) -> TransportResult<Option<AnyRpcTransaction>> {
+        self.request("eth_getTransactionByHash", |provider| async move {
+            provider.get_transaction_by_hash(hash).await
+        })
+        .await
+    }
+
+    pub async fn get_transaction_receipt(
+        &self,
+        hash: TxHash,
+    ) -> TransportResult<Option<AnyTransactionReceipt>> {
+        self.request("eth_getTransactionReceipt", |provider| async move {
+            provider.get_transaction_receipt(hash).await
+        })
+        .await
+    }
+
+    /// Traces every transaction of a block with the geth `callTracer`
+    pub async fn debug_trace_block(&self, number: u64) -> TransportResult<Vec<TraceResult>> {
+        self.request("debug_traceBlockByNumber", |provider| async move {
+            let options = GethDebugTracingOptions::call_tracer(CallConfig::default());
+            provider
+                .debug_trace_block_by_number(number.into(), options)
+                .await
+        })
+        .await
+    }
+
+    /// The parity style traces of every transaction of a block
+    pub async fn trace_block(
+        &self,
+        number: u64,
+    ) -> TransportResult<Vec<LocalizedTransactionTrace>> {
+        self.request("trace_block", |provider| async move {
+            provider.trace_block(number.into()).await
+        })
+        .await
+    }
+
+    /// The live logs matching `filter`, see [`RpcProvider::resubscribing`]
+    pub async fn subscribe_logs(
+        &self,
+        filter: &Filter,
+    ) -> TransportResult<BoxStream<'static, TransportResult<Log>>> {
+        let gap_filter = filter.clone();
+        let recovery = Recovery {
+            block_number: |log: &Log| log.block_number,
+            fill: Arc::new(move |provider: RpcProvider, from: u64| {
+                let filter = gap_filter.clone();
+                async move {
+                    let head = provider.get_block_number().await?;
+                    if from > head {
+                        return Ok((Vec::new(), head));
+                    }
+                    let logs = provider
+                        .get_logs(&filter.from_block(from).to_block(head))
+                        .await?;
+                    Ok((logs, head))
+                }
+                .boxed()
+            }),
+        };
+        let filter = filter.clone();
+        self.resubscribing(true, Some(recovery), move |provider, size| {
+            let filter = filter.clone();
+            async move { provider.subscribe_logs(&filter).channel_size(size).await }
+        })
+        .await
+    }
+
+    /// The transactions entering the mempool of the node, in full so that none has to be fetched.
+    /// The mempool can outpace any consumer, the transactions the subscription falls behind on
+    /// are skipped.
+    pub async fn subscribe_full_pending_transactions(
+        &self,
+    ) -> TransportResult<BoxStream<'static, TransportResult<AnyRpcTransaction>>> {
+        self.resubscribing(false, None, |provider, size| async move {
+            provider
+                .subscribe_full_pending_transactions()
+                .channel_size(size)
+                .await
+        })
+        .await
+    }
+
+    /// The headers of the new blocks, see [`RpcProvider::resubscribing`]
+    pub async fn subscribe_blocks(
+        &self,
+    ) -> TransportResult<BoxStream<'static, TransportResult<AnyRpcHeader>>> {
+        let recovery = Recovery {
+            block_number: |header: &AnyRpcHeader| Some(header.number),
+            fill: Arc::new(|provider: RpcProvider, from: u64| {
+                async move {
+                    let head = provider.get_block_number().await?;
+                    let mut headers = Vec::new();
+                    for number in from..=head {
+                        if let Some(block) = provider.get_block(number.into()).await? {
+                            headers.push(block.header.clone());
+                        }
+                    }
+                    Ok((headers, head))
+                }
+                .boxed()
+            }),
+        };
+        self.resubscribing(true, Some(recovery), |provider, size| async move {
+            provider.subscribe_blocks().channel_size(size).await
+        })
+        .await
+    }
+
+    /// The block ranges from `from_block` on, of at most `max_range` blocks, ending
+    /// `confirmations` blocks behind the chain head, see [`BlockRanges`]
+    pub async fn block_ranges(
+        &self,
+        from_block: u64,
+        max_range: u64,
+        confirmations: u64,
+    ) -> TransportResult<BlockRanges> {
+        // Only the latest header matters, a subscription falling behind skips the others
+        let heads = self
+            .resubscribing(false, None, |provider, size| async move {
+                provider.subscribe_blocks().channel_size(size).await
+            })
+            .await?;
+
+        Ok(BlockRanges {
+            heads,
+            next: from_block,
+            head: self.get_block_number().await?.saturating_sub(confirmations),
+            max_range,
+            confirmations,
+        })
+    }
+
+    /// Opens a subscription with `subscribe` before returning, and opens it again whenever it ends,
+    /// e.g. when the node drops the connection. Notifications are buffered up to
+    /// `subscription_channel_size`, when `strict` a subscription that falls further behind fails
+    /// the stream rather than skipping them.
+    /// With a `recovery`, what was emitted while the subscription was down is read back from the
+    /// block after the last one seen up to the head, and the notifications of the new subscription
+    /// up to that head are skipped. Without one, the gap fails a `strict` stream.
+    async fn resubscribing<T, F, Fut>(
+        &self,
+        strict: bool,
+        recovery: Option<Recovery<T>>,
+        subscribe: F,
+    ) -> TransportResult<BoxStream<'static, TransportResult<T>>>
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(RootProvider<AnyNetwork>, usize) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = TransportResult<Subscription<T>>> + Send,
+    {
+        let size = self.config.subscription_channel_size.max(1);
+        let first = self
+            .request("eth_subscribe", |root| subscribe(root, size))
+            .await?;
+        // Everything up to the head is left to the caller, which subscribes before backfilling
+        let last = match recovery {
+            Some(_) => Some(self.get_block_number().await?),
+            None => None,
+        };
+        let state = ResubscribeState {
+            open: Some(first),
+            failed: false,
+            last,
+            missed: VecDeque::new(),
+            filled_to: None,
+        };
+        let provider = self.clone();
+        Ok(stream::unfold(state, move |mut state| {
+            let provider = provider.clone();
+            let subscribe = subscribe.clone();
+            let recovery = recovery.clone();
+            async move {
+                if state.failed {
+                    return None;
+                }
+                loop {
+                    if let Some(item) = state.missed.pop_front() {
+                        return Some((Ok(item), state));
+                    }
+                    match state.open.as_mut() {
+                        Some(subscription) => match subscription.recv().await {
+                            Ok(item) => {
+                                if let Some(recovery) = &recovery {
+                                    let number = (recovery.block_number)(&item);
+                                    if number.zip(state.filled_to).is_some_and(|(n, to)| n <= to) {
+                                        continue;
+                                    }
+                                    state.last = state.last.max(number);
+                                }
+                                return Some((Ok(item), state));
+                            }
+                            Err(RecvError::Lagged(_)) if !strict => {}
+                            Err(RecvError::Lagged(skipped)) => {
+                                let err = TransportErrorKind::custom_str(&format!(
+                                    "the subscription fell behind, {skipped} notifications were skipped"
+                                ));
+                                state.failed = true;
+                                return Some((Err(err), state));
+                            }
+                            Err(RecvError::Closed) if strict && recovery.is_none() => {
+                                let err = TransportErrorKind::custom_str(
+                                    "the subscription ended, the notifications since are lost",
+                                );
+                                state.failed = true;
+                                return Some((Err(err), state));
+                            }
+                            Err(RecvError::Closed) => {
+                                warn!("The subscription ended, subscribing again");
+                                state.open = None;
+                            }
+                        },
+                        None => {
+                            match provider
+                                .request("eth_subscribe", |root| subscribe(root, size))
+                                .await
+                            {
+                                Ok(subscription) => state.open = Some(subscription),
+                                Err(err) => {
+                                    state.failed = true;
+                                    return Some((Err(err), state));
+                                }
+                            }
+                            let (Some(recovery), Some(last)) = (&recovery, state.last) else {
+                                continue;
+                            };
+                            match (recovery.fill)(provider.clone(), last + 1).await {
+                                Ok((missed, head)) => {
+                                    warn!(
+                                        "Read back {} notifications missed up to block {head}",
+                                        missed.len()
+                                    );
+                                    state.missed.extend(missed);
+                                    state.last = state.last.max(Some(head));
+                                    state.filled_to = Some(head);
+                                }
+                                Err(err) => {
+                                    state.failed = true;
+                                    return Some((Err(err), state));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+}
+
+/// Reads back the notifications of a subscription from a block up to the head, returned with them
+type GapFill<T> = Arc<
+    dyn Fn(RpcProvider, u64) -> BoxFuture<'static, TransportResult<(Vec<T>, u64)>> + Send + Sync,
+>;
+
+/// How [`RpcProvider::resubscribing`] recovers the notifications missed while reconnecting
+struct Recovery<T> {
+    /// The block a notification belongs to
+    block_number: fn(&T) -> Option<u64>,
+    fill: GapFill<T>,
+}
+
+impl<T> Clone for Recovery<T> {
+    fn clone(&self) -> Self {
+        Self {
+            block_number: self.block_number,
+            fill: self.fill.clone(),
+        }
+    }
+}
+
+struct ResubscribeState<T> {
+    open: Option<Subscription<T>>,
+    failed: bool,
+    /// The last block notified, or read back
+    last: Option<u64>,
+    /// Notifications read back after a reconnection, handed out first
+    missed: VecDeque<T>,
+    /// The head the last read back went up to
+    filled_to: Option<u64>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    fn error_response(payload: &str) -> TransportError {
+        TransportError::ErrorResp(serde_json::from_str(payload).unwrap())
+    }
+
+    #[test]
+    pub fn test_classify_error() {
+        assert_eq!(
+            classify_error(&error_response(
+                r#"{"code":429,"message":"Too Many Requests"}"#
+            )),
+            ErrorClass::RateLimited
+        );
+        assert_eq!(
+            classify_error(&error_response(
+                r#"{"code":-32602,"message":"invalid params"}"#
+            )),
+            ErrorClass::Permanent
+        );
+
+        assert_eq!(
+            classify_error(&TransportErrorKind::backend_gone()),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify_error(&TransportErrorKind::custom(std::io::Error::from(
+                ErrorKind::ConnectionReset
+            ))),
+            ErrorClass::Transient
+        );
+        // Only the kind of the error counts, not its message
+        assert_eq!(
+            classify_error(&TransportErrorKind::custom_str("connection refused")),
+            ErrorClass::Permanent
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_query_split_ranges() {
+        let too_many = r#"{"code":-32005,"message":"query returned more than 10000 results"}"#;
+        assert_eq!(
+            classify_error(&error_response(too_many)),
+            ErrorClass::TooManyResults
+        );
+
+        // The endpoint refuses any range of more than 3 blocks
+        let queried = std::sync::Mutex::new(Vec::new());
+        let blocks = query_split_ranges(0, 9, |from, to| {
+            queried.lock().unwrap().push((from, to));
+            async move {
+                if to - from >= 3 {
+                    return Err(error_response(too_many));
+                }
+                Ok((from..=to).collect::<Vec<u64>>())
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(blocks, (0..=9).collect::<Vec<u64>>());
+        assert_eq!(
+            *queried.lock().unwrap(),
+            [(0, 9), (0, 4), (0, 2), (3, 4), (5, 9), (5, 7), (8, 9)]
+        );
+
+        // A single block is not split any further
+        let error = query_split_ranges(5, 5, |_, _| async {
+            Err::<Vec<u64>, _>(error_response(too_many))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(classify_error(&error), ErrorClass::TooManyResults);
+    }
+
+    #[tokio::test]
+    pub async fn test_rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(&RpcConfig {
+            requests_per_second: NonZeroU32::new(10),
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        for _ in 0..15 {
+            limiter.acquire("eth_blockNumber").await;
+        }
+        // The first 10 requests use the initial burst, the next 5 need half a second
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[test]
+    pub fn test_next_block_range() {
+        assert_eq!(next_block_range(100, 1000, 500), Some((100, 599)));
+        assert_eq!(next_block_range(600, 1000, 500), Some((600, 1000)));
+        assert_eq!(next_block_range(1000, 1000, 500), Some((1000, 1000)));
+        // Caught up, or the head went back with a reorg
+        assert_eq!(next_block_range(1001, 1000, 500), None);
+        assert_eq!(next_block_range(7, 9, 0), Some((7, 7)));
+        assert_eq!(next_block_range(7, u64::MAX, u64::MAX), Some((7, u64::MAX)));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::provider::RpcProvider;
+use alloy::primitives::{Address, B256, U256, keccak256};
+use futures::StreamExt;
+use primitives::{
+    SlotConfig, StorageConfig,
+    db::{
+        create_progress_db_table, create_slot_changes_db_table, get_latest_slot_values,
+        get_progress, set_progress, store_slot_change_to_db,
+    },
+    monitor::SlotChange,
+};
+use std::collections::HashMap;
+use tracing::info;
+
+/// Parses a slot or a mapping key, addresses are left padded, numbers are decimal or hex
+fn parse_word(value: &str) -> Result<B256, anyhow::Error> {
+    let value = value.trim();
+    if value.starts_with("0x") && value.len() == 42 {
+        return Ok(value.parse::<Address>()?.into_word());
+    }
+
+    Ok(B256::from(value.parse::<U256>()?))
+}
+
+/// The slot of a mapping entry, `keccak256(key . slot)` applied once per key, outermost first.
+/// Only value type keys are supported, string and bytes keys are hashed differently.
+pub fn mapping_slot(slot: B256, keys: &[B256]) -> B256 {
+    keys.iter().fold(slot, |slot, key| {
+        keccak256([key.as_slice(), slot.as_slice()].concat())
+    })
+}
+
+/// The storage slot watched for a slot config
+pub fn resolve_slot(config: &SlotConfig) -> Result<B256, anyhow::Error> {
+    let keys = config
+        .keys
+        .iter()
+        .map(|key| parse_word(key))
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    Ok(mapping_slot(parse_word(&config.slot)?, &keys))
+}
+
+/// Watches storage slots of a contract with `eth_getStorageAt`, storing a row only when the
+/// value differs from the one read at the previous polled block
+pub struct StorageMonitorTable {
+    name: String,
+    interval: u64,
+    slots: Vec<(String, B256)>,
+}
+
+impl StorageMonitorTable {
+    pub fn new(name: String, config: &StorageConfig) -> Result<Self, anyhow::Error> {
+        let slots = config
+            .slots
+            .iter()
+            .map(|slot| Ok((slot.name.clone(), resolve_slot(slot)?)))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        Ok(Self {
+            name,
+            interval: config.interval.max(1),
+            slots,
+        })
+    }
+
+    /// Reads every slot at a block and stores those that changed, `latest` holds the last values.
+    /// The changes of a block are stored in one db transaction, which also records the block as the
+    /// progress of the monitor, so that a restart resumes after the last polled block without
+    /// missing the other slots of that block.
+    async fn poll(
+        &self,
+        provider: &RpcProvider,
+        address: Address,
+        block_number: u64,
+        latest: &mut HashMap<B256, B256>,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let mut changes = Vec::new();
+        for (slot_name, slot) in self.slots.iter() {
+            let value = B256::from(
+                provider
+                    .get_storage_at(address, *slot, block_number)
+                    .await?,
+            );
+            let previous = latest.get(slot).copied();
+            if previous == Some(value) {
+                continue;
+            }
+
+            changes.push(SlotChange {
+                slot_name: slot_name.clone(),
+                slot: *slot,
+                block_number,
+                value,
+                previous,
+            });
+        }
+        let db_tx = db_client.transaction().await?;
+        for change in changes.iter() {
+            store_slot_change_to_db(&db_tx, &self.name, change).await?;
+        }
+        set_progress(&db_tx, &self.name, block_number).await?;
+        db_tx.commit().await?;
+        for change in changes {
+            latest.insert(change.slot, change.value);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the slots from `from_block` up to the chain head, which needs an archive node for old
+    /// blocks, then at every new block. A previous run is resumed after its last polled block.
+    pub async fn query_and_subscribe_slots(
+        &self,
+        provider: RpcProvider,
+        address: Address,
+        from_block: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_slot_changes_db_table(db_client, &self.name).await?;
+        create_progress_db_table(db_client, &self.name).await?;
+
+        let progress = get_progress(db_client, &self.name).await?;
+        let stored = get_latest_slot_values(db_client, &self.name).await?;
+        let mut latest: HashMap<B256, B256> = stored
+            .into_iter()
+            .map(|(slot, value, _)| (slot, value))
+            .collect();
+
+        // Subscribe before reading the head so that no block falls between backfill and live blocks
+        let mut stream = provider.subscribe_blocks().await?;
+        let head = provider.get_block_number().await?;
+        let from_block = match progress {
+            Some(last) => last + 1,
+            None => from_block,
+        };
+        let first = from_block.div_ceil(self.interval) * self.interval;
+        for block_number in (first..=head).step_by(self.interval as usize) {
+            self.poll(&provider, address, block_number, &mut latest, db_client)
+                .await?;
+        }
+        info!("{}: read the slots up to block {head}", self.name);
+        let polled = progress.map_or(head, |last| last.max(head));
+
+        while let Some(header) = stream.next().await {
+            let header = header?;
+            if header.number > polled && header.number % self.interval == 0 {
+                self.poll(&provider, address, header.number, &mut latest, db_client)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::b256;
+
+    #[test]
+    pub fn test_mapping_slot() {
+        // balances[address(0)] of a mapping at slot 0 is keccak256 of 64 zero bytes
+        let config = SlotConfig {
+            name: "zero_balance".to_string(),
+            slot: "0".to_string(),
+            keys: vec!["0x0000000000000000000000000000000000000000".to_string()],
+        };
+        assert_eq!(
+            resolve_slot(&config).unwrap(),
+            b256!("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5")
+        );
+
+        let raw = SlotConfig {
+            name: "owner".to_string(),
+            slot: "0x5".to_string(),
+            keys: vec![],
+        };
+        assert_eq!(resolve_slot(&raw).unwrap(), B256::with_last_byte(5));
+
+        // allowances[owner][spender] hashes the owner first
+        let owner = parse_word("0x00000000000000000000000000000000000000aa").unwrap();
+        let spender = parse_word("187").unwrap();
+        let inner = keccak256([owner.as_slice(), B256::with_last_byte(1).as_slice()].concat());
+        assert_eq!(
+            mapping_slot(B256::with_last_byte(1), &[owner, spender]),
+            keccak256([spender.as_slice(), inner.as_slice()].concat())
+        );
+    }
+}
@@ -1,46 +1,40 @@
+use crate::provider::RpcProvider;
 use alloy::{
-    network::Ethereum,
+    network::{AnyRpcTransaction, TransactionResponse},
     primitives::Address,
-    providers::{Provider, RootProvider},
-    rpc::types::{Block, Transaction, TransactionTrait},
+    rpc::types::TransactionTrait,
 };
 use futures::StreamExt;
 
 pub async fn subscribe_transactions<F>(
     index_address: Address,
-    provider: RootProvider<Ethereum>,
+    provider: &RpcProvider,
     mut callback: F,
 ) -> Result<(), anyhow::Error>
 where
-    F: FnMut(Vec<Transaction>),
+    F: FnMut(Vec<AnyRpcTransaction>),
 {
     // Subscribe to new blocks
-    let subscription = provider.subscribe_blocks().await?;
-    let mut stream = subscription.into_stream();
+    let mut stream = provider.subscribe_blocks().await?;
 
     while let Some(header) = stream.next().await {
+        let header = header?;
         // Fetch the full block with transactions
-        let full_block = provider
-            .get_block_by_number(header.inner.number.into())
-            .await?;
-
-        if let Some(Block {
-            transactions: alloy::rpc::types::BlockTransactions::Full(txs),
-            ..
-        }) = full_block
-        {
-            let filtered_txs = txs
-                .into_iter()
+        let full_block = provider.get_full_block(header.inner.number.into()).await?;
+
+        if let Some(block) = full_block {
+            let filtered_txs = block
+                .into_transactions_iter()
                 .filter(|tx| {
                     println!("Captured Tx: {:?}", tx.block_hash);
 
                     // Get the 'to' address, defaulting to zero address if None
-                    let to_address = tx.inner.to().unwrap_or(Address::ZERO);
+                    let to_address = tx.to().unwrap_or(Address::ZERO);
 
                     // Filter transactions involving the target address
-                    tx.inner.signer() == index_address || to_address == index_address
+                    tx.from() == index_address || to_address == index_address
                 })
-                .collect::<Vec<Transaction>>();
+                .collect::<Vec<AnyRpcTransaction>>();
 
             if !filtered_txs.is_empty() {
                 callback(filtered_txs);
@@ -51,12 +45,37 @@ where
     Ok(())
 }
 
+/// Fetches the transactions of a block, empty when the block is not known yet
+pub async fn get_block_transactions(
+    provider: &RpcProvider,
+    block_number: u64,
+) -> Result<Vec<AnyRpcTransaction>, anyhow::Error> {
+    Ok(provider
+        .get_full_block(block_number.into())
+        .await?
+        .map(|block| block.into_transactions_iter().collect())
+        .unwrap_or_default())
+}
+
+/// A transaction moving a non zero amount of ETH from or to one of the watched addresses
+pub fn is_value_transfer(tx: &AnyRpcTransaction, watched: &[Address]) -> bool {
+    !tx.value().is_zero()
+        && (watched.contains(&tx.from()) || tx.to().is_some_and(|to| watched.contains(&to)))
+}
+
+/// A contract creation, sent by one of the `deployers` unless the list is empty
+pub fn is_deployment(tx: &AnyRpcTransaction, deployers: &[Address]) -> bool {
+    tx.to().is_none() && (deployers.is_empty() || deployers.contains(&tx.from()))
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use alloy::network::AnyNetwork;
     use alloy::primitives::address;
     use alloy::providers::{Provider, ProviderBuilder};
     use alloy::rpc::client::WsConnect;
+    use primitives::RpcConfig;
 
     #[tokio::test]
     #[ignore]
@@ -65,15 +84,19 @@ pub mod tests {
 
         // Create WebSocket provider
         let ws = WsConnect::new(rpc_url);
-        let provider = ProviderBuilder::new().connect_ws(ws).await.unwrap();
+        let provider = ProviderBuilder::new_with_network::<AnyNetwork>()
+            .connect_ws(ws)
+            .await
+            .unwrap();
+        let provider = RpcProvider::new(provider.root().clone(), RpcConfig::default());
 
         let usdc_token_address = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
 
-        let callback = |tx: Vec<Transaction>| {
+        let callback = |tx: Vec<AnyRpcTransaction>| {
             println!("Received Tx: {:?}", tx);
         };
 
-        subscribe_transactions(usdc_token_address, provider.root().clone(), callback)
+        subscribe_transactions(usdc_token_address, &provider, callback)
             .await
             .unwrap();
     }
@@ -0,0 +1,198 @@
+use super::rollup::enrich_l2_fields;
+use crate::provider::RpcProvider;
+use alloy::{network::AnyRpcTransaction, primitives::Address};
+use futures::StreamExt;
+use primitives::{
+    Rollup,
+    db::{
+        create_new_tx_db_table, create_pending_tx_db_table, enqueue_transactions_to_outbox,
+        mark_pending_tx_mined, store_pending_tx_to_db, store_tx_to_db,
+    },
+    monitor::{PendingStatus, Tx},
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::select;
+use tracing::info;
+
+/// A transaction to its own sender without value nor data, the usual way to cancel a pending one
+pub fn is_cancellation(tx: &Tx) -> bool {
+    tx.to == Some(tx.from) && tx.value.is_zero() && tx.data.is_empty()
+}
+
+/// A transaction sent by or to one of the watched addresses
+fn is_watched(tx: &Tx, watched: &[Address]) -> bool {
+    watched.contains(&tx.from) || tx.to.is_some_and(|to| watched.contains(&to))
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Follows the transactions of the watched addresses from the mempool to a block.
+/// Pending transactions are recorded in `{name}_pending` the first time the node announces them,
+/// and the mined ones in the transaction table `name`. The inclusion latency is the time between
+/// the announcements of the transaction and of its block, both read from the local clock.
+pub struct MempoolMonitorTable {
+    name: String,
+    rollup: Option<Rollup>,
+    outbox: bool,
+}
+
+impl MempoolMonitorTable {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            rollup: None,
+            outbox: false,
+        }
+    }
+
+    /// Stores the L1 fields of the rollup stack of the chain with the mined transactions
+    pub fn with_rollup(mut self, rollup: Option<Rollup>) -> Self {
+        self.rollup = rollup;
+        self
+    }
+
+    /// Queues the mined transactions of every block in the outbox of the monitor, for its sinks
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// Records a transaction announced by the mempool, if it belongs to a watched address
+    async fn handle_pending(
+        &self,
+        provider: &RpcProvider,
+        watched: &[Address],
+        tx: AnyRpcTransaction,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let first_seen_at = now_ms();
+        let tx = Tx::from(tx).with_chain_id(provider.chain_id());
+        if !is_watched(&tx, watched) {
+            return Ok(());
+        }
+
+        let replacement = if is_cancellation(&tx) {
+            PendingStatus::Cancelled
+        } else {
+            PendingStatus::Replaced
+        };
+        let db_tx = db_client.transaction().await?;
+        let stored =
+            store_pending_tx_to_db(&db_tx, &self.name, &tx, first_seen_at, replacement).await?;
+        db_tx.commit().await?;
+        if stored {
+            info!(
+                "{}: {} pending from {} with nonce {}",
+                self.name, tx.hash, tx.from, tx.nonce
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Stores the watched transactions of a new block and links them to their pending entries.
+    /// `included_at` is when the node announced the block, on the clock `first_seen_at` is read
+    /// from.
+    async fn handle_block(
+        &self,
+        provider: &RpcProvider,
+        watched: &[Address],
+        block_number: u64,
+        included_at: i64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let Some(block) = provider.get_full_block(block_number.into()).await? else {
+            return Ok(());
+        };
+        let block_txs: Vec<_> = block.into_transactions_iter().collect();
+        let mut txs: Vec<Tx> = block_txs
+            .iter()
+            .map(|tx| Tx::from(tx.clone()).with_chain_id(provider.chain_id()))
+            .filter(|tx| is_watched(tx, watched))
+            .collect();
+        if let Some(rollup) = self.rollup {
+            enrich_l2_fields(provider, rollup, &block_txs, &mut txs).await?;
+        }
+
+        let db_tx = db_client.transaction().await?;
+        let mut stored = Vec::with_capacity(txs.len());
+        for tx in txs.iter() {
+            if store_tx_to_db(&db_tx, tx, &self.name).await? {
+                stored.push(tx.clone());
+            }
+            mark_pending_tx_mined(&db_tx, &self.name, tx, included_at).await?;
+        }
+        if self.outbox && !stored.is_empty() {
+            enqueue_transactions_to_outbox(&db_tx, &self.name, &stored).await?;
+        }
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Follows the mempool and the new blocks until one of the subscriptions ends
+    pub async fn subscribe_pending_transactions(
+        &self,
+        provider: RpcProvider,
+        watched: Vec<Address>,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_new_tx_db_table(db_client, &self.name).await?;
+        create_pending_tx_db_table(db_client, &self.name).await?;
+
+        let mut pending_stream = provider.subscribe_full_pending_transactions().await?;
+        let mut block_stream = provider.subscribe_blocks().await?;
+
+        loop {
+            select! {
+                tx = pending_stream.next() => {
+                    let Some(tx) = tx else { break };
+                    self.handle_pending(&provider, &watched, tx?, db_client).await?;
+                }
+                header = block_stream.next() => {
+                    let Some(header) = header else { break };
+                    let included_at = now_ms();
+                    self.handle_block(&provider, &watched, header?.number, included_at, db_client)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{Bytes, U256};
+
+    #[test]
+    pub fn test_is_cancellation() {
+        let sender = Address::repeat_byte(1);
+        let cancel = Tx {
+            from: sender,
+            to: Some(sender),
+            ..Default::default()
+        };
+        assert!(is_cancellation(&cancel));
+
+        let self_transfer = Tx {
+            value: U256::from(1),
+            ..cancel.clone()
+        };
+        assert!(!is_cancellation(&self_transfer));
+
+        let call = Tx {
+            to: Some(Address::repeat_byte(2)),
+            data: Bytes::from_static(&[0xa9, 0x05, 0x9c, 0xbb]),
+            ..cancel
+        };
+        assert!(!is_cancellation(&call));
+    }
+}
@@ -1 +1,245 @@
 pub mod evm;
+pub mod mempool;
+pub mod rollup;
+pub mod trace;
+
+use crate::provider::RpcProvider;
+use alloy::{
+    network::{AnyRpcHeader, TransactionResponse},
+    primitives::Address,
+    transports::TransportResult,
+};
+use async_trait::async_trait;
+use evm::{get_block_transactions, is_deployment, is_value_transfer};
+use futures::{
+    StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+};
+use primitives::{
+    BlocksConfig, Rollup,
+    db::{
+        create_new_tx_db_table, create_progress_db_table, enqueue_transactions_to_outbox,
+        get_progress, set_progress, store_tx_to_db,
+    },
+    monitor::Tx,
+    traits::TransactionMonitor,
+};
+use rollup::{apply_receipt_fields, block_l1_number};
+use tracing::info;
+
+/// Stores a batch of transactions in `db_tx`, queued in the outbox of the monitor when it has
+/// sinks. Transactions that are already stored are not queued again.
+pub async fn store_tx_batch(
+    db_tx: &tokio_postgres::Transaction<'_>,
+    name: &str,
+    txs: &[Tx],
+    outbox: bool,
+) -> Result<(), anyhow::Error> {
+    let mut stored = Vec::with_capacity(txs.len());
+    for tx in txs.iter() {
+        if store_tx_to_db(db_tx, tx, name).await? {
+            stored.push(tx.clone());
+        }
+    }
+    if outbox && !stored.is_empty() {
+        enqueue_transactions_to_outbox(db_tx, name, &stored).await?;
+    }
+
+    Ok(())
+}
+
+/// What a [`TransactionMonitorTable`] keeps from each block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    /// Transactions moving ETH from or to a watched address
+    ValueTransfer,
+    /// Contract creations, by the watched addresses when there are any
+    Deployment,
+}
+
+/// Indexes the transactions of every block the chain head has confirmed, one range of blocks per
+/// db transaction which also records the progress of the monitor
+pub struct TransactionMonitorTable {
+    name: String,
+    kind: TransactionKind,
+    blocks: BlocksConfig,
+    rollup: Option<Rollup>,
+    outbox: bool,
+}
+
+impl TransactionMonitorTable {
+    pub fn new(name: String, kind: TransactionKind) -> Self {
+        Self {
+            name,
+            kind,
+            blocks: BlocksConfig::default(),
+            rollup: None,
+            outbox: false,
+        }
+    }
+
+    /// How many blocks are indexed at once, and how deep they must be
+    pub fn with_blocks(mut self, blocks: BlocksConfig) -> Self {
+        self.blocks = blocks;
+        self
+    }
+
+    /// Stores the L1 fields of the rollup stack of the chain with every transaction
+    pub fn with_rollup(mut self, rollup: Option<Rollup>) -> Self {
+        self.rollup = rollup;
+        self
+    }
+
+    /// Queues the transactions of every indexed block in the outbox of the monitor, for its sinks
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// The transactions of a block this monitor keeps. A kept transaction costs one receipt
+    /// request when it is a deployment, for the created address, or on a rollup, for the L1 fields.
+    async fn block_txs(
+        &self,
+        provider: &RpcProvider,
+        watched: &[Address],
+        block_number: u64,
+    ) -> Result<Vec<Tx>, anyhow::Error> {
+        let txs = get_block_transactions(provider, block_number).await?;
+        let l1_block_number = self.rollup.and_then(|rollup| block_l1_number(rollup, &txs));
+        let chain_id = provider.chain_id();
+        let mut kept = Vec::new();
+        for tx in txs.iter() {
+            let keep = match self.kind {
+                TransactionKind::ValueTransfer => is_value_transfer(tx, watched),
+                TransactionKind::Deployment => is_deployment(tx, watched),
+            };
+            if !keep {
+                continue;
+            }
+            let mut kept_tx = Tx::from(tx.clone()).with_chain_id(chain_id);
+            if self.kind == TransactionKind::Deployment || self.rollup.is_some() {
+                if let Some(receipt) = provider.get_transaction_receipt(tx.tx_hash()).await? {
+                    kept_tx.contract_address = receipt.contract_address;
+                    if let Some(rollup) = self.rollup {
+                        apply_receipt_fields(rollup, &mut kept_tx, &receipt);
+                    }
+                }
+                kept_tx.l1_block_number = kept_tx.l1_block_number.or(l1_block_number);
+            }
+            kept.push(kept_tx);
+        }
+
+        Ok(kept)
+    }
+
+    /// Indexes the blocks `from..=to` in one db transaction, which also records the progress of
+    /// the monitor, so that a restart neither skips nor repeats a block
+    async fn index_range(
+        &self,
+        provider: &RpcProvider,
+        watched: &[Address],
+        from: u64,
+        to: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let blocks: Vec<Vec<Tx>> = stream::iter(from..=to)
+            .map(|block_number| self.block_txs(provider, watched, block_number))
+            .buffered(self.blocks.concurrency.max(1))
+            .try_collect()
+            .await?;
+        let txs: Vec<Tx> = blocks.into_iter().flatten().collect();
+
+        let db_tx = db_client.transaction().await?;
+        store_tx_batch(&db_tx, &self.name, &txs, self.outbox).await?;
+        set_progress(&db_tx, &self.name, to).await?;
+        db_tx.commit().await?;
+        if !txs.is_empty() {
+            info!(
+                "{}: indexed {} transactions of blocks {from}..={to}",
+                self.name,
+                txs.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Hands the kept transactions of every new block of `stream` to `callback`, the blocks up to
+    /// `head` were already backfilled
+    async fn follow_blocks<F>(
+        &self,
+        provider: &RpcProvider,
+        mut stream: BoxStream<'static, TransportResult<AnyRpcHeader>>,
+        watched: &[Address],
+        head: Option<u64>,
+        mut callback: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        F: FnMut(Vec<Tx>) + Send,
+    {
+        while let Some(header) = stream.next().await {
+            let header = header?;
+            if head.is_some_and(|head| header.number <= head) {
+                continue;
+            }
+            let txs = self.block_txs(provider, watched, header.number).await?;
+            if !txs.is_empty() {
+                callback(txs);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Indexes the blocks from `from_block`, or from the block after the stored progress, up to
+    /// the confirmed head one `block_range` at a time, then the blocks of every new head once they
+    /// are confirmed
+    pub async fn query_and_subscribe_transactions(
+        &self,
+        provider: RpcProvider,
+        watched: Vec<Address>,
+        from_block: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_new_tx_db_table(db_client, &self.name).await?;
+        create_progress_db_table(db_client, &self.name).await?;
+
+        let from_block = match get_progress(db_client, &self.name).await? {
+            Some(last) => last + 1,
+            None => from_block,
+        };
+        let mut ranges = provider
+            .block_ranges(
+                from_block,
+                self.blocks.block_range,
+                self.blocks.confirmations,
+            )
+            .await?;
+        loop {
+            let (from, to) = ranges.next_range().await?;
+            self.index_range(&provider, &watched, from, to, db_client)
+                .await?;
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionMonitor for TransactionMonitorTable {
+    type SubProvider = RpcProvider;
+    type TargetAddress = Vec<Address>;
+
+    async fn subscribe_transactions<F>(
+        &self,
+        index_address: Self::TargetAddress,
+        provider: Self::SubProvider,
+        callback: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        F: FnMut(Vec<Tx>) + Send,
+    {
+        let stream = provider.subscribe_blocks().await?;
+
+        self.follow_blocks(&provider, stream, &index_address, None, callback)
+            .await
+    }
+}
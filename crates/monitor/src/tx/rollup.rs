@@ -0,0 +1,152 @@
+use crate::provider::RpcProvider;
+use alloy::{
+    eips::Typed2718,
+    network::{AnyRpcTransaction, AnyTransactionReceipt},
+    primitives::{Address, U256, address},
+    rpc::types::TransactionTrait,
+};
+use primitives::{Rollup, monitor::Tx};
+
+/// The OP Stack predeploy receiving the L1 attributes deposit at the start of every block
+pub const OP_L1_BLOCK: Address = address!("4200000000000000000000000000000000000015");
+
+/// Deposits bridged from L1 on OP Stack chains
+pub const OP_DEPOSIT_TX_TYPE: u8 = 0x7e;
+
+/// The L1 block an OP Stack block is derived from, read from its L1 attributes deposit.
+/// The number is the uint64 at bytes 28..36 of the calldata, both in the ABI encoded Bedrock
+/// `setL1BlockValues` and in the packed calldata used since Ecotone.
+pub fn op_l1_block_number(block_txs: &[AnyRpcTransaction]) -> Option<u64> {
+    let tx = block_txs.first()?;
+    if tx.ty() != OP_DEPOSIT_TX_TYPE || tx.to() != Some(OP_L1_BLOCK) {
+        return None;
+    }
+    let number: [u8; 8] = tx.input().get(28..36)?.try_into().ok()?;
+
+    Some(u64::from_be_bytes(number))
+}
+
+/// The L1 block of every transaction of a block, when the rollup records it in the block itself
+pub fn block_l1_number(rollup: Rollup, block_txs: &[AnyRpcTransaction]) -> Option<u64> {
+    match rollup {
+        Rollup::Optimism => op_l1_block_number(block_txs),
+        Rollup::Arbitrum => None,
+    }
+}
+
+/// Copies the L1 fields a rollup node adds to the receipt of a transaction
+pub fn apply_receipt_fields(rollup: Rollup, tx: &mut Tx, receipt: &AnyTransactionReceipt) {
+    let field = |key: &str| {
+        receipt
+            .other
+            .get_deserialized::<U256>(key)
+            .and_then(Result::ok)
+    };
+
+    match rollup {
+        Rollup::Optimism => {
+            tx.l1_fee = field("l1Fee");
+            tx.l1_gas_used = field("l1GasUsed");
+        }
+        Rollup::Arbitrum => {
+            // `gasUsedForL1` is L2 gas, paid at the same price as the gas of the execution
+            let gas_used_for_l1 = field("gasUsedForL1");
+            tx.l1_gas_used = gas_used_for_l1;
+            tx.l1_fee = gas_used_for_l1.map(|gas| gas * U256::from(receipt.effective_gas_price));
+            tx.l1_block_number = field("l1BlockNumber").map(|number| number.saturating_to());
+        }
+    }
+}
+
+/// Fills the L1 fields of `txs`, kept from a block whose transactions are `block_txs`.
+/// Costs one receipt request per kept transaction.
+pub async fn enrich_l2_fields(
+    provider: &RpcProvider,
+    rollup: Rollup,
+    block_txs: &[AnyRpcTransaction],
+    txs: &mut [Tx],
+) -> Result<(), anyhow::Error> {
+    let l1_block_number = block_l1_number(rollup, block_txs);
+
+    for tx in txs.iter_mut() {
+        if let Some(receipt) = provider.get_transaction_receipt(tx.hash).await? {
+            apply_receipt_fields(rollup, tx, &receipt);
+        }
+        tx.l1_block_number = tx.l1_block_number.or(l1_block_number);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const RECEIPT: &str = r#"{
+        "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+        "transactionIndex": "0x1",
+        "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+        "blockNumber": "0x7",
+        "from": "0x00000000000000000000000000000000000000aa",
+        "to": "0x00000000000000000000000000000000000000bb",
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "effectiveGasPrice": "0x3b9aca00",
+        "contractAddress": null,
+        "logs": [],
+        "logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        "status": "0x1",
+        "type": "0x2",
+        "l1Fee": "0x2386f26fc10000",
+        "l1GasUsed": "0x640",
+        "gasUsedForL1": "0x10",
+        "l1BlockNumber": "0x1312d00"
+    }"#;
+
+    #[test]
+    pub fn test_apply_receipt_fields() {
+        let receipt: AnyTransactionReceipt = serde_json::from_str(RECEIPT).unwrap();
+
+        let mut op = Tx::default();
+        apply_receipt_fields(Rollup::Optimism, &mut op, &receipt);
+        assert_eq!(op.l1_fee, Some(U256::from(10_000_000_000_000_000u64)));
+        assert_eq!(op.l1_gas_used, Some(U256::from(1600)));
+        assert_eq!(op.l1_block_number, None);
+
+        let mut arbitrum = Tx::default();
+        apply_receipt_fields(Rollup::Arbitrum, &mut arbitrum, &receipt);
+        assert_eq!(arbitrum.l1_gas_used, Some(U256::from(16)));
+        assert_eq!(arbitrum.l1_fee, Some(U256::from(16_000_000_000u64)));
+        assert_eq!(arbitrum.l1_block_number, Some(20_000_000));
+    }
+
+    #[test]
+    pub fn test_op_deposit_l1_block_number() {
+        // An Ecotone L1 attributes deposit for L1 block 0x1312d00
+        let deposit: AnyRpcTransaction = serde_json::from_str(
+            r#"{
+                "type": "0x7e",
+                "sourceHash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+                "from": "0xdeaddeaddeaddeaddeaddeaddeaddeaddead0001",
+                "to": "0x4200000000000000000000000000000000000015",
+                "mint": "0x0",
+                "value": "0x0",
+                "gas": "0xf4240",
+                "isSystemTx": false,
+                "input": "0x440a5e200000146b000f79c500000000000000040000000066d6a2d70000000001312d00",
+                "hash": "0x0000000000000000000000000000000000000000000000000000000000000004",
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "blockNumber": "0x7",
+                "transactionIndex": "0x0",
+                "gasPrice": "0x0",
+                "nonce": "0x1"
+            }"#,
+        )
+        .unwrap();
+
+        let tx = Tx::from(deposit.clone());
+        assert_eq!(tx.tx_type, OP_DEPOSIT_TX_TYPE);
+        assert_eq!(tx.to, Some(OP_L1_BLOCK));
+        assert_eq!(op_l1_block_number(&[deposit]), Some(20_000_000));
+    }
+}
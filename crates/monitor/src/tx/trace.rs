@@ -0,0 +1,421 @@
+use super::{evm::get_block_transactions, rollup::enrich_l2_fields};
+use crate::provider::RpcProvider;
+use alloy::{
+    primitives::{Address, B256},
+    rpc::types::trace::{
+        geth::{CallFrame, GethTrace, TraceResult},
+        parity::{Action, CreationMethod, LocalizedTransactionTrace, TraceOutput},
+    },
+};
+use anyhow::bail;
+use futures::StreamExt;
+use primitives::{
+    Rollup, TraceFormat,
+    db::{
+        create_internal_calls_db_table, create_new_tx_db_table, enqueue_transactions_to_outbox,
+        store_internal_call_to_db, store_tx_to_db,
+    },
+    monitor::{InternalCall, Tx},
+};
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// Flattens a geth call frame and its sub calls in execution order.
+/// The calls below a failed call are marked as reverted along with it.
+pub fn flatten_call_frame(
+    frame: &CallFrame,
+    transaction_hash: B256,
+    block_number: u64,
+) -> Vec<InternalCall> {
+    fn walk(
+        frame: &CallFrame,
+        trace_address: Vec<usize>,
+        parent_reverted: bool,
+        base: &InternalCall,
+        calls: &mut Vec<InternalCall>,
+    ) {
+        let reverted = parent_reverted || frame.error.is_some();
+        calls.push(InternalCall {
+            trace_address: trace_address.clone(),
+            call_type: frame.typ.to_lowercase(),
+            from: frame.from,
+            to: frame.to,
+            value: frame.value.unwrap_or_default(),
+            error: frame.error.clone(),
+            reverted,
+            ..base.clone()
+        });
+        for (index, call) in frame.calls.iter().enumerate() {
+            let mut address = trace_address.clone();
+            address.push(index);
+            walk(call, address, reverted, base, calls);
+        }
+    }
+
+    let base = InternalCall {
+        transaction_hash,
+        block_number,
+        ..Default::default()
+    };
+    let mut calls = Vec::new();
+    walk(frame, Vec::new(), false, &base, &mut calls);
+
+    calls
+}
+
+/// Marks the calls below a failed call of their transaction as reverted. Parity traces list every
+/// call on its own, a call only knows its own error.
+pub fn mark_reverted(calls: &mut [InternalCall]) {
+    let failed: HashSet<(B256, Vec<usize>)> = calls
+        .iter()
+        .filter(|call| call.error.is_some())
+        .map(|call| (call.transaction_hash, call.trace_address.clone()))
+        .collect();
+    for call in calls.iter_mut() {
+        call.reverted = (0..=call.trace_address.len()).any(|depth| {
+            failed.contains(&(call.transaction_hash, call.trace_address[..depth].to_vec()))
+        });
+    }
+}
+
+/// Converts a parity trace, block rewards are not calls and give `None`.
+/// Only the own error of the call is known, see [`mark_reverted`].
+pub fn from_parity_trace(trace: &LocalizedTransactionTrace) -> Option<InternalCall> {
+    let (call_type, from, to, value) = match &trace.trace.action {
+        Action::Call(call) => (
+            call.call_type.to_string().to_lowercase(),
+            call.from,
+            Some(call.to),
+            call.value,
+        ),
+        Action::Create(create) => {
+            let created = match &trace.trace.result {
+                Some(TraceOutput::Create(output)) => Some(output.address),
+                _ => None,
+            };
+            let call_type = match create.creation_method {
+                CreationMethod::Create2 => "create2",
+                _ => "create",
+            };
+            (call_type.to_string(), create.from, created, create.value)
+        }
+        Action::Selfdestruct(selfdestruct) => (
+            "selfdestruct".to_string(),
+            selfdestruct.address,
+            Some(selfdestruct.refund_address),
+            selfdestruct.balance,
+        ),
+        Action::Reward(_) => return None,
+    };
+
+    Some(InternalCall {
+        transaction_hash: trace.transaction_hash?,
+        block_number: trace.block_number.unwrap_or_default(),
+        trace_address: trace.trace.trace_address.clone(),
+        call_type,
+        from,
+        to,
+        value,
+        error: trace.trace.error.clone(),
+        reverted: trace.trace.error.is_some(),
+        ..Default::default()
+    })
+}
+
+/// Indexes the internal calls of every transaction from the block traces, together with
+/// the transactions they belong to.
+/// The top level call of a transaction is the transaction itself, only the calls below it are kept.
+pub struct TraceMonitorTable {
+    name: String,
+    format: TraceFormat,
+    rollup: Option<Rollup>,
+    outbox: bool,
+}
+
+impl TraceMonitorTable {
+    pub fn new(name: String, format: TraceFormat) -> Self {
+        Self {
+            name,
+            format,
+            rollup: None,
+            outbox: false,
+        }
+    }
+
+    /// Stores the L1 fields of the rollup stack of the chain with the parent transactions
+    pub fn with_rollup(mut self, rollup: Option<Rollup>) -> Self {
+        self.rollup = rollup;
+        self
+    }
+
+    /// Queues the parent transactions of every indexed block in the outbox of the monitor
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// The internal calls of a block, from or to one of the `watched` addresses when there are any
+    pub async fn block_calls(
+        &self,
+        provider: &RpcProvider,
+        watched: &[Address],
+        block_number: u64,
+    ) -> Result<Vec<InternalCall>, anyhow::Error> {
+        let calls: Vec<InternalCall> = match self.format {
+            TraceFormat::Geth => {
+                let mut calls = Vec::new();
+                for result in provider.debug_trace_block(block_number).await? {
+                    match result {
+                        TraceResult::Success {
+                            result: GethTrace::CallTracer(frame),
+                            tx_hash: Some(tx_hash),
+                        } => calls.extend(flatten_call_frame(&frame, tx_hash, block_number)),
+                        // The block is failed rather than stored without the calls of the transaction
+                        TraceResult::Error { error, tx_hash } => bail!(
+                            "{}: could not trace {tx_hash:?} in block {block_number}: {error}",
+                            self.name
+                        ),
+                        // Nodes omitting the hash of the traced transaction can not be linked
+                        _ => warn!(
+                            "{}: unexpected trace in block {block_number}, is the node running geth's callTracer?",
+                            self.name
+                        ),
+                    }
+                }
+                calls
+            }
+            TraceFormat::Parity => {
+                let mut calls: Vec<InternalCall> = provider
+                    .trace_block(block_number)
+                    .await?
+                    .iter()
+                    .filter_map(from_parity_trace)
+                    .collect();
+                mark_reverted(&mut calls);
+                calls
+            }
+        };
+
+        Ok(calls
+            .into_iter()
+            .map(|call| InternalCall {
+                chain_id: provider.chain_id(),
+                ..call
+            })
+            .filter(|call| !call.trace_address.is_empty())
+            .filter(|call| {
+                watched.is_empty()
+                    || watched.contains(&call.from)
+                    || call.to.is_some_and(|to| watched.contains(&to))
+            })
+            .collect())
+    }
+
+    /// Stores the internal calls of a block along with their parent transactions,
+    /// in one db transaction
+    async fn index_block(
+        &self,
+        provider: &RpcProvider,
+        watched: &[Address],
+        block_number: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        let calls = self.block_calls(provider, watched, block_number).await?;
+        if calls.is_empty() {
+            return Ok(());
+        }
+
+        let hashes: HashSet<B256> = calls.iter().map(|call| call.transaction_hash).collect();
+        let block_txs = get_block_transactions(provider, block_number).await?;
+        let mut txs: Vec<Tx> = block_txs
+            .iter()
+            .map(|tx| Tx::from(tx.clone()).with_chain_id(provider.chain_id()))
+            .filter(|tx| hashes.contains(&tx.hash))
+            .collect();
+        if let Some(rollup) = self.rollup {
+            enrich_l2_fields(provider, rollup, &block_txs, &mut txs).await?;
+        }
+
+        let db_tx = db_client.transaction().await?;
+        let mut stored = Vec::with_capacity(txs.len());
+        for tx in txs.iter() {
+            if store_tx_to_db(&db_tx, tx, &self.name).await? {
+                stored.push(tx.clone());
+            }
+        }
+        for call in calls.iter() {
+            store_internal_call_to_db(&db_tx, &self.name, call).await?;
+        }
+        // A block indexed again by a restart is not queued for the sinks twice
+        if self.outbox && !stored.is_empty() {
+            enqueue_transactions_to_outbox(&db_tx, &self.name, &stored).await?;
+        }
+        db_tx.commit().await?;
+        info!(
+            "{}: indexed {} internal calls of block {block_number}",
+            self.name,
+            calls.len()
+        );
+
+        Ok(())
+    }
+
+    /// Indexes the blocks from `from_block` up to the chain head, then every new block
+    pub async fn query_and_subscribe_traces(
+        &self,
+        provider: RpcProvider,
+        watched: Vec<Address>,
+        from_block: u64,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        create_new_tx_db_table(db_client, &self.name).await?;
+        create_internal_calls_db_table(db_client, &self.name).await?;
+
+        // Subscribe before reading the head so that no block falls between backfill and live blocks
+        let mut stream = provider.subscribe_blocks().await?;
+        let head = provider.get_block_number().await?;
+        for block_number in from_block..=head {
+            self.index_block(&provider, &watched, block_number, db_client)
+                .await?;
+        }
+
+        while let Some(header) = stream.next().await {
+            let header = header?;
+            if header.number > head {
+                self.index_block(&provider, &watched, header.number, db_client)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_flatten_call_frame() {
+        let frame: CallFrame = serde_json::from_str(
+            r#"{
+                "type": "CALL",
+                "from": "0x00000000000000000000000000000000000000aa",
+                "to": "0x00000000000000000000000000000000000000bb",
+                "value": "0x0",
+                "gas": "0x10000",
+                "gasUsed": "0x5000",
+                "input": "0x",
+                "calls": [
+                    {
+                        "type": "DELEGATECALL",
+                        "from": "0x00000000000000000000000000000000000000bb",
+                        "to": "0x00000000000000000000000000000000000000cc",
+                        "gas": "0x8000",
+                        "gasUsed": "0x1000",
+                        "input": "0x",
+                        "calls": [
+                            {
+                                "type": "CALL",
+                                "from": "0x00000000000000000000000000000000000000bb",
+                                "to": "0x00000000000000000000000000000000000000dd",
+                                "value": "0xde0b6b3a7640000",
+                                "gas": "0x2300",
+                                "gasUsed": "0x0",
+                                "input": "0x",
+                                "error": "out of gas"
+                            }
+                        ]
+                    },
+                    {
+                        "type": "STATICCALL",
+                        "from": "0x00000000000000000000000000000000000000bb",
+                        "to": "0x00000000000000000000000000000000000000ee",
+                        "gas": "0x1000",
+                        "gasUsed": "0x100",
+                        "input": "0x"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let calls = flatten_call_frame(&frame, B256::ZERO, 7);
+        let shape: Vec<(Vec<usize>, &str)> = calls
+            .iter()
+            .map(|call| (call.trace_address.clone(), call.call_type.as_str()))
+            .collect();
+        assert_eq!(
+            shape,
+            vec![
+                (vec![], "call"),
+                (vec![0], "delegatecall"),
+                (vec![0, 0], "call"),
+                (vec![1], "staticcall"),
+            ]
+        );
+        assert_eq!(calls[2].value.to_string(), "1000000000000000000");
+        assert_eq!(calls[2].error.as_deref(), Some("out of gas"));
+        assert_eq!(calls[2].block_number, 7);
+        let reverted: Vec<bool> = calls.iter().map(|call| call.reverted).collect();
+        assert_eq!(reverted, [false, false, true, false]);
+
+        // Every call below a reverted parent is reverted, whatever its own outcome
+        let mut failed = frame.clone();
+        failed.calls[0].error = Some("execution reverted".to_string());
+        let calls = flatten_call_frame(&failed, B256::ZERO, 7);
+        let reverted: Vec<bool> = calls.iter().map(|call| call.reverted).collect();
+        assert_eq!(reverted, [false, true, true, false]);
+        assert_eq!(calls[2].error.as_deref(), Some("out of gas"));
+
+        // The same marking from the flat calls of a parity trace
+        let mut flat: Vec<InternalCall> = calls
+            .iter()
+            .map(|call| InternalCall {
+                reverted: false,
+                ..call.clone()
+            })
+            .collect();
+        mark_reverted(&mut flat);
+        let reverted: Vec<bool> = flat.iter().map(|call| call.reverted).collect();
+        assert_eq!(reverted, [false, true, true, false]);
+    }
+
+    #[test]
+    pub fn test_from_parity_trace() {
+        let trace: LocalizedTransactionTrace = serde_json::from_str(
+            r#"{
+                "action": {
+                    "from": "0x00000000000000000000000000000000000000bb",
+                    "callType": "call",
+                    "gas": "0x2300",
+                    "input": "0x",
+                    "to": "0x00000000000000000000000000000000000000dd",
+                    "value": "0x1"
+                },
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "blockNumber": 7,
+                "result": { "gasUsed": "0x0", "output": "0x" },
+                "subtraces": 0,
+                "traceAddress": [0, 0],
+                "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "transactionPosition": 0,
+                "type": "call"
+            }"#,
+        )
+        .unwrap();
+
+        let call = from_parity_trace(&trace).unwrap();
+        assert_eq!(call.trace_address, vec![0, 0]);
+        assert_eq!(call.call_type, "call");
+        assert_eq!(call.block_number, 7);
+        assert_eq!(
+            call.to,
+            Some(
+                "0x00000000000000000000000000000000000000dd"
+                    .parse()
+                    .unwrap()
+            )
+        );
+    }
+}
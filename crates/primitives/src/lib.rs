@@ -1,9 +1,17 @@
 pub mod db;
 pub mod error;
 pub mod monitor;
+pub mod notify;
+pub mod schema;
 pub mod traits;
 
+use alloy::primitives::{Address, B256};
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU32, NonZeroU64},
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
@@ -20,10 +28,637 @@ pub struct ServerConfig {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MonitorConfig {
+    #[serde(default)]
+    pub kind: MonitorKind,
     pub event_name: String,
+    /// The chain family of `rpc_url`, selects the chain adapter running the monitor
+    #[serde(default = "default_state_machine")]
+    pub state_machine: String,
     pub rpc_url: String,
-    pub address: String,
-    pub event_signature: String,
+    /// Checked against the chain id reported by `rpc_url` at startup, stored on every row
+    pub chain_id: Option<u64>,
+    /// Stores the L1 fee, L1 gas and L1 block of the indexed transactions of a rollup
+    pub rollup: Option<Rollup>,
+    /// Omitted for a wildcard `event` monitor, which indexes the signature from every emitter
+    pub address: Option<String>,
+    /// More watched addresses for the `transfers` and `deployments` monitors
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Not needed by the token presets, which know their event signatures
+    pub event_signature: Option<String>,
+    /// Optional filters on the indexed parameters, addresses are padded to 32 bytes
+    pub topic1: Option<TopicFilter>,
+    pub topic2: Option<TopicFilter>,
+    pub topic3: Option<TopicFilter>,
+    /// Stores the sender, recipient, gas used and method selector of the transaction of every
+    /// log of an `event` monitor, at the cost of two requests per transaction
+    #[serde(default)]
+    pub join_transactions: bool,
     pub block_number: u64,
     pub db_url: String,
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    /// Required when `kind = "factory"`
+    pub factory: Option<FactoryConfig>,
+    /// Maintains holder balances from the transfers of a token monitor
+    pub balances: Option<BalancesConfig>,
+    /// Tuning of the wildcard mode, used when `address` is omitted
+    #[serde(default)]
+    pub wildcard: WildcardConfig,
+    /// Block scanning of the `transfers` and `deployments` monitors
+    #[serde(default)]
+    pub blocks: BlocksConfig,
+    /// Tracing API used by a `traces` monitor
+    #[serde(default)]
+    pub traces: TracesConfig,
+    /// Required when `kind = "polling"`
+    pub polling: Option<PollingConfig>,
+    /// Required when `kind = "storage"`
+    pub storage: Option<StorageConfig>,
+    /// Announces every committed batch of an `event`, `factory` or wildcard monitor on the
+    /// `<event_name>_changes` channel, see `primitives::notify::ChangeFeed`
+    #[serde(default)]
+    pub notify: bool,
+    /// Destinations the indexed events and transactions are forwarded to, in order and at least once
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    /// A WebAssembly mapping run on the events of an `event` or `factory` monitor
+    pub wasm: Option<WasmConfig>,
+    /// A Rhai script filtering and transforming the events of an `event` or `factory` monitor
+    /// before they are stored
+    pub script: Option<ScriptConfig>,
+    /// Human readable ABI of the indexed event, e.g.
+    /// `Transfer(address indexed from, address indexed to, uint256 value)`, decodes the parameters
+    /// of the events served by the typed GraphQL fields
+    pub abi: Option<String>,
+    /// Path to a GraphQL schema declaring the entities the WebAssembly mapping writes,
+    /// see `primitives::schema::EntitySchema`
+    pub schema: Option<String>,
+}
+
+impl MonitorConfig {
+    /// The ABI of the indexed event, `abi` or else the event of the wasm mapping or script
+    pub fn event_abi(&self) -> Option<&str> {
+        self.abi
+            .as_deref()
+            .or(self.wasm.as_ref().map(|wasm| wasm.event.as_str()))
+            .or(self.script.as_ref().map(|script| script.event.as_str()))
+    }
+
+    /// `address` followed by `addresses`
+    pub fn watched_addresses(&self) -> Result<Vec<Address>, anyhow::Error> {
+        self.address
+            .iter()
+            .chain(self.addresses.iter())
+            .map(|address| Ok(address.parse()?))
+            .collect()
+    }
+
+    /// The values accepted for topic1 to topic3, an empty list matches any value
+    pub fn topic_filters(&self) -> Result<[Vec<B256>; 3], anyhow::Error> {
+        let parse = |filter: &Option<TopicFilter>| match filter {
+            Some(filter) => filter.to_topics(),
+            None => Ok(Vec::new()),
+        };
+
+        Ok([
+            parse(&self.topic1)?,
+            parse(&self.topic2)?,
+            parse(&self.topic3)?,
+        ])
+    }
+}
+
+/// A single topic value or a list of values, any of which matches
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TopicFilter {
+    One(String),
+    Any(Vec<String>),
+}
+
+impl TopicFilter {
+    pub fn to_topics(&self) -> Result<Vec<B256>, anyhow::Error> {
+        match self {
+            Self::One(value) => Ok(vec![parse_topic(value)?]),
+            Self::Any(values) => values.iter().map(|value| parse_topic(value)).collect(),
+        }
+    }
+}
+
+/// Parses a 32 bytes topic, 20 bytes addresses are left padded the way they are indexed
+pub fn parse_topic(value: &str) -> Result<B256, anyhow::Error> {
+    let hex = value.trim();
+    match hex.trim_start_matches("0x").len() {
+        40 => Ok(hex.parse::<Address>()?.into_word()),
+        64 => Ok(hex.parse::<B256>()?),
+        _ => bail!("topic `{value}` is neither a 20 bytes address nor a 32 bytes word"),
+    }
+}
+
+/// What a monitor indexes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorKind {
+    /// Logs matching `event_signature` emitted by `address`
+    #[default]
+    Event,
+    /// Logs matching `event_signature` emitted by every child created by the factory at `address`
+    Factory,
+    /// Decoded ERC-20 `Transfer` and `Approval` events of the token at `address`
+    Erc20,
+    /// Decoded ERC-721 `Transfer`, `Approval` and `ApprovalForAll` events of the collection at `address`
+    Erc721,
+    /// Decoded ERC-1155 `TransferSingle`, `TransferBatch` and `ApprovalForAll` events of the contract at `address`
+    Erc1155,
+    /// Transactions with a non zero `value` from or to `address`/`addresses`
+    Transfers,
+    /// Contract creation transactions, only those sent by `address`/`addresses` when set
+    Deployments,
+    /// Internal calls from the block traces, only those from or to `address`/`addresses` when set
+    Traces,
+    /// Pending transactions from or to `address`/`addresses`, followed until they are mined or replaced
+    Mempool,
+    /// Results of view calls, executed every `polling.interval` blocks
+    Polling,
+    /// Changes of storage slots of the contract at `address`
+    Storage,
+}
+
+impl MonitorKind {
+    /// Whether the outbox of the monitor holds events, the transaction kinds queue transactions
+    pub fn queues_events(&self) -> bool {
+        matches!(
+            self,
+            Self::Event | Self::Factory | Self::Erc20 | Self::Erc721 | Self::Erc1155
+        )
+    }
+}
+
+/// The rollup stack of a chain, which decides where its L1 metadata is read from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rollup {
+    /// OP Stack chains (Optimism, Base, ...), with `0x7e` deposit transactions
+    Optimism,
+    /// Arbitrum One and Nova, with `0x64` deposit transactions
+    Arbitrum,
+}
+
+/// The tracing API of the node and the shape of the traces it returns
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceFormat {
+    /// `debug_traceBlockByNumber` with the `callTracer`, nested call frames (geth, reth, erigon)
+    #[default]
+    Geth,
+    /// `trace_block`, flat traces located by their trace address (openethereum, erigon, reth)
+    Parity,
+}
+
+/// View calls of a `polling` monitor, each stored in its own time series table
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollingConfig {
+    /// The calls are executed at every block whose number is a multiple of `interval`
+    #[serde(default = "default_polling_interval")]
+    pub interval: u64,
+    pub calls: Vec<ViewCallConfig>,
+}
+
+fn default_state_machine() -> String {
+    "EVM".to_string()
+}
+
+fn default_polling_interval() -> u64 {
+    1
+}
+
+/// A view call polled by a `polling` monitor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ViewCallConfig {
+    /// The results are stored in `{event_name}_{name}`
+    pub name: String,
+    /// Human readable function with its outputs, e.g.
+    /// `getReserves() returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)`
+    pub function: String,
+    /// The arguments of the call, as they would be written in solidity
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The called contract, defaults to the monitor `address`
+    pub address: Option<String>,
+}
+
+/// Storage slots of a `storage` monitor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageConfig {
+    /// The slots are read at every block whose number is a multiple of `interval`
+    #[serde(default = "default_polling_interval")]
+    pub interval: u64,
+    pub slots: Vec<SlotConfig>,
+}
+
+/// A storage slot, or an entry of a mapping stored at that slot
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlotConfig {
+    /// Identifies the slot in the changes table
+    pub name: String,
+    /// The slot of the variable, decimal or hex
+    pub slot: String,
+    /// Mapping keys, outermost first. Addresses are padded to 32 bytes, numbers are decimal or hex
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+/// Call tracing of a `traces` monitor
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TracesConfig {
+    pub format: TraceFormat,
+}
+
+/// Storage and batching of a wildcard monitor, which can see every log of a signature on the chain
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WildcardConfig {
+    /// Number of hash partitions of the events table, keyed by emitter address
+    pub partitions: u32,
+    /// Number of blocks queried per `eth_getLogs` call, and stored per db transaction
+    pub block_range: u64,
+    /// Maximum number of logs handed to the hooks and the sinks as one batch
+    pub batch_size: usize,
+    /// Blocks a block must be behind the chain head before its logs are indexed, so that the
+    /// indexed blocks are past the reach of a reorg
+    pub confirmations: u64,
+}
+
+impl Default for WildcardConfig {
+    fn default() -> Self {
+        Self {
+            partitions: 16,
+            block_range: 500,
+            batch_size: 1000,
+            confirmations: 12,
+        }
+    }
+}
+
+/// Block scanning of the `transfers` and `deployments` monitors, which read every block in full
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BlocksConfig {
+    /// Number of blocks stored per db transaction, along with the progress of the monitor
+    pub block_range: u64,
+    /// Number of blocks requested at the same time
+    pub concurrency: usize,
+    /// Blocks a block must be behind the chain head before it is indexed, so that the indexed
+    /// transactions are past the reach of a reorg
+    pub confirmations: u64,
+}
+
+impl Default for BlocksConfig {
+    fn default() -> Self {
+        Self {
+            block_range: 100,
+            concurrency: 8,
+            confirmations: 12,
+        }
+    }
+}
+
+/// Derived balance tables of a token monitor
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BalancesConfig {
+    /// Also keep the balance of every holder after each block it changed in
+    #[serde(default)]
+    pub history: bool,
+}
+
+/// Describes how child contracts are discovered from the factory events
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FactoryConfig {
+    /// Human readable factory event, e.g.
+    /// `PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool)`
+    pub event: String,
+    /// Name of the event parameter holding the child address
+    pub child_param: String,
+}
+
+/// A destination of the events of a monitor, fed from the `{event_name}_outbox` table
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SinkConfig {
+    /// Identifies the delivery cursor of the sink, changing it replays the outbox. The cursor of
+    /// the old name pins the outbox until the monitor restarts without it.
+    pub name: String,
+    /// The first delay before a failed delivery is retried, doubled on every retry
+    #[serde(default = "default_sink_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Upper bound for a single retry delay, deliveries are retried until they succeed
+    #[serde(default = "default_sink_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(flatten)]
+    pub kind: SinkKind,
+}
+
+/// Where a sink delivers, selected by its `kind` key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SinkKind {
+    /// POSTs every batch as JSON to `url`
+    Webhook(WebhookConfig),
+    /// Produces one message per event to a Kafka (or Redpanda) topic
+    Kafka(KafkaConfig),
+    /// Publishes one message per event to a NATS JetStream subject
+    Nats(NatsConfig),
+    /// Appends one entry per event to a capped Redis stream
+    Redis(RedisConfig),
+    /// Writes the events and transactions to rolling local files
+    File(FileConfig),
+}
+
+/// A Kafka topic receiving the events of a monitor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KafkaConfig {
+    /// `bootstrap.servers`, e.g. `"localhost:9092"`
+    pub brokers: String,
+    /// The `event_name` of the monitor when omitted
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub key: MessageKey,
+    #[serde(default)]
+    pub encoding: MessageEncoding,
+    /// Extra librdkafka producer settings, applied over the idempotent defaults
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+    #[serde(default = "default_kafka_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// A NATS JetStream subject receiving the events of a monitor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NatsConfig {
+    /// e.g. `"nats://localhost:4222"`
+    pub url: String,
+    /// `nexus.<event_name>` when omitted
+    pub subject: Option<String>,
+    /// Creates this stream over the subject when it does not exist yet
+    pub stream: Option<String>,
+    #[serde(default)]
+    pub encoding: MessageEncoding,
+}
+
+/// A Redis stream receiving the events of a monitor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RedisConfig {
+    /// e.g. `"redis://localhost:6379"`
+    pub url: String,
+    /// `nexus:<event_name>` when omitted
+    pub stream: Option<String>,
+    /// Entries kept by the stream, older ones are trimmed (`XADD MAXLEN ~`)
+    #[serde(default = "default_redis_max_len")]
+    pub max_len: u64,
+    #[serde(default)]
+    pub encoding: MessageEncoding,
+}
+
+/// Local files receiving the events and transactions of a monitor, e.g. for DuckDB
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileConfig {
+    /// The files of a monitor go to `<path>/<event_name>/`, next to their `manifest.json`
+    pub path: String,
+    #[serde(default)]
+    pub format: FileFormat,
+    /// A file is closed once it covers this many blocks
+    #[serde(default = "default_file_rotate_blocks")]
+    pub rotate_blocks: u64,
+    /// A file is closed once the batches written to it reach this many bytes
+    #[serde(default = "default_file_rotate_bytes")]
+    pub rotate_bytes: u64,
+    /// Human readable ABI of the indexed event, e.g.
+    /// `Transfer(address indexed from, address indexed to, uint256 value)`, every parameter
+    /// becomes a column
+    pub event: Option<String>,
+}
+
+/// The format of the exported files
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    /// JSON Lines
+    #[default]
+    Jsonl,
+    Csv,
+    Parquet,
+}
+
+/// What identifies the message of an event, events sharing a key keep their order.
+/// Only with `event` is a removed event retracted with a tombstone, with the other keys the
+/// retraction carries the event and the `nexus-removed` header, so compaction keeps the other
+/// events of the transaction or the contract.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKey {
+    /// `<transaction_hash>:<log_index>`, unique per event so a compacted topic keeps every event
+    #[default]
+    Event,
+    TransactionHash,
+    /// The contract that emitted the event
+    Address,
+}
+
+/// How an event is written in a message
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEncoding {
+    #[default]
+    Json,
+    /// Avro single object encoding of the schema published by the sink crate
+    Avro,
+}
+
+/// An HTTP endpoint receiving the batches of a monitor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Signs the body with HMAC-SHA256, sent as `X-Nexus-Signature: sha256=<hex>`
+    pub secret: Option<String>,
+    #[serde(default = "default_webhook_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_sink_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_sink_max_backoff_ms() -> u64 {
+    60_000
+}
+
+fn default_webhook_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_kafka_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_redis_max_len() -> u64 {
+    100_000
+}
+
+fn default_file_rotate_blocks() -> u64 {
+    100_000
+}
+
+fn default_file_rotate_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+/// A WebAssembly module handling the events of a monitor, see the README for its imports and
+/// exports. Floating point instructions are rejected so that every run gives the same result.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WasmConfig {
+    /// Path to the `.wasm` file
+    pub module: String,
+    /// Human readable ABI the events are decoded with, e.g.
+    /// `Transfer(address indexed from, address indexed to, uint256 value)`
+    pub event: String,
+    /// The export called with every new event
+    #[serde(default = "default_wasm_handler")]
+    pub handler: String,
+    /// The export called with every event removed by a reorg, skipped when the module lacks it
+    #[serde(default = "default_wasm_revert")]
+    pub revert: String,
+    /// The instructions a single call may run, roughly one unit per instruction
+    #[serde(default = "default_wasm_fuel")]
+    pub fuel: u64,
+    /// The memory a single call may grow to
+    #[serde(default = "default_wasm_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+/// A Rhai script run on every decoded event before it is stored, see the README.
+/// It sees the event as the map `event`, returns `false` to drop it, and otherwise stores
+/// `event.params` as the record of the event in `<event_name>_<event.table>`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScriptConfig {
+    /// Human readable ABI the events are decoded with, e.g.
+    /// `Transfer(address indexed from, address indexed to, uint256 value)`
+    pub event: String,
+    /// The script itself, or
+    pub source: Option<String>,
+    /// the path of a file holding it
+    pub path: Option<String>,
+    /// The record table when the script sets no `event.table`
+    #[serde(default = "default_script_table")]
+    pub table: String,
+    /// The operations a single run may take before it is aborted
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,
+}
+
+fn default_script_table() -> String {
+    "records".to_string()
+}
+
+fn default_script_max_operations() -> u64 {
+    100_000
+}
+
+fn default_wasm_handler() -> String {
+    "handle".to_string()
+}
+
+fn default_wasm_revert() -> String {
+    "revert".to_string()
+}
+
+fn default_wasm_fuel() -> u64 {
+    10_000_000
+}
+
+fn default_wasm_max_memory_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+/// Limits and retry behaviour applied to every RPC call made by a monitor.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RpcConfig {
+    /// Maximum number of requests sent to the endpoint per second, unlimited when unset
+    pub requests_per_second: Option<NonZeroU32>,
+    /// Maximum number of compute units spent per second, unlimited when unset
+    pub compute_units_per_second: Option<NonZeroU64>,
+    /// How many times a transient error is retried before giving up
+    pub max_retries: u32,
+    /// The first backoff delay, doubled on every retry
+    pub initial_backoff_ms: u64,
+    /// Upper bound for a single backoff delay
+    pub max_backoff_ms: u64,
+    /// Notifications buffered per subscription, a monitor falling further behind fails instead
+    /// of silently missing them
+    pub subscription_channel_size: usize,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: None,
+            compute_units_per_second: None,
+            max_retries: 10,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            subscription_channel_size: 1024,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::b256;
+
+    #[test]
+    pub fn test_topic_filters_pad_addresses() {
+        let config: MonitorConfig = serde_json::from_value(serde_json::json!({
+            "event_name": "treasury_transfers",
+            "rpc_url": "wss://ethereum-rpc.publicnode.com",
+            "address": "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984",
+            "event_signature": "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+            "block_number": 0,
+            "db_url": "host=localhost user=postgres",
+            "topic2": [
+                "0xd8da6bf26964af9d7eed9e03e53415d37aa96045",
+                "0x00000000000000000000000088da6bf26964af9d7eed9e03e53415d37aa96045"
+            ]
+        }))
+        .unwrap();
+
+        let [topic1, topic2, topic3] = config.topic_filters().unwrap();
+        assert!(topic1.is_empty() && topic3.is_empty());
+        assert_eq!(
+            topic2,
+            vec![
+                b256!("000000000000000000000000d8da6bf26964af9d7eed9e03e53415d37aa96045"),
+                b256!("00000000000000000000000088da6bf26964af9d7eed9e03e53415d37aa96045"),
+            ]
+        );
+        assert!(parse_topic("0x1234").is_err());
+    }
+
+    #[test]
+    pub fn test_rpc_rates_are_not_zero() {
+        let config: RpcConfig =
+            serde_json::from_value(serde_json::json!({"requests_per_second": 10})).unwrap();
+        assert_eq!(config.requests_per_second, NonZeroU32::new(10));
+        assert!(config.compute_units_per_second.is_none());
+
+        assert!(
+            serde_json::from_value::<RpcConfig>(serde_json::json!({"requests_per_second": 0}))
+                .is_err()
+        );
+        assert!(
+            serde_json::from_value::<RpcConfig>(serde_json::json!({"compute_units_per_second": 0}))
+                .is_err()
+        );
+    }
 }
@@ -1,7 +1,10 @@
 use crate::db::store_event_to_db;
 use alloy::{
-    primitives::{Address, B256, Bytes, U256},
-    rpc::types::eth::{Log, Transaction, TransactionTrait},
+    dyn_abi::DynSolValue,
+    eips::Typed2718,
+    network::{AnyRpcTransaction, TransactionResponse},
+    primitives::{Address, B256, Bytes, FixedBytes, U256},
+    rpc::types::eth::{Log, TransactionTrait},
 };
 use async_graphql::SimpleObject;
 use serde::{Deserialize, Serialize};
@@ -14,6 +17,122 @@ pub struct Event {
     pub transaction_hash: B256,
     pub topics: Vec<B256>,
     pub data: Bytes,
+    pub log_index: u64,
+    /// Set when the log was removed from the canonical chain by a reorg
+    pub removed: bool,
+    pub chain_id: u64,
+    /// The transaction that emitted the log, when the monitor joins transactions
+    pub transaction: Option<EventTx>,
+}
+
+// OutboxEntry => A committed batch of a monitor waiting in its outbox for the sinks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutboxEntry {
+    /// Increasing with the commit order, the delivery cursors point to these ids
+    pub id: u64,
+    pub payload: serde_json::Value,
+}
+
+// EventContext => A stored event as handed to the event handlers of its monitor
+#[derive(Debug, Clone)]
+pub struct EventContext {
+    pub monitor: String,
+    /// The id of the row of the event in the table of the monitor
+    pub row_id: i64,
+    /// The raw log with its block, transaction and chain, and the joined transaction if any
+    pub event: Event,
+    /// The parameters decoded with the ABI of the handlers, in the order they are declared
+    pub params: Vec<(String, DynSolValue)>,
+}
+
+impl EventContext {
+    /// The decoded value of the parameter `name`
+    pub fn param(&self, name: &str) -> Option<&DynSolValue> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The event as handed to scripted handlers, with the integers as decimal strings
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = event_json(&self.monitor, &self.event, &self.params);
+        json["row_id"] = self.row_id.into();
+        json
+    }
+}
+
+/// An event and its decoded parameters in JSON, with the integers as decimal strings
+pub fn event_json(
+    monitor: &str,
+    event: &Event,
+    params: &[(String, DynSolValue)],
+) -> serde_json::Value {
+    let params: serde_json::Map<String, serde_json::Value> = params
+        .iter()
+        .map(|(name, value)| (name.clone(), sol_value_json(value)))
+        .collect();
+
+    serde_json::json!({
+        "monitor": monitor,
+        "address": event.address,
+        "block_number": event.block_number,
+        "transaction_hash": event.transaction_hash,
+        "log_index": event.log_index,
+        "chain_id": event.chain_id,
+        "removed": event.removed,
+        "transaction": event.transaction,
+        "params": params,
+    })
+}
+
+/// A decoded value in JSON, integers do not fit in a JSON number and are written as strings
+pub fn sol_value_json(value: &DynSolValue) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        DynSolValue::Bool(value) => Value::Bool(*value),
+        DynSolValue::Int(value, _) => Value::String(value.to_string()),
+        DynSolValue::Uint(value, _) => Value::String(value.to_string()),
+        DynSolValue::Address(value) => Value::String(value.to_string()),
+        DynSolValue::Function(value) => Value::String(value.to_string()),
+        DynSolValue::FixedBytes(value, size) => {
+            Value::String(alloy::hex::encode_prefixed(&value[..*size]))
+        }
+        DynSolValue::Bytes(value) => Value::String(alloy::hex::encode_prefixed(value)),
+        DynSolValue::String(value) => Value::String(value.clone()),
+        other => Value::Array(
+            other
+                .as_fixed_seq()
+                .or_else(|| other.as_array())
+                .unwrap_or_default()
+                .iter()
+                .map(sol_value_json)
+                .collect(),
+        ),
+    }
+}
+
+// ScriptStats => What the script of a monitor did with a batch, added to its running totals
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptStats {
+    pub kept: u64,
+    pub dropped: u64,
+    /// Events the script failed on, they are stored without a record
+    pub failed: u64,
+    pub last_error: Option<String>,
+}
+
+// EventTx => What an event keeps of the transaction that emitted it
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventTx {
+    pub from: Address,
+    /// `None` for contract creations
+    pub to: Option<Address>,
+    /// Gas used by the whole transaction, `None` when its receipt was not found
+    pub gas_used: Option<u64>,
+    /// The first 4 bytes of the input, `None` for plain transfers
+    pub method_selector: Option<FixedBytes<4>>,
 }
 
 // DisplayEvent => Struct to display events
@@ -24,6 +143,19 @@ pub struct DisplayEvent {
     pub transaction_hash: String,
     pub topics: Vec<String>,
     pub data: String,
+    pub chain_id: String,
+    /// Set for the monitors joining transactions
+    pub transaction: Option<DisplayEventTx>,
+}
+
+// DisplayEventTx => Struct to display the transaction of an event
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "EventTransaction")]
+pub struct DisplayEventTx {
+    pub from: String,
+    pub to: Option<String>,
+    pub gas_used: Option<String>,
+    pub method_selector: Option<String>,
 }
 
 // Monitoring and getting of the Transactions
@@ -34,12 +166,267 @@ pub struct Tx {
     pub block_hash: B256,
     pub block_number: u64,
     pub from: Address,
-    pub to: Address,
+    /// `None` for contract creations
+    pub to: Option<Address>,
+    /// The created contract, read from the receipt of a contract creation
+    pub contract_address: Option<Address>,
     pub value: U256,
     pub gas_price: u128,
     pub gas_limit: u64,
     pub max_fee_per_gas: u128,
     pub data: Bytes,
+    pub chain_id: u64,
+    /// The EIP-2718 type, including the deposit types of the rollups
+    pub tx_type: u8,
+    /// The L1 data fee paid by a rollup transaction, in wei
+    pub l1_fee: Option<U256>,
+    /// The L1 gas charged for the data of a rollup transaction
+    pub l1_gas_used: Option<U256>,
+    /// The L1 block the rollup block was derived from
+    pub l1_block_number: Option<u64>,
+}
+
+// DisplayTx => Struct to display transactions
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+pub struct DisplayTx {
+    pub hash: String,
+    pub nonce: String,
+    pub block_hash: String,
+    pub block_number: String,
+    pub from: String,
+    pub to: Option<String>,
+    pub contract_address: Option<String>,
+    pub value: String,
+    pub gas_price: String,
+    pub gas_limit: String,
+    pub max_fee_per_gas: String,
+    pub data: String,
+    pub chain_id: String,
+    pub tx_type: String,
+    pub l1_fee: Option<String>,
+    pub l1_gas_used: Option<String>,
+    pub l1_block_number: Option<String>,
+}
+
+// PendingStatus => Where a transaction seen in the mempool ended up
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingStatus {
+    /// Waiting in the mempool
+    Pending,
+    /// Included in a block
+    Mined,
+    /// Another transaction with the same sender and nonce was sent or mined instead
+    Replaced,
+    /// Replaced by a transaction to the sender itself without value nor data
+    Cancelled,
+}
+
+impl PendingStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Mined => "mined",
+            Self::Replaced => "replaced",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+// DisplayPendingTx => Struct to display the mempool history of a transaction
+// Times are unix milliseconds on the clock of the indexer, `included_at` being when the block
+// that mined it was announced.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "PendingTx")]
+pub struct DisplayPendingTx {
+    pub hash: String,
+    pub from: String,
+    pub to: Option<String>,
+    pub nonce: String,
+    pub value: String,
+    pub max_fee_per_gas: String,
+    pub status: String,
+    /// The transaction that replaced or cancelled this one
+    pub replaced_by: Option<String>,
+    pub first_seen_at: String,
+    pub block_number: Option<String>,
+    pub included_at: Option<String>,
+    pub inclusion_latency_ms: Option<String>,
+    pub chain_id: String,
+}
+
+// CallResult => The decoded outputs of a view call at a block
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CallResult {
+    pub block_number: u64,
+    pub block_timestamp: u64,
+    /// The value of every output column, in the order of the columns
+    pub values: Vec<String>,
+}
+
+// CallColumnType => How an output of a view call is stored
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallColumnType {
+    /// Integers, up to 256 bits
+    Numeric,
+    Boolean,
+    /// Addresses, bytes, strings and the textual form of arrays and tuples
+    Text,
+}
+
+// DisplayCallResult => Struct to display the result of a view call
+// `values` is a JSON object keyed by output column.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "CallResult")]
+pub struct DisplayCallResult {
+    pub block_number: String,
+    pub block_timestamp: String,
+    pub values: String,
+}
+
+// SlotChange => A new value of a watched storage slot
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SlotChange {
+    pub slot_name: String,
+    pub slot: B256,
+    pub block_number: u64,
+    pub value: B256,
+    /// `None` for the first value read of the slot
+    pub previous: Option<B256>,
+}
+
+// DisplaySlotChange => Struct to display storage slot changes
+// `value_numeric` is the value read as an unsigned integer.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "SlotChange")]
+pub struct DisplaySlotChange {
+    pub slot_name: String,
+    pub slot: String,
+    pub block_number: String,
+    pub value: String,
+    pub value_numeric: String,
+    pub previous: Option<String>,
+}
+
+// InternalCall => A call made during the execution of a transaction, flattened from its trace
+// `trace_address` locates the call in the call tree, [1, 0] being the first call made by the second
+// call of the transaction, so its length is the depth of the call.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct InternalCall {
+    pub transaction_hash: B256,
+    pub block_number: u64,
+    pub trace_address: Vec<usize>,
+    /// `call`, `delegatecall`, `staticcall`, `create`, `selfdestruct`...
+    pub call_type: String,
+    pub from: Address,
+    /// `None` when a contract creation failed
+    pub to: Option<Address>,
+    pub value: U256,
+    pub error: Option<String>,
+    /// Set when the call or one of its parents failed, its effects were undone
+    pub reverted: bool,
+    pub chain_id: u64,
+}
+
+// DisplayInternalCall => Struct to display internal calls
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "InternalCall")]
+pub struct DisplayInternalCall {
+    pub transaction_hash: String,
+    pub block_number: String,
+    pub trace_address: String,
+    pub depth: String,
+    pub call_type: String,
+    pub from: String,
+    pub to: Option<String>,
+    pub value: String,
+    pub error: Option<String>,
+    pub reverted: bool,
+    pub chain_id: String,
+}
+
+// TokenTransfer => A decoded ERC-20, ERC-721 or ERC-1155 transfer
+// ERC-20 transfers only carry a `value`, ERC-721 ones only a `token_id` and ERC-1155 ones both.
+// A `TransferBatch` is split into one transfer per id, numbered by `batch_index`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TokenTransfer {
+    pub address: Address,
+    pub block_number: u64,
+    pub transaction_hash: B256,
+    pub log_index: u64,
+    pub batch_index: u32,
+    pub operator: Option<Address>,
+    pub from: Address,
+    pub to: Address,
+    pub token_id: Option<U256>,
+    pub value: Option<U256>,
+    pub chain_id: u64,
+}
+
+// TokenApproval => A decoded `Approval` or `ApprovalForAll` event
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TokenApproval {
+    pub address: Address,
+    pub block_number: u64,
+    pub transaction_hash: B256,
+    pub log_index: u64,
+    pub owner: Address,
+    pub spender: Address,
+    pub token_id: Option<U256>,
+    pub value: Option<U256>,
+    /// Only set for `ApprovalForAll`
+    pub approved: Option<bool>,
+    pub chain_id: u64,
+}
+
+// DisplayTokenTransfer => Struct to display token transfers
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "TokenTransfer")]
+pub struct DisplayTokenTransfer {
+    pub address: String,
+    pub block_number: String,
+    pub transaction_hash: String,
+    pub log_index: String,
+    pub operator: Option<String>,
+    pub from: String,
+    pub to: String,
+    pub token_id: Option<String>,
+    pub value: Option<String>,
+    pub chain_id: String,
+}
+
+// DisplayTokenApproval => Struct to display token approvals
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+#[graphql(name = "TokenApproval")]
+pub struct DisplayTokenApproval {
+    pub address: String,
+    pub block_number: String,
+    pub transaction_hash: String,
+    pub log_index: String,
+    pub owner: String,
+    pub spender: String,
+    pub token_id: Option<String>,
+    pub value: Option<String>,
+    pub approved: Option<bool>,
+    pub chain_id: String,
+}
+
+// TokenHolder => The balance of a holder, ERC-1155 balances are kept per token id
+#[derive(Serialize, Deserialize, Debug, Clone, Default, SimpleObject)]
+pub struct TokenHolder {
+    pub holder: String,
+    /// Empty for ERC-20 and ERC-721, where an ERC-721 balance is the number of tokens held
+    pub token_id: String,
+    pub balance: String,
+}
+
+// FactoryChild => A contract discovered through a factory event
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FactoryChild {
+    pub address: Address,
+    pub creation_block: u64,
+    pub transaction_hash: B256,
+    /// The last block the child's events have been indexed up to
+    pub last_block: u64,
 }
 
 // The struct to index for the event and for transactions
@@ -49,20 +436,24 @@ pub enum IndexingMode {
     Event,
 }
 
-impl From<Transaction> for Tx {
-    fn from(tx: Transaction) -> Self {
+impl From<AnyRpcTransaction> for Tx {
+    fn from(tx: AnyRpcTransaction) -> Self {
         Self {
-            hash: *tx.inner.tx_hash(),
+            hash: TransactionResponse::tx_hash(&tx),
             nonce: tx.nonce(),
-            block_hash: tx.block_hash.unwrap_or(B256::default()),
+            block_hash: tx.block_hash.unwrap_or_default(),
             block_number: tx.block_number.unwrap_or(0),
-            from: tx.inner.signer(),
-            to: tx.inner.to().unwrap_or(Address::ZERO),
+            from: TransactionResponse::from(&tx),
+            to: tx.to(),
+            contract_address: None,
             value: tx.value(),
-            gas_price: tx.gas_price().unwrap_or(0),
+            gas_price: TransactionTrait::gas_price(&tx).unwrap_or(0),
             gas_limit: tx.gas_limit(),
             data: tx.input().clone(),
-            max_fee_per_gas: tx.max_fee_per_gas(),
+            max_fee_per_gas: TransactionTrait::max_fee_per_gas(&tx),
+            chain_id: tx.chain_id().unwrap_or_default(),
+            tx_type: tx.ty(),
+            ..Default::default()
         }
     }
 }
@@ -74,7 +465,11 @@ impl From<Log> for Event {
             block_number: log.block_number.unwrap_or(0),
             transaction_hash: log.transaction_hash.unwrap_or(B256::default()),
             topics: log.data().clone().topics().to_vec(),
+            log_index: log.log_index.unwrap_or(0),
+            removed: log.removed,
             data: log.inner.data.data,
+            chain_id: 0,
+            transaction: None,
         }
     }
 }
@@ -86,6 +481,7 @@ impl DisplayEvent {
         transaction_hash: String,
         topics: Vec<String>,
         data: String,
+        chain_id: String,
     ) -> Self {
         Self {
             address,
@@ -93,10 +489,57 @@ impl DisplayEvent {
             transaction_hash,
             topics,
             data,
+            chain_id,
+            transaction: None,
         }
     }
 }
 
+/// Selects a page of the stored events of a monitor, in the order they were stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventPage {
+    pub address: Option<Address>,
+    pub transaction_hash: Option<B256>,
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub chain_id: Option<u64>,
+    pub first: u64,
+    pub skip: u64,
+    /// Newest first
+    pub descending: bool,
+}
+
+impl Default for EventPage {
+    fn default() -> Self {
+        Self {
+            address: None,
+            transaction_hash: None,
+            from_block: None,
+            to_block: None,
+            chain_id: None,
+            first: 100,
+            skip: 0,
+            descending: false,
+        }
+    }
+}
+
+impl Event {
+    /// Records the chain the log was read from, which the log itself does not carry
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+}
+
+impl Tx {
+    /// Records the chain the transaction was read from, legacy transactions may not carry it
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+}
+
 impl Event {
     pub async fn store_event(
         &self,
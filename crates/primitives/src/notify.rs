@@ -0,0 +1,213 @@
+use crate::monitor::Event;
+use postgres::NoTls;
+use serde::{Deserialize, Serialize};
+use std::future::poll_fn;
+use tokio::sync::mpsc;
+use tokio_postgres::AsyncMessage;
+use tracing::{error, warn};
+
+/// Postgres drops notifications with a payload of 8000 bytes or more
+const MAX_PAYLOAD: usize = 7900;
+
+/// The channel the committed batches of a monitor are announced on
+pub fn change_channel(name: &str) -> String {
+    format!("{name}_changes")
+}
+
+// ChangeNotification => A committed batch of rows of a monitor, sent on its change channel
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeNotification {
+    pub monitor: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    /// The ids of the new rows as inclusive `[first, last]` runs
+    pub ids: Vec<[i64; 2]>,
+    /// Set when the batch holds logs removed by a reorg
+    pub reorg: bool,
+    /// Set when the ids did not fit in a notification, the block range still covers the rows
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+impl ChangeNotification {
+    /// Describes the rows `ids` just stored for `events`, `None` for an empty batch
+    pub fn new(monitor: &str, events: &[Event], ids: &[i64]) -> Option<Self> {
+        let from_block = events.iter().map(|event| event.block_number).min()?;
+        let to_block = events.iter().map(|event| event.block_number).max()?;
+
+        let mut sorted = ids.to_vec();
+        sorted.sort_unstable();
+        let mut runs: Vec<[i64; 2]> = Vec::new();
+        for id in sorted {
+            match runs.last_mut() {
+                Some(run) if run[1] + 1 >= id => run[1] = id,
+                _ => runs.push([id, id]),
+            }
+        }
+
+        Some(Self {
+            monitor: monitor.to_string(),
+            from_block,
+            to_block,
+            ids: runs,
+            reorg: events.iter().any(|event| event.removed),
+            truncated: false,
+        })
+    }
+
+    /// The JSON payload, without the ids when they would not fit
+    pub fn payload(&self) -> Result<String, anyhow::Error> {
+        let payload = serde_json::to_string(self)?;
+        if payload.len() <= MAX_PAYLOAD {
+            return Ok(payload);
+        }
+
+        Ok(serde_json::to_string(&Self {
+            ids: Vec::new(),
+            truncated: true,
+            ..self.clone()
+        })?)
+    }
+}
+
+/// A connection following the change channels of monitors.
+/// ```ignore
+/// let mut feed = ChangeFeed::connect(db_url).await?;
+/// feed.listen("uni_transfers").await?;
+/// while let Some(change) = feed.recv().await? {
+///     // read the rows `change.ids` of the `uni_transfers` table
+/// }
+/// ```
+pub struct ChangeFeed {
+    client: tokio_postgres::Client,
+    receiver: mpsc::UnboundedReceiver<Result<ChangeNotification, tokio_postgres::Error>>,
+}
+
+impl ChangeFeed {
+    pub async fn connect(db_url: &str) -> Result<Self, anyhow::Error> {
+        let (client, mut connection) = tokio_postgres::connect(db_url, NoTls).await?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        // The connection delivers the notifications, it runs until the feed is dropped
+        tokio::spawn(async move {
+            while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        let change = match serde_json::from_str(notification.payload()) {
+                            Ok(change) => change,
+                            Err(e) => {
+                                warn!(
+                                    "change feed: invalid payload on {}: {e}",
+                                    notification.channel()
+                                );
+                                continue;
+                            }
+                        };
+                        if sender.send(Ok(change)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("change feed connection error: {e}");
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { client, receiver })
+    }
+
+    /// Subscribes to the committed batches of the monitor `name`
+    pub async fn listen(&self, name: &str) -> Result<(), anyhow::Error> {
+        self.client
+            .batch_execute(&format!("LISTEN \"{}\"", change_channel(name)))
+            .await?;
+
+        Ok(())
+    }
+
+    /// The next committed batch, `None` once the connection is closed and an error when it failed
+    pub async fn recv(&mut self) -> Result<Option<ChangeNotification>, anyhow::Error> {
+        match self.receiver.recv().await {
+            Some(change) => Ok(Some(change?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::db::notify_changes;
+
+    const DB_URL: &str = "host=localhost user=postgres";
+
+    #[test]
+    pub fn test_ids_are_compacted_into_runs() {
+        let events = [
+            Event {
+                block_number: 12,
+                ..Default::default()
+            },
+            Event {
+                block_number: 10,
+                removed: true,
+                ..Default::default()
+            },
+        ];
+        let change = ChangeNotification::new("uni", &events, &[5, 3, 4, 9]).unwrap();
+
+        assert_eq!(change.from_block, 10);
+        assert_eq!(change.to_block, 12);
+        assert_eq!(change.ids, vec![[3, 5], [9, 9]]);
+        assert!(change.reorg);
+        assert!(ChangeNotification::new("uni", &[], &[]).is_none());
+    }
+
+    #[test]
+    pub fn test_payload_drops_ids_that_do_not_fit() {
+        let events = [Event::default()];
+        let ids: Vec<i64> = (0..4000).map(|id| id * 2).collect();
+        let change = ChangeNotification::new("uni", &events, &ids).unwrap();
+
+        let payload: ChangeNotification = serde_json::from_str(&change.payload().unwrap()).unwrap();
+        assert!(payload.truncated);
+        assert!(payload.ids.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    pub async fn test_change_feed_receives_notifications() {
+        let mut feed = ChangeFeed::connect(DB_URL).await.unwrap();
+        feed.listen("feed_test").await.unwrap();
+
+        let change = ChangeNotification::new("feed_test", &[Event::default()], &[1]).unwrap();
+        let client = crate::db::create_db_instance(DB_URL).await.unwrap();
+        notify_changes(&client, &change).await.unwrap();
+
+        assert_eq!(feed.recv().await.unwrap(), Some(change));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    pub async fn test_change_feed_returns_connection_errors() {
+        let mut feed = ChangeFeed::connect(DB_URL).await.unwrap();
+        let pid: i32 = feed
+            .client
+            .query_one("SELECT pg_backend_pid()", &[])
+            .await
+            .unwrap()
+            .get(0);
+
+        let client = crate::db::create_db_instance(DB_URL).await.unwrap();
+        client
+            .execute("SELECT pg_terminate_backend($1)", &[&pid])
+            .await
+            .unwrap();
+
+        assert!(feed.recv().await.is_err());
+    }
+}
@@ -0,0 +1,728 @@
+use anyhow::{anyhow, bail};
+use async_graphql::parser::{
+    parse_schema,
+    types::{BaseType, ConstDirective, TypeKind, TypeSystemDefinition},
+};
+use async_graphql::{Name, Value as GqlValue};
+use serde_json::Value;
+
+/// The scalars an entity field may hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarType {
+    Id,
+    String,
+    /// 32 bit signed integer
+    Int,
+    /// Arbitrary precision integer, e.g. a token amount, exchanged as a decimal string
+    BigInt,
+    /// Arbitrary precision decimal, exchanged as a decimal string
+    BigDecimal,
+    Float,
+    Boolean,
+    /// Hex string, e.g. an address or a hash
+    Bytes,
+}
+
+impl ScalarType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "ID" => Self::Id,
+            "String" => Self::String,
+            "Int" => Self::Int,
+            "BigInt" => Self::BigInt,
+            "BigDecimal" => Self::BigDecimal,
+            "Float" => Self::Float,
+            "Boolean" => Self::Boolean,
+            "Bytes" => Self::Bytes,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Id => "ID",
+            Self::String => "String",
+            Self::Int => "Int",
+            Self::BigInt => "BigInt",
+            Self::BigDecimal => "BigDecimal",
+            Self::Float => "Float",
+            Self::Boolean => "Boolean",
+            Self::Bytes => "Bytes",
+        }
+    }
+
+    /// The column type, also used to cast the text parameters bound to the column
+    fn sql_type(&self) -> &'static str {
+        match self {
+            Self::Id | Self::String | Self::Bytes => "VARCHAR",
+            Self::Int => "INTEGER",
+            Self::BigInt | Self::BigDecimal => "NUMERIC",
+            Self::Float => "DOUBLE PRECISION",
+            Self::Boolean => "BOOLEAN",
+        }
+    }
+
+    /// Ordered scalars accept the range filters
+    pub fn is_ordered(&self) -> bool {
+        !matches!(self, Self::Boolean)
+    }
+}
+
+/// What an entity field holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldKind {
+    Scalar(ScalarType),
+    /// A list of scalars, stored as JSONB
+    ScalarList(ScalarType),
+    /// The id of another entity
+    Relation(String),
+    /// The entities of type `entity` whose relation `field` points to this one, not stored
+    Derived {
+        entity: String,
+        field: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityField {
+    /// The name in the schema, also the key of the field in the JSON of an entity
+    pub name: String,
+    pub kind: FieldKind,
+    pub required: bool,
+    /// Set by `@index`, relations are always indexed
+    pub indexed: bool,
+}
+
+impl EntityField {
+    /// The column of a stored field
+    pub fn column(&self) -> String {
+        format!("\"{}\"", snake_case(&self.name))
+    }
+
+    pub fn is_stored(&self) -> bool {
+        !matches!(self.kind, FieldKind::Derived { .. })
+    }
+
+    /// The scalar compared by the filters of the field, `None` for lists
+    pub fn filter_type(&self) -> Option<ScalarType> {
+        match &self.kind {
+            FieldKind::Scalar(scalar) => Some(*scalar),
+            FieldKind::Relation(_) => Some(ScalarType::Id),
+            _ => None,
+        }
+    }
+
+    fn cast(&self) -> &'static str {
+        match &self.kind {
+            FieldKind::Scalar(scalar) => scalar.sql_type(),
+            FieldKind::Relation(_) => "VARCHAR",
+            FieldKind::ScalarList(_) | FieldKind::Derived { .. } => "JSONB",
+        }
+    }
+
+    /// The column in the JSON of an entity, numerics as strings so that they keep their precision
+    fn json_column(&self) -> String {
+        match &self.kind {
+            FieldKind::Scalar(ScalarType::BigInt | ScalarType::BigDecimal) => {
+                format!("{}::TEXT", self.column())
+            }
+            _ => self.column(),
+        }
+    }
+
+    /// The text parameter bound for `value`, cast to the column type in the statement
+    fn param(&self, value: &Value) -> Result<Option<String>, anyhow::Error> {
+        let text = match (&self.kind, value) {
+            (_, Value::Null) if self.required => bail!("`{}` cannot be null", self.name),
+            (_, Value::Null) => return Ok(None),
+            (FieldKind::ScalarList(_), Value::Array(_)) => value.to_string(),
+            (FieldKind::Scalar(ScalarType::Boolean), Value::Bool(value)) => value.to_string(),
+            (
+                FieldKind::Scalar(
+                    ScalarType::Int
+                    | ScalarType::BigInt
+                    | ScalarType::BigDecimal
+                    | ScalarType::Float,
+                ),
+                Value::Number(value),
+            ) => value.to_string(),
+            (FieldKind::Scalar(ScalarType::Boolean) | FieldKind::ScalarList(_), _) => {
+                bail!("`{}` cannot hold {value}", self.name)
+            }
+            (FieldKind::Scalar(_) | FieldKind::Relation(_), Value::String(value)) => value.clone(),
+            _ => bail!("`{}` cannot hold {value}", self.name),
+        };
+
+        Ok(Some(text))
+    }
+}
+
+/// An object type of the schema, stored in `<monitor>_<snake_case name>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityType {
+    pub name: String,
+    /// Declared order, `id` included
+    pub fields: Vec<EntityField>,
+}
+
+impl EntityType {
+    pub fn table(&self, monitor: &str) -> String {
+        format!("{monitor}_{}", snake_case(&self.name))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&EntityField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn stored_fields(&self) -> impl Iterator<Item = &EntityField> {
+        self.fields.iter().filter(|field| field.is_stored())
+    }
+
+    /// The statements creating the table and its indexes
+    pub fn create_sql(&self, monitor: &str) -> String {
+        let table = self.table(monitor);
+        let mut columns = Vec::new();
+        let mut indexes = Vec::new();
+        for field in self.stored_fields() {
+            if field.name == "id" {
+                columns.push(format!("{} VARCHAR PRIMARY KEY", field.column()));
+                continue;
+            }
+            let null = if field.required { "NOT NULL" } else { "NULL" };
+            columns.push(format!("{} {} {null}", field.column(), field.cast()));
+            if field.indexed || matches!(field.kind, FieldKind::Relation(_)) {
+                indexes.push(format!(
+                    "CREATE INDEX IF NOT EXISTS {table}_{}_idx ON {table} ({});",
+                    snake_case(&field.name),
+                    field.column()
+                ));
+            }
+        }
+        columns.push("_block_number BIGINT NOT NULL".to_string());
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {table} ({});\n{}",
+            columns.join(", "),
+            indexes.join("\n")
+        )
+    }
+
+    /// Selects the rows as JSON objects keyed by field name
+    fn select_sql(&self, monitor: &str) -> String {
+        let pairs: Vec<String> = self
+            .stored_fields()
+            .map(|field| format!("'{}', {}", field.name, field.json_column()))
+            .collect();
+
+        format!(
+            "SELECT json_build_object({})::TEXT FROM {}",
+            pairs.join(", "),
+            self.table(monitor)
+        )
+    }
+
+    /// Creates or replaces the entity `id` with the fields of `data`, written at `block_number`.
+    /// The fields `data` lacks keep their value.
+    pub fn upsert_sql(
+        &self,
+        monitor: &str,
+        id: &str,
+        data: &Value,
+        block_number: u64,
+    ) -> Result<(String, Vec<Option<String>>), anyhow::Error> {
+        let data = data
+            .as_object()
+            .ok_or_else(|| anyhow!("{} {id} is not a JSON object", self.name))?;
+        let mut columns = vec!["\"id\"".to_string()];
+        let mut values = vec!["$1::TEXT".to_string()];
+        let mut params = vec![Some(id.to_string())];
+        if let Some(key) = data
+            .keys()
+            .find(|key| !self.field(key).is_some_and(|field| field.is_stored()))
+        {
+            bail!("{} has no stored field `{key}`", self.name);
+        }
+        // In declared order, so that the statement of a set of fields never changes
+        for field in self.stored_fields().filter(|field| field.name != "id") {
+            let Some(value) = data.get(&field.name) else {
+                continue;
+            };
+            params.push(field.param(value)?);
+            columns.push(field.column());
+            values.push(format!("${}::TEXT::{}", params.len(), field.cast()));
+        }
+        params.push(Some(block_number.to_string()));
+        columns.push("_block_number".to_string());
+        values.push(format!("${}::TEXT::BIGINT", params.len()));
+
+        let updates: Vec<String> = columns[1..]
+            .iter()
+            .map(|column| format!("{column} = EXCLUDED.{column}"))
+            .collect();
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT (\"id\") DO UPDATE SET {}",
+            self.table(monitor),
+            columns.join(", "),
+            values.join(", "),
+            updates.join(", ")
+        );
+
+        Ok((sql, params))
+    }
+
+    /// Reads the entities matching `query`
+    pub fn query_sql(
+        &self,
+        monitor: &str,
+        query: &EntityQuery,
+    ) -> Result<(String, Vec<Option<String>>), anyhow::Error> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        for filter in query.filters.iter() {
+            let field = self
+                .field(&filter.field)
+                .ok_or_else(|| anyhow!("{} has no field `{}`", self.name, filter.field))?;
+            let scalar = field
+                .filter_type()
+                .ok_or_else(|| anyhow!("`{}` cannot be filtered", field.name))?;
+            params.push(Some(filter.value.clone()));
+            let param = format!("${}::TEXT", params.len());
+            let cast = scalar.sql_type();
+            let column = field.column();
+            conditions.push(match filter.op {
+                FilterOp::Eq => format!("{column} = {param}::{cast}"),
+                FilterOp::Not => format!("{column} IS DISTINCT FROM {param}::{cast}"),
+                FilterOp::Gt => format!("{column} > {param}::{cast}"),
+                FilterOp::Gte => format!("{column} >= {param}::{cast}"),
+                FilterOp::Lt => format!("{column} < {param}::{cast}"),
+                FilterOp::Lte => format!("{column} <= {param}::{cast}"),
+                FilterOp::In => format!(
+                    "{column} IN (SELECT value::{cast} FROM jsonb_array_elements_text({param}::JSONB))"
+                ),
+                FilterOp::Contains => format!("{column} LIKE '%' || {param} || '%'"),
+            });
+        }
+
+        let mut sql = self.select_sql(monitor);
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+        let order = match &query.order_by {
+            Some(field) => self
+                .field(field)
+                .filter(|field| field.filter_type().is_some())
+                .ok_or_else(|| anyhow!("{} cannot be ordered by `{field}`", self.name))?
+                .column(),
+            None => "\"id\"".to_string(),
+        };
+        let direction = if query.descending { "DESC" } else { "ASC" };
+        sql.push_str(&format!(
+            " ORDER BY {order} {direction} LIMIT {} OFFSET {}",
+            query.first, query.skip
+        ));
+
+        Ok((sql, params))
+    }
+}
+
+/// The comparisons of the filters, named by their suffix in the GraphQL filter inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Not,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// The value is a JSON array
+    In,
+    /// Only for `String` fields
+    Contains,
+}
+
+impl FilterOp {
+    pub const ALL: [Self; 8] = [
+        Self::Eq,
+        Self::Not,
+        Self::Gt,
+        Self::Gte,
+        Self::Lt,
+        Self::Lte,
+        Self::In,
+        Self::Contains,
+    ];
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Eq => "",
+            Self::Not => "_not",
+            Self::Gt => "_gt",
+            Self::Gte => "_gte",
+            Self::Lt => "_lt",
+            Self::Lte => "_lte",
+            Self::In => "_in",
+            Self::Contains => "_contains",
+        }
+    }
+
+    /// Whether fields of type `scalar` have this filter
+    pub fn applies_to(&self, scalar: ScalarType) -> bool {
+        match self {
+            Self::Eq | Self::Not | Self::In => true,
+            Self::Gt | Self::Gte | Self::Lt | Self::Lte => scalar.is_ordered(),
+            Self::Contains => scalar == ScalarType::String,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityFilter {
+    pub field: String,
+    pub op: FilterOp,
+    /// The compared value as text
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityQuery {
+    pub filters: Vec<EntityFilter>,
+    pub order_by: Option<String>,
+    pub descending: bool,
+    pub first: u64,
+    pub skip: u64,
+}
+
+impl Default for EntityQuery {
+    fn default() -> Self {
+        Self {
+            filters: Vec::new(),
+            order_by: None,
+            descending: false,
+            first: 100,
+            skip: 0,
+        }
+    }
+}
+
+/// The entities of a monitor, declared in a GraphQL schema file like the one of a subgraph:
+/// ```graphql
+/// type Account @entity {
+///   id: ID!
+///   balance: BigInt! @index
+///   sent: [Transfer!]! @derivedFrom(field: "from")
+/// }
+///
+/// type Transfer @entity {
+///   id: ID!
+///   from: Account!
+///   value: BigInt!
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntitySchema {
+    pub entities: Vec<EntityType>,
+}
+
+impl EntitySchema {
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let sdl = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("could not read the schema {path}: {err}"))?;
+        Self::parse(&sdl).map_err(|err| anyhow!("{path}: {err}"))
+    }
+
+    pub fn parse(sdl: &str) -> Result<Self, anyhow::Error> {
+        let document = parse_schema(sdl)?;
+        let mut entities = Vec::new();
+        for definition in document.definitions {
+            let TypeSystemDefinition::Type(definition) = definition else {
+                bail!("only `type` definitions are supported");
+            };
+            let definition = definition.node;
+            let name = definition.name.node.to_string();
+            let TypeKind::Object(object) = definition.kind else {
+                bail!("`{name}` is not an object type");
+            };
+            if !has_directive(&definition.directives, "entity") {
+                bail!("`{name}` is not marked with @entity");
+            }
+
+            let mut fields = Vec::new();
+            for field in object.fields {
+                let field = field.node;
+                let field_name = field.name.node.to_string();
+                let ty = field.ty.node;
+                let derived = field
+                    .directives
+                    .iter()
+                    .find(|directive| directive.node.name.node == "derivedFrom")
+                    .map(|directive| match directive.node.get_argument("field") {
+                        Some(value) => match &value.node {
+                            GqlValue::String(field) => Ok(field.clone()),
+                            _ => Err(anyhow!(
+                                "@derivedFrom(field:) of `{field_name}` is not a string"
+                            )),
+                        },
+                        None => Err(anyhow!(
+                            "@derivedFrom of `{field_name}` has no field argument"
+                        )),
+                    })
+                    .transpose()?;
+
+                let kind = match (&ty.base, derived) {
+                    (BaseType::List(inner), Some(field)) => match &inner.base {
+                        BaseType::Named(entity) => FieldKind::Derived {
+                            entity: entity.to_string(),
+                            field,
+                        },
+                        BaseType::List(_) => bail!("`{name}.{field_name}` is a nested list"),
+                    },
+                    (BaseType::Named(entity), Some(field)) => FieldKind::Derived {
+                        entity: entity.to_string(),
+                        field,
+                    },
+                    (BaseType::List(inner), None) => match &inner.base {
+                        BaseType::Named(scalar) => FieldKind::ScalarList(
+                            ScalarType::from_name(scalar).ok_or_else(|| {
+                                anyhow!("`{name}.{field_name}` lists entities without @derivedFrom")
+                            })?,
+                        ),
+                        BaseType::List(_) => bail!("`{name}.{field_name}` is a nested list"),
+                    },
+                    (BaseType::Named(named), None) => match ScalarType::from_name(named) {
+                        Some(scalar) => FieldKind::Scalar(scalar),
+                        None => FieldKind::Relation(named.to_string()),
+                    },
+                };
+                fields.push(EntityField {
+                    indexed: has_directive(&field.directives, "index"),
+                    required: !ty.nullable,
+                    name: field_name,
+                    kind,
+                });
+            }
+            entities.push(EntityType { name, fields });
+        }
+
+        let schema = Self { entities };
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    pub fn entity(&self, name: &str) -> Option<&EntityType> {
+        self.entities.iter().find(|entity| entity.name == name)
+    }
+
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        for entity in self.entities.iter() {
+            if !is_name(&entity.name) {
+                bail!("`{}` is not a valid type name", entity.name);
+            }
+            if self
+                .entities
+                .iter()
+                .filter(|other| other.name == entity.name)
+                .count()
+                > 1
+            {
+                bail!("`{}` is declared twice", entity.name);
+            }
+            match entity.field("id") {
+                Some(field)
+                    if field.kind == FieldKind::Scalar(ScalarType::Id) && field.required => {}
+                _ => bail!("`{}` needs an `id: ID!` field", entity.name),
+            }
+            for field in entity.fields.iter() {
+                if !is_name(&field.name) {
+                    bail!("`{}.{}` is not a valid field name", entity.name, field.name);
+                }
+                match &field.kind {
+                    FieldKind::Relation(target) if self.entity(target).is_none() => {
+                        bail!(
+                            "`{}.{}` points to the unknown type `{target}`",
+                            entity.name,
+                            field.name
+                        )
+                    }
+                    FieldKind::Derived {
+                        entity: target,
+                        field: back,
+                    } => {
+                        let points_back = self
+                            .entity(target)
+                            .and_then(|target| target.field(back))
+                            .is_some_and(|back| {
+                                back.kind == FieldKind::Relation(entity.name.clone())
+                            });
+                        if !points_back {
+                            bail!(
+                                "`{}.{}` is derived from `{target}.{back}`, which is not a relation to `{}`",
+                                entity.name,
+                                field.name,
+                                entity.name
+                            );
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn has_directive(directives: &[async_graphql::Positioned<ConstDirective>], name: &str) -> bool {
+    directives
+        .iter()
+        .any(|directive| directive.node.name.node == Name::new(name))
+}
+
+/// GraphQL names end up in SQL identifiers, only letters, digits and underscores are allowed
+fn is_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `balanceUSD` => `balance_usd`, `TokenPair` => `token_pair`
+pub fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() {
+            let after_lower = i > 0 && !chars[i - 1].is_ascii_uppercase() && chars[i - 1] != '_';
+            let before_lower = i > 0
+                && chars[i - 1].is_ascii_uppercase()
+                && chars
+                    .get(i + 1)
+                    .is_some_and(|next| next.is_ascii_lowercase());
+            if after_lower || before_lower {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(*c);
+        }
+    }
+
+    snake
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Account @entity {
+          id: ID!
+          balance: BigInt! @index
+          label: String
+          sent: [Transfer!]! @derivedFrom(field: "from")
+        }
+
+        type Transfer @entity {
+          id: ID!
+          from: Account!
+          value: BigInt!
+          blockNumber: Int!
+        }
+    "#;
+
+    #[test]
+    pub fn test_schema_is_parsed_into_entities() {
+        let schema = EntitySchema::parse(SCHEMA).unwrap();
+        let account = schema.entity("Account").unwrap();
+
+        assert_eq!(account.table("uni"), "uni_account");
+        assert_eq!(
+            account.field("sent").unwrap().kind,
+            FieldKind::Derived {
+                entity: "Transfer".to_string(),
+                field: "from".to_string()
+            }
+        );
+        assert!(account.field("balance").unwrap().indexed);
+        assert!(!account.field("label").unwrap().required);
+        assert_eq!(
+            schema
+                .entity("Transfer")
+                .unwrap()
+                .field("from")
+                .unwrap()
+                .kind,
+            FieldKind::Relation("Account".to_string())
+        );
+        assert_eq!(snake_case("blockNumber"), "block_number");
+        assert_eq!(snake_case("balanceUSD"), "balance_usd");
+        assert_eq!(snake_case("TokenPair"), "token_pair");
+    }
+
+    #[test]
+    pub fn test_invalid_schemas_are_rejected() {
+        assert!(EntitySchema::parse("type Account { id: ID! }").is_err());
+        assert!(EntitySchema::parse("type Account @entity { balance: BigInt! }").is_err());
+        assert!(EntitySchema::parse("type Account @entity { id: ID! owner: Wallet! }").is_err());
+        assert!(
+            EntitySchema::parse(
+                r#"type Account @entity { id: ID! sent: [Account!]! @derivedFrom(field: "id") }"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_entity_statements() {
+        let schema = EntitySchema::parse(SCHEMA).unwrap();
+        let transfer = schema.entity("Transfer").unwrap();
+
+        let (sql, params) = transfer
+            .upsert_sql(
+                "uni",
+                "0xabc-1",
+                &serde_json::json!({"from": "0x01", "value": "1000", "blockNumber": 5}),
+                5,
+            )
+            .unwrap();
+        assert_eq!(
+            sql,
+            "INSERT INTO uni_transfer (\"id\", \"from\", \"value\", \"block_number\", _block_number) \
+             VALUES ($1::TEXT, $2::TEXT::VARCHAR, $3::TEXT::NUMERIC, $4::TEXT::INTEGER, $5::TEXT::BIGINT) \
+             ON CONFLICT (\"id\") DO UPDATE SET \"from\" = EXCLUDED.\"from\", \
+             \"value\" = EXCLUDED.\"value\", \"block_number\" = EXCLUDED.\"block_number\", \
+             _block_number = EXCLUDED._block_number"
+        );
+        assert_eq!(
+            params,
+            vec![
+                Some("0xabc-1".to_string()),
+                Some("0x01".to_string()),
+                Some("1000".to_string()),
+                Some("5".to_string()),
+                Some("5".to_string()),
+            ]
+        );
+        assert!(
+            transfer
+                .upsert_sql("uni", "1", &serde_json::json!({"amount": "1"}), 5)
+                .is_err()
+        );
+
+        let query = EntityQuery {
+            filters: vec![EntityFilter {
+                field: "value".to_string(),
+                op: FilterOp::Gte,
+                value: "10".to_string(),
+            }],
+            order_by: Some("blockNumber".to_string()),
+            descending: true,
+            first: 10,
+            skip: 20,
+        };
+        let (sql, params) = transfer.query_sql("uni", &query).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT json_build_object('id', \"id\", 'from', \"from\", 'value', \"value\"::TEXT, \
+             'blockNumber', \"block_number\")::TEXT FROM uni_transfer \
+             WHERE \"value\" >= $1::TEXT::NUMERIC ORDER BY \"block_number\" DESC LIMIT 10 OFFSET 20"
+        );
+        assert_eq!(params, vec![Some("10".to_string())]);
+    }
+}
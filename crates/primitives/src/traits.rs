@@ -1,4 +1,7 @@
-use crate::monitor::Tx;
+use crate::{
+    MonitorConfig,
+    monitor::{EventContext, OutboxEntry, Tx},
+};
 use async_trait::async_trait;
 
 /// The purpose of event monitoring triat is that it would be shared across many supported chains
@@ -47,5 +50,61 @@ pub trait TransactionMonitor {
         callback: F,
     ) -> Result<(), anyhow::Error>
     where
-        F: FnMut(Vec<Tx>);
+        F: FnMut(Vec<Tx>) + Send;
+}
+
+/// A chain family the monitors can run on, selected by the `state_machine` key of a monitor.
+/// Supporting a new family means implementing this trait, usually on top of [`EventMonitor`] and
+/// [`TransactionMonitor`] implementations for that chain, and registering it in the adapter
+/// registry of the monitor crate.
+#[async_trait]
+pub trait ChainAdapter: Send + Sync {
+    /// The `state_machine` value served by this adapter, matched case insensitively
+    fn state_machine(&self) -> &'static str;
+
+    /// Connects to `config.rpc_url` and runs the monitor until it fails or its subscriptions end
+    async fn run(
+        &self,
+        config: &MonitorConfig,
+        db_client: &mut tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error>;
+}
+
+/// A destination the committed batches of a monitor are forwarded to, e.g. a webhook.
+/// Batches are read from the outbox of the monitor and handed over one at a time, in commit order.
+/// A batch is only considered delivered once `deliver` returns `Ok`, so a batch may be delivered
+/// again after a failure or a restart and receivers should deduplicate on its `id`.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Identifies the delivery cursor of the sink in the outbox of the monitor
+    fn name(&self) -> &str;
+
+    /// Delivers a batch of the monitor `monitor`, an error makes the same batch be retried
+    async fn deliver(&self, monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error>;
+}
+
+/// Custom logic run on the events of a monitor, like the mappings of a subgraph.
+/// Handlers are called once per stored event, in the order the events are stored, inside the db
+/// transaction storing them: whatever they write to their own tables commits or rolls back with
+/// the events. An error aborts the batch, which then fails the monitor.
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// Creates the tables of the handler, called once before the monitor starts indexing
+    async fn setup(&self, _db_client: &tokio_postgres::Client) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Applies a new event of the monitor
+    async fn handle(
+        &self,
+        ctx: &EventContext,
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Undoes `handle` for an event removed from the canonical chain by a reorg
+    async fn revert(
+        &self,
+        ctx: &EventContext,
+        db_tx: &tokio_postgres::Transaction<'_>,
+    ) -> Result<(), anyhow::Error>;
 }
@@ -1,24 +1,36 @@
 use async_graphql::{Context, Object};
 use primitives::{
     ServerConfig,
-    db::{create_db_instance, get_all_events, get_events_by_block_number, get_events_by_tx_hash},
-    monitor::DisplayEvent,
+    db::{
+        create_db_instance, get_all_events, get_call_results, get_events_by_block_number,
+        get_events_by_tx_hash, get_internal_calls, get_pending_transactions, get_slot_changes,
+        get_token_approvals, get_token_balance, get_token_balance_at_block, get_token_holders,
+        get_token_transfers, get_transactions,
+    },
+    monitor::{
+        DisplayCallResult, DisplayEvent, DisplayInternalCall, DisplayPendingTx, DisplaySlotChange,
+        DisplayTokenApproval, DisplayTokenTransfer, DisplayTx, TokenHolder,
+    },
 };
 
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
-    async fn get_all_events<'a>(&self, cxt: &Context<'a>, name: String) -> Vec<DisplayEvent> {
+    /// Events of a monitor, optionally only those indexed from the chain `chain_id`
+    async fn get_all_events<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        chain_id: Option<String>,
+    ) -> Vec<DisplayEvent> {
         let config = cxt.data_unchecked::<ServerConfig>();
         let mut db_client = create_db_instance(&config.db_url)
             .await
             .expect("Could not connect to the db");
-        let events = get_all_events(&mut db_client, &name)
+        get_all_events(&mut db_client, &name, chain_id)
             .await
-            .expect("Could not get events from db");
-
-        events
+            .expect("Could not get events from db")
     }
 
     async fn get_events_by_tx_hash<'a>(
@@ -26,16 +38,15 @@ impl QueryRoot {
         cxt: &Context<'a>,
         name: String,
         transaction_hash: String,
+        chain_id: Option<String>,
     ) -> Vec<DisplayEvent> {
         let config = cxt.data_unchecked::<ServerConfig>();
         let mut db_client = create_db_instance(&config.db_url)
             .await
             .expect("Could not connect to the db");
-        let events = get_events_by_tx_hash(&mut db_client, &name, transaction_hash)
+        get_events_by_tx_hash(&mut db_client, &name, transaction_hash, chain_id)
             .await
-            .expect("Could not get events from db");
-
-        events
+            .expect("Could not get events from db")
     }
 
     async fn get_events_by_block_number<'a>(
@@ -43,15 +54,218 @@ impl QueryRoot {
         cxt: &Context<'a>,
         name: String,
         block_number: String,
+        chain_id: Option<String>,
     ) -> Vec<DisplayEvent> {
         let config = cxt.data_unchecked::<ServerConfig>();
         let mut db_client = create_db_instance(&config.db_url)
             .await
             .expect("Could not connect to the db");
-        let events = get_events_by_block_number(&mut db_client, &name, block_number)
+        get_events_by_block_number(&mut db_client, &name, block_number, chain_id)
+            .await
+            .expect("Could not get events from db")
+    }
+
+    /// Transfers indexed by an `erc20`, `erc721` or `erc1155` monitor,
+    /// optionally only those involving `holder` or moving `token_id`
+    async fn get_token_transfers<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        holder: Option<String>,
+        token_id: Option<String>,
+        chain_id: Option<String>,
+    ) -> Vec<DisplayTokenTransfer> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_token_transfers(&mut db_client, &name, holder, token_id, chain_id)
+            .await
+            .expect("Could not get token transfers from db")
+    }
+
+    /// Approvals indexed by a token monitor, optionally only those granted by `owner`
+    async fn get_token_approvals<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        owner: Option<String>,
+        chain_id: Option<String>,
+    ) -> Vec<DisplayTokenApproval> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_token_approvals(&mut db_client, &name, owner, chain_id)
             .await
-            .expect("Could not get events from db");
+            .expect("Could not get token approvals from db")
+    }
+
+    /// Current balance of `holder`, `token_id` is only used by ERC-1155 monitors
+    async fn get_token_balance<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        holder: String,
+        token_id: Option<String>,
+    ) -> String {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_token_balance(&mut db_client, &name, holder, token_id.unwrap_or_default())
+            .await
+            .expect("Could not get token balance from db")
+    }
+
+    /// Balance of `holder` at the end of `block_number`, needs the balance history enabled
+    async fn get_token_balance_at_block<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        holder: String,
+        block_number: i64,
+        token_id: Option<String>,
+    ) -> String {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_token_balance_at_block(
+            &mut db_client,
+            &name,
+            holder,
+            token_id.unwrap_or_default(),
+            block_number,
+        )
+        .await
+        .expect("Could not get token balance from db")
+    }
 
-        events
+    /// Holders ranked by balance, 100 per page by default
+    async fn get_token_holders<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        token_id: Option<String>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Vec<TokenHolder> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_token_holders(
+            &mut db_client,
+            &name,
+            token_id.unwrap_or_default(),
+            limit.unwrap_or(100),
+            offset.unwrap_or(0),
+        )
+        .await
+        .expect("Could not get token holders from db")
+    }
+
+    /// Transactions of a transfers or deployments monitor, optionally involving `address`
+    /// or included in `block_number`
+    async fn get_transactions<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        address: Option<String>,
+        block_number: Option<String>,
+        chain_id: Option<String>,
+    ) -> Vec<DisplayTx> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_transactions(&mut db_client, &name, address, block_number, chain_id)
+            .await
+            .expect("Could not get transactions from db")
+    }
+
+    /// Internal calls of a traces monitor, optionally of `transaction_hash` or from or to `address`.
+    /// The parent transactions are returned by `get_transactions` with the same name.
+    async fn get_internal_calls<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        transaction_hash: Option<String>,
+        address: Option<String>,
+        chain_id: Option<String>,
+    ) -> Vec<DisplayInternalCall> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_internal_calls(&mut db_client, &name, transaction_hash, address, chain_id)
+            .await
+            .expect("Could not get internal calls from db")
+    }
+
+    /// Transactions seen by a mempool monitor, optionally from or to `address` or with `status`
+    /// (pending, mined, replaced or cancelled)
+    async fn get_pending_transactions<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        address: Option<String>,
+        status: Option<String>,
+        chain_id: Option<String>,
+    ) -> Vec<DisplayPendingTx> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_pending_transactions(&mut db_client, &name, address, status, chain_id)
+            .await
+            .expect("Could not get pending transactions from db")
+    }
+
+    /// Results of the view call `call` of a polling monitor, optionally within a block range.
+    /// `values` is a JSON object keyed by output.
+    async fn get_call_results<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        call: String,
+        from_block: Option<String>,
+        to_block: Option<String>,
+    ) -> async_graphql::Result<Vec<DisplayCallResult>> {
+        // The call names the table of its results
+        if call.is_empty() || !call.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("`{call}` is not the name of a call").into());
+        }
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        let results = get_call_results(
+            &mut db_client,
+            &format!("{name}_{call}"),
+            from_block,
+            to_block,
+        )
+        .await
+        .map_err(|err| format!("Could not get the results of `{call}`: {err}"))?;
+
+        Ok(results)
+    }
+
+    /// Value changes of the slots of a storage monitor, optionally of the slot `slot_name`
+    async fn get_slot_changes<'a>(
+        &self,
+        cxt: &Context<'a>,
+        name: String,
+        slot_name: Option<String>,
+    ) -> Vec<DisplaySlotChange> {
+        let config = cxt.data_unchecked::<ServerConfig>();
+        let mut db_client = create_db_instance(&config.db_url)
+            .await
+            .expect("Could not connect to the db");
+        get_slot_changes(&mut db_client, &name, slot_name)
+            .await
+            .expect("Could not get slot changes from db")
     }
 }
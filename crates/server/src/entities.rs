@@ -0,0 +1,392 @@
+use crate::typed::{MonitorDb, TypedSchema, camel_case, json_field, parent};
+use async_graphql::Value;
+use async_graphql::dynamic::{
+    Enum, Field, FieldFuture, FieldValue, InputObject, InputValue, Object, ResolverContext, TypeRef,
+};
+use primitives::{
+    MonitorConfig,
+    db::{get_schema_entity, query_schema_entities},
+    schema::{
+        EntityField, EntityFilter, EntityQuery, EntitySchema, EntityType, FieldKind, FilterOp,
+    },
+};
+use std::sync::Arc;
+
+/// The most entities a list field returns
+const MAX_FIRST: i64 = 1000;
+
+/// The entity schema of a monitor, shared by the resolvers of its types
+struct MonitorEntities {
+    name: String,
+    schema: EntitySchema,
+    db: Arc<MonitorDb>,
+}
+
+impl MonitorEntities {
+    fn entity(&self, name: &str) -> &EntityType {
+        self.schema
+            .entity(name)
+            .expect("resolvers are only built for declared entities")
+    }
+
+    async fn get(
+        &self,
+        entity: &str,
+        id: &str,
+    ) -> Result<Option<serde_json::Value>, anyhow::Error> {
+        let client = self.db.client().await?;
+        get_schema_entity(client.as_ref(), &self.name, self.entity(entity), id).await
+    }
+
+    async fn list(
+        &self,
+        entity: &str,
+        query: &EntityQuery,
+    ) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+        let client = self.db.client().await?;
+        query_schema_entities(client.as_ref(), &self.name, self.entity(entity), query).await
+    }
+}
+
+/// Adds the entities declared by the entity schema of `monitor`, if it has one.
+/// Every entity type gets a root field reading one entity by id and a root field listing them,
+/// e.g. `account(id:)` and `accounts(where:, orderBy:, orderDirection:, first:, skip:)`.
+pub(crate) fn register(
+    schema: &mut TypedSchema,
+    monitor: &MonitorConfig,
+    db: &Arc<MonitorDb>,
+) -> Result<(), anyhow::Error> {
+    let Some(path) = &monitor.schema else {
+        return Ok(());
+    };
+    let entities = Arc::new(MonitorEntities {
+        name: monitor.event_name.clone(),
+        schema: EntitySchema::load(path)?,
+        db: db.clone(),
+    });
+    let name = &monitor.event_name;
+    for entity in entities.schema.entities.iter() {
+        schema.register(name, &entity.name, entity_object(&entities, entity))?;
+        schema.register(
+            name,
+            &format!("{}_filter", entity.name),
+            filter_input(entity),
+        )?;
+        schema.register(
+            name,
+            &format!("{}_orderBy", entity.name),
+            order_enum(entity),
+        )?;
+        let (single, list) = root_fields(&entities, entity);
+        schema.field(name, &camel_case(&entity.name), single)?;
+        schema.field(name, &format!("{}s", camel_case(&entity.name)), list)?;
+    }
+
+    Ok(())
+}
+
+fn root_fields(entities: &Arc<MonitorEntities>, entity: &EntityType) -> (Field, Field) {
+    let single = {
+        let entities = entities.clone();
+        let name = entity.name.clone();
+        Field::new(
+            camel_case(&entity.name),
+            TypeRef::named(&entity.name),
+            move |ctx| {
+                let entities = entities.clone();
+                let name = name.clone();
+                FieldFuture::new(async move {
+                    let id = ctx.args.try_get("id")?.string()?;
+                    Ok(entities.get(&name, id).await?.map(FieldValue::owned_any))
+                })
+            },
+        )
+        .argument(InputValue::new("id", TypeRef::named_nn(TypeRef::ID)))
+    };
+
+    let list = {
+        let entities = entities.clone();
+        let name = entity.name.clone();
+        Field::new(
+            format!("{}s", camel_case(&entity.name)),
+            TypeRef::named_nn_list_nn(&entity.name),
+            move |ctx| {
+                let entities = entities.clone();
+                let name = name.clone();
+                FieldFuture::new(async move {
+                    let query = entity_query(&ctx, entities.entity(&name))?;
+                    let rows = entities.list(&name, &query).await?;
+                    Ok(Some(FieldValue::list(
+                        rows.into_iter().map(FieldValue::owned_any),
+                    )))
+                })
+            },
+        )
+    };
+
+    (single, list_arguments(list, &entity.name))
+}
+
+fn list_arguments(field: Field, entity: &str) -> Field {
+    field
+        .argument(InputValue::new(
+            "where",
+            TypeRef::named(format!("{entity}_filter")),
+        ))
+        .argument(InputValue::new(
+            "orderBy",
+            TypeRef::named(format!("{entity}_orderBy")),
+        ))
+        .argument(InputValue::new(
+            "orderDirection",
+            TypeRef::named("OrderDirection"),
+        ))
+        .argument(InputValue::new("first", TypeRef::named(TypeRef::INT)).default_value(100))
+        .argument(InputValue::new("skip", TypeRef::named(TypeRef::INT)).default_value(0))
+}
+
+fn entity_object(entities: &Arc<MonitorEntities>, entity: &EntityType) -> Object {
+    let mut object = Object::new(&entity.name);
+    for field in entity.fields.iter() {
+        object = object.field(entity_field(entities, field));
+    }
+
+    object
+}
+
+fn entity_field(entities: &Arc<MonitorEntities>, field: &EntityField) -> Field {
+    let name = field.name.clone();
+    match &field.kind {
+        FieldKind::Scalar(scalar) | FieldKind::ScalarList(scalar) => {
+            let ty = match (&field.kind, field.required) {
+                (FieldKind::Scalar(_), true) => TypeRef::named_nn(scalar.name()),
+                (FieldKind::Scalar(_), false) => TypeRef::named(scalar.name()),
+                (_, true) => TypeRef::named_nn_list_nn(scalar.name()),
+                (_, false) => TypeRef::named_nn_list(scalar.name()),
+            };
+            json_field(&field.name, ty)
+        }
+        FieldKind::Relation(target) => {
+            let ty = if field.required {
+                TypeRef::named_nn(target)
+            } else {
+                TypeRef::named(target)
+            };
+            let entities = entities.clone();
+            let target = target.clone();
+            Field::new(&field.name, ty, move |ctx| {
+                let entities = entities.clone();
+                let name = name.clone();
+                let target = target.clone();
+                FieldFuture::new(async move {
+                    let Some(id) = parent(&ctx)?.get(&name).and_then(|id| id.as_str()) else {
+                        return Ok(None);
+                    };
+                    Ok(entities.get(&target, id).await?.map(FieldValue::owned_any))
+                })
+            })
+        }
+        FieldKind::Derived {
+            entity: target,
+            field: back,
+        } => {
+            let entities = entities.clone();
+            let target_name = target.clone();
+            let back = back.clone();
+            let derived = Field::new(&field.name, TypeRef::named_nn_list_nn(target), move |ctx| {
+                let entities = entities.clone();
+                let target = target_name.clone();
+                let back = back.clone();
+                FieldFuture::new(async move {
+                    let id = parent(&ctx)?
+                        .get("id")
+                        .and_then(|id| id.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    let mut query = entity_query(&ctx, entities.entity(&target))?;
+                    query.filters.push(EntityFilter {
+                        field: back,
+                        op: FilterOp::Eq,
+                        value: id,
+                    });
+                    let rows = entities.list(&target, &query).await?;
+                    Ok(Some(FieldValue::list(
+                        rows.into_iter().map(FieldValue::owned_any),
+                    )))
+                })
+            });
+            list_arguments(derived, target)
+        }
+    }
+}
+
+/// `<Entity>_filter`, e.g. `{ balance_gt: "100", label_contains: "pool" }`
+fn filter_input(entity: &EntityType) -> InputObject {
+    let mut input = InputObject::new(format!("{}_filter", entity.name));
+    for field in entity.fields.iter() {
+        let Some(scalar) = field.filter_type() else {
+            continue;
+        };
+        for op in FilterOp::ALL.iter().filter(|op| op.applies_to(scalar)) {
+            let ty = match op {
+                FilterOp::In => TypeRef::named_nn_list(scalar.name()),
+                _ => TypeRef::named(scalar.name()),
+            };
+            input = input.field(InputValue::new(
+                format!("{}{}", field.name, op.suffix()),
+                ty,
+            ));
+        }
+    }
+
+    input
+}
+
+/// `<Entity>_orderBy`, the fields a list can be ordered by
+fn order_enum(entity: &EntityType) -> Enum {
+    Enum::new(format!("{}_orderBy", entity.name)).items(
+        entity
+            .fields
+            .iter()
+            .filter(|field| field.filter_type().is_some())
+            .map(|field| field.name.clone()),
+    )
+}
+
+/// The filters, order and page of a list field
+fn entity_query(
+    ctx: &ResolverContext<'_>,
+    entity: &EntityType,
+) -> async_graphql::Result<EntityQuery> {
+    let mut query = EntityQuery::default();
+    if let Some(filter) = ctx.args.get("where") {
+        for (key, value) in filter.object()?.iter() {
+            if value.is_null() {
+                continue;
+            }
+            let (field, op) = filter_key(entity, key);
+            let value = match op {
+                FilterOp::In => serde_json::Value::Array(
+                    value
+                        .list()?
+                        .iter()
+                        .map(|value| scalar_text(value.as_value()).map(serde_json::Value::String))
+                        .collect::<async_graphql::Result<_>>()?,
+                )
+                .to_string(),
+                _ => scalar_text(value.as_value())?,
+            };
+            query.filters.push(EntityFilter {
+                field: field.to_string(),
+                op,
+                value,
+            });
+        }
+    }
+    if let Some(order_by) = ctx.args.get("orderBy") {
+        query.order_by = Some(order_by.enum_name()?.to_string());
+    }
+    if let Some(direction) = ctx.args.get("orderDirection") {
+        query.descending = direction.enum_name()? == "desc";
+    }
+    if let Some(first) = ctx.args.get("first") {
+        query.first = first.i64()?.clamp(0, MAX_FIRST) as u64;
+    }
+    if let Some(skip) = ctx.args.get("skip") {
+        query.skip = skip.i64()?.max(0) as u64;
+    }
+
+    Ok(query)
+}
+
+/// Splits a key of a filter input, e.g. `balance_gt`, into its field and comparison
+fn filter_key<'a>(entity: &EntityType, key: &'a str) -> (&'a str, FilterOp) {
+    if entity.field(key).is_some() {
+        return (key, FilterOp::Eq);
+    }
+    FilterOp::ALL
+        .iter()
+        .filter(|op| **op != FilterOp::Eq)
+        .find_map(|op| {
+            key.strip_suffix(op.suffix())
+                .filter(|field| entity.field(field).is_some())
+                .map(|field| (field, *op))
+        })
+        .unwrap_or((key, FilterOp::Eq))
+}
+
+fn scalar_text(value: &Value) -> async_graphql::Result<String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err(format!("{value} cannot be compared").into()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::typed::typed_schema;
+
+    #[test]
+    pub fn test_filter_keys_are_split_into_field_and_comparison() {
+        let schema = EntitySchema::parse(
+            "type Pool @entity { id: ID! name: String! liquidity: BigInt! token_in: String! }",
+        )
+        .unwrap();
+        let pool = schema.entity("Pool").unwrap();
+
+        assert_eq!(filter_key(pool, "name"), ("name", FilterOp::Eq));
+        assert_eq!(
+            filter_key(pool, "liquidity_gte"),
+            ("liquidity", FilterOp::Gte)
+        );
+        assert_eq!(filter_key(pool, "name_not"), ("name", FilterOp::Not));
+        assert_eq!(filter_key(pool, "id_in"), ("id", FilterOp::In));
+        // A field whose name ends like a comparison is compared as is
+        assert_eq!(filter_key(pool, "token_in"), ("token_in", FilterOp::Eq));
+    }
+
+    #[test]
+    pub fn test_entity_schema_is_generated_from_the_monitors() {
+        let path = std::env::temp_dir().join("nexus_entity_schema_test.graphql");
+        std::fs::write(
+            &path,
+            r#"
+                type Account @entity {
+                  id: ID!
+                  balance: BigInt! @index
+                  sent: [Transfer!]! @derivedFrom(field: "from")
+                }
+
+                type Transfer @entity {
+                  id: ID!
+                  from: Account!
+                  value: BigInt!
+                }
+            "#,
+        )
+        .unwrap();
+        let monitor: MonitorConfig = serde_json::from_value(serde_json::json!({
+            "event_name": "uni",
+            "rpc_url": "ws://localhost:8545",
+            "block_number": 0,
+            "db_url": "postgresql://localhost/nexus",
+            "schema": path,
+        }))
+        .unwrap();
+
+        let sdl = typed_schema(std::slice::from_ref(&monitor))
+            .unwrap()
+            .unwrap()
+            .sdl();
+        assert!(sdl.contains("accounts(where: Account_filter, orderBy: Account_orderBy"));
+        assert!(sdl.contains("transfer(id: ID!): Transfer"));
+        assert!(sdl.contains("balance_gte: BigInt"));
+        assert!(sdl.contains("from: Account!"));
+        assert!(sdl.contains("sent(where: Transfer_filter"));
+        // The same entity twice is ambiguous
+        assert!(typed_schema(&[monitor.clone(), monitor]).is_err());
+    }
+}
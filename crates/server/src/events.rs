@@ -0,0 +1,320 @@
+use crate::typed::{
+    MonitorDb, TypedSchema, camel_case, json_field, json_object_field, pascal_case,
+};
+use alloy::{
+    dyn_abi::{DynSolType, Specifier},
+    json_abi,
+    primitives::{B256, Bytes},
+};
+use anyhow::anyhow;
+use async_graphql::dynamic::{
+    Field, FieldFuture, FieldValue, InputValue, Object, ResolverContext, TypeRef,
+};
+use monitor::events::evm::decode_event_params;
+use primitives::{
+    MonitorConfig, MonitorKind,
+    db::get_events_page,
+    monitor::{DisplayEvent, EventPage, sol_value_json},
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// The most events a page returns
+const MAX_FIRST: i64 = 1000;
+
+/// Adds the root field listing the events of `monitor`, e.g. `uniTransfers` for `uni_transfers`,
+/// when it is an `event` or `factory` monitor. Its events are of the type `UniTransfersEvent`,
+/// with their decoded parameters under `params` when the monitor has an event ABI.
+pub(crate) fn register(
+    schema: &mut TypedSchema,
+    monitor: &MonitorConfig,
+    db: &Arc<MonitorDb>,
+) -> Result<(), anyhow::Error> {
+    if !matches!(monitor.kind, MonitorKind::Event | MonitorKind::Factory) {
+        return Ok(());
+    }
+    let name = &monitor.event_name;
+    let abi = monitor
+        .event_abi()
+        .map(json_abi::Event::parse)
+        .transpose()
+        .map_err(|err| anyhow!("{name}: the event ABI could not be parsed: {err}"))?;
+
+    let type_name = format!("{}Event", pascal_case(name));
+    let mut event = Object::new(&type_name)
+        .description(format!("An event indexed by the monitor `{name}`"))
+        .field(json_field("address", TypeRef::named_nn(TypeRef::STRING)))
+        .field(json_field("blockNumber", TypeRef::named_nn("BigInt")))
+        .field(json_field(
+            "transactionHash",
+            TypeRef::named_nn(TypeRef::STRING),
+        ))
+        .field(json_field(
+            "topics",
+            TypeRef::named_nn_list_nn(TypeRef::STRING),
+        ))
+        .field(json_field("data", TypeRef::named_nn("Bytes")))
+        .field(json_field("chainId", TypeRef::named_nn("BigInt")));
+
+    if monitor.join_transactions {
+        let tx_name = format!("{}Transaction", pascal_case(name));
+        let tx = Object::new(&tx_name)
+            .field(json_field("from", TypeRef::named_nn(TypeRef::STRING)))
+            .field(json_field("to", TypeRef::named(TypeRef::STRING)))
+            .field(json_field("gasUsed", TypeRef::named("BigInt")))
+            .field(json_field("methodSelector", TypeRef::named("Bytes")));
+        schema.register(name, &tx_name, tx)?;
+        event = event.field(json_object_field("transaction", TypeRef::named(&tx_name)));
+    }
+
+    if let Some(abi) = &abi {
+        let params_name = format!("{}Params", pascal_case(name));
+        let mut params = Object::new(&params_name)
+            .description(format!("The parameters of `{}`", abi.signature()));
+        for (position, input) in abi.inputs.iter().enumerate() {
+            params = params.field(json_field(
+                &param_name(position, &input.name),
+                TypeRef::named_nn(param_type(&input.resolve()?)),
+            ));
+        }
+        schema.register(name, &params_name, params)?;
+        // Null for a log the ABI does not decode
+        event = event.field(json_object_field("params", TypeRef::named(&params_name)));
+    }
+    schema.register(name, &type_name, event)?;
+
+    let monitor_name = name.clone();
+    let db = db.clone();
+    let abi = abi.map(Arc::new);
+    let field = Field::new(
+        camel_case(name),
+        TypeRef::named_nn_list_nn(&type_name),
+        move |ctx| {
+            let monitor_name = monitor_name.clone();
+            let db = db.clone();
+            let abi = abi.clone();
+            FieldFuture::new(async move {
+                let page = event_page(&ctx)?;
+                let client = db.client().await?;
+                let events = get_events_page(client.as_ref(), &monitor_name, &page).await?;
+                Ok(Some(FieldValue::list(events.into_iter().map(|event| {
+                    FieldValue::owned_any(event_value(event, abi.as_deref()))
+                }))))
+            })
+        },
+    )
+    .description(format!("The events indexed by the monitor `{name}`"))
+    .argument(InputValue::new("address", TypeRef::named(TypeRef::STRING)))
+    .argument(InputValue::new(
+        "transactionHash",
+        TypeRef::named(TypeRef::STRING),
+    ))
+    .argument(InputValue::new("fromBlock", TypeRef::named("BigInt")))
+    .argument(InputValue::new("toBlock", TypeRef::named("BigInt")))
+    .argument(InputValue::new("chainId", TypeRef::named("BigInt")))
+    .argument(InputValue::new(
+        "orderDirection",
+        TypeRef::named("OrderDirection"),
+    ))
+    .argument(InputValue::new("first", TypeRef::named(TypeRef::INT)).default_value(100))
+    .argument(InputValue::new("skip", TypeRef::named(TypeRef::INT)).default_value(0));
+
+    schema.field(name, &camel_case(name), field)
+}
+
+/// An unnamed parameter is named after its position
+fn param_name(position: usize, name: &str) -> String {
+    if name.is_empty() {
+        format!("param{position}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// The GraphQL type of a decoded parameter, see `sol_value_json`
+fn param_type(ty: &DynSolType) -> &'static str {
+    match ty {
+        DynSolType::Bool => TypeRef::BOOLEAN,
+        DynSolType::Int(_) | DynSolType::Uint(_) => "BigInt",
+        DynSolType::Address | DynSolType::Function | DynSolType::String => TypeRef::STRING,
+        DynSolType::Bytes | DynSolType::FixedBytes(_) => "Bytes",
+        _ => "JSON",
+    }
+}
+
+/// An event in JSON, keyed by the fields of its type
+fn event_value(event: DisplayEvent, abi: Option<&json_abi::Event>) -> serde_json::Value {
+    let params = abi.and_then(|abi| {
+        let topics = event
+            .topics
+            .iter()
+            .map(|topic| topic.parse::<B256>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        let data = event.data.parse::<Bytes>().ok()?;
+        let params = decode_event_params(abi, &topics, &data).ok()?;
+
+        Some(serde_json::Value::Object(
+            params
+                .iter()
+                .enumerate()
+                .map(|(position, (name, value))| {
+                    (param_name(position, name), sol_value_json(value))
+                })
+                .collect(),
+        ))
+    });
+
+    json!({
+        "address": event.address,
+        "blockNumber": event.block_number,
+        "transactionHash": event.transaction_hash,
+        "topics": event.topics,
+        "data": event.data,
+        "chainId": event.chain_id,
+        "transaction": event.transaction.map(|tx| json!({
+            "from": tx.from,
+            "to": tx.to,
+            "gasUsed": tx.gas_used,
+            "methodSelector": tx.method_selector,
+        })),
+        "params": params,
+    })
+}
+
+/// The filters and page of the events field
+fn event_page(ctx: &ResolverContext<'_>) -> async_graphql::Result<EventPage> {
+    let number = |name: &str| -> async_graphql::Result<Option<u64>> {
+        match ctx.args.get(name) {
+            Some(value) => match value.as_value() {
+                async_graphql::Value::Number(number) => number
+                    .as_u64()
+                    .map(Some)
+                    .ok_or_else(|| format!("`{name}` is not a block or chain id").into()),
+                async_graphql::Value::String(number) => Ok(Some(number.parse()?)),
+                _ => Err(format!("`{name}` is not a block or chain id").into()),
+            },
+            None => Ok(None),
+        }
+    };
+
+    Ok(EventPage {
+        address: ctx
+            .args
+            .get("address")
+            .map(|address| Ok::<_, async_graphql::Error>(address.string()?.parse()?))
+            .transpose()?,
+        transaction_hash: ctx
+            .args
+            .get("transactionHash")
+            .map(|hash| Ok::<_, async_graphql::Error>(hash.string()?.parse()?))
+            .transpose()?,
+        from_block: number("fromBlock")?,
+        to_block: number("toBlock")?,
+        chain_id: number("chainId")?,
+        first: match ctx.args.get("first") {
+            Some(first) => first.i64()?.clamp(0, MAX_FIRST) as u64,
+            None => 100,
+        },
+        skip: match ctx.args.get("skip") {
+            Some(skip) => skip.i64()?.max(0) as u64,
+            None => 0,
+        },
+        descending: match ctx.args.get("orderDirection") {
+            Some(direction) => direction.enum_name()? == "desc",
+            None => false,
+        },
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::typed::typed_schema;
+    use alloy::primitives::{U256, address};
+
+    fn monitor(config: serde_json::Value) -> MonitorConfig {
+        let mut monitor = json!({
+            "event_name": "uni_transfers",
+            "rpc_url": "ws://localhost:8545",
+            "block_number": 0,
+            "db_url": "postgresql://localhost/nexus",
+        });
+        monitor
+            .as_object_mut()
+            .unwrap()
+            .extend(config.as_object().unwrap().clone());
+        serde_json::from_value(monitor).unwrap()
+    }
+
+    #[test]
+    pub fn test_monitors_get_typed_event_fields() {
+        let monitors = [
+            monitor(json!({
+                "abi": "Transfer(address indexed from, address indexed to, uint256 value)",
+                "join_transactions": true,
+            })),
+            monitor(json!({"event_name": "usdc_balances", "kind": "erc20"})),
+        ];
+
+        let sdl = typed_schema(&monitors).unwrap().unwrap().sdl();
+        assert!(sdl.contains(
+            "uniTransfers(address: String, transactionHash: String, fromBlock: BigInt, \
+             toBlock: BigInt, chainId: BigInt, orderDirection: OrderDirection, \
+             first: Int = 100, skip: Int = 0): [UniTransfersEvent!]!"
+        ));
+        assert!(sdl.contains("params: UniTransfersParams"));
+        assert!(sdl.contains("value: BigInt!"));
+        assert!(sdl.contains("transaction: UniTransfersTransaction"));
+        // Only event and factory monitors store events
+        assert!(!sdl.contains("usdcBalances"));
+
+        // Without an ABI the raw log is served, two monitors cannot share a field
+        let sdl = typed_schema(&[monitor(json!({}))]).unwrap().unwrap().sdl();
+        assert!(!sdl.contains("params"));
+        assert!(typed_schema(&[monitor(json!({})), monitor(json!({}))]).is_err());
+        assert!(typed_schema(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    pub fn test_event_values_decode_params() {
+        let abi =
+            json_abi::Event::parse("Transfer(address indexed from, address indexed to, uint256)")
+                .unwrap();
+        let from = address!("1f9840a85d5af5bf1d1762f925bdaddc4201f984");
+        let to = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let event = DisplayEvent::new(
+            from.to_string(),
+            "7".to_string(),
+            B256::ZERO.to_string(),
+            vec![
+                abi.selector().to_string(),
+                from.into_word().to_string(),
+                to.into_word().to_string(),
+            ],
+            Bytes::from(U256::from(1000).to_be_bytes_vec()).to_string(),
+            "1".to_string(),
+        );
+
+        let value = event_value(event.clone(), Some(&abi));
+        assert_eq!(value["blockNumber"], "7");
+        assert_eq!(
+            value["params"],
+            json!({
+                "from": "0x1f9840a85d5aF5bf1D1762F925BDADdC4201F984",
+                "to": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+                "param2": "1000",
+            })
+        );
+
+        // A log of another event keeps its raw fields
+        let other = DisplayEvent {
+            topics: vec![B256::ZERO.to_string()],
+            ..event
+        };
+        assert_eq!(
+            event_value(other, Some(&abi))["params"],
+            serde_json::Value::Null
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use async_graphql::{EmptyMutation, EmptySubscription, ObjectType, Schema, http::GraphiQLSource};
+use async_graphql::{
+    EmptyMutation, EmptySubscription, ObjectType, Schema, dynamic, http::GraphiQLSource,
+};
 use async_graphql_axum::GraphQL;
 use axum::{
     Router,
@@ -9,18 +11,28 @@ use primitives::ServerConfig;
 use tokio::net::TcpListener;
 
 pub mod db_query;
+pub mod entities;
+pub mod events;
+pub mod typed;
 
 /// This function is used to serve the graphQL server and GraphiQL IDE.
 async fn graphiql() -> impl IntoResponse {
     response::Html(GraphiQLSource::build().endpoint("/").finish())
 }
 
+/// GraphiQL for the typed schema
+async fn typed_graphiql() -> impl IntoResponse {
+    response::Html(GraphiQLSource::build().endpoint("/typed").finish())
+}
+
 /// This function is used to run the server.
 /// `[DB]` This is a generic type, which is used to store the database.
 /// `[Query]` This is a gaint Query entity, for all the Events enitities and all the tx enitities.
+/// `[typed]` The schema generated from the monitors, served on `/typed`
 pub async fn run_server<Query>(
     config: ServerConfig,
     query: Query,
+    typed: Option<dynamic::Schema>,
 ) -> Result<(), anyhow::Error>
 where
     Query: ObjectType + 'static,
@@ -29,7 +41,13 @@ where
     let schema = Schema::build(query, EmptyMutation, EmptySubscription)
         .data(config)
         .finish();
-    let app = Router::new().route("/", get(graphiql).post_service(GraphQL::new(schema)));
+    let mut app = Router::new().route("/", get(graphiql).post_service(GraphQL::new(schema)));
+    if let Some(typed) = typed {
+        app = app.route(
+            "/typed",
+            get(typed_graphiql).post_service(GraphQL::new(typed)),
+        );
+    }
 
     tracing::info!(url);
     axum::serve(TcpListener::bind(url).await.unwrap(), app)
@@ -0,0 +1,203 @@
+use crate::{entities, events};
+use anyhow::bail;
+use async_graphql::{
+    Value,
+    dynamic::{
+        Enum, Field, FieldFuture, FieldValue, Object, ResolverContext, Scalar, Schema, Type,
+        TypeRef,
+    },
+};
+use primitives::{MonitorConfig, db::create_db_instance};
+use std::{collections::HashSet, sync::Arc};
+use tokio::sync::Mutex;
+
+/// The root fields and types gathered from the monitors, checked for name clashes
+pub(crate) struct TypedSchema {
+    query: Object,
+    types: Vec<Type>,
+    fields: HashSet<String>,
+    type_names: HashSet<String>,
+}
+
+impl TypedSchema {
+    fn new() -> Self {
+        Self {
+            query: Object::new("Query"),
+            types: Vec::new(),
+            fields: HashSet::new(),
+            type_names: [
+                "Query",
+                "OrderDirection",
+                "BigInt",
+                "BigDecimal",
+                "Bytes",
+                "JSON",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        }
+    }
+
+    /// Adds the root field `name` of the monitor `monitor`
+    pub(crate) fn field(
+        &mut self,
+        monitor: &str,
+        name: &str,
+        field: Field,
+    ) -> Result<(), anyhow::Error> {
+        if !self.fields.insert(name.to_string()) {
+            bail!("{monitor}: the query field `{name}` is generated by another monitor");
+        }
+        self.query = std::mem::replace(&mut self.query, Object::new("Query")).field(field);
+        Ok(())
+    }
+
+    /// Adds the type `name` of the monitor `monitor`
+    pub(crate) fn register(
+        &mut self,
+        monitor: &str,
+        name: &str,
+        ty: impl Into<Type>,
+    ) -> Result<(), anyhow::Error> {
+        if !self.type_names.insert(name.to_string()) {
+            bail!("{monitor}: the type `{name}` is generated by another monitor or reserved");
+        }
+        self.types.push(ty.into());
+        Ok(())
+    }
+}
+
+/// The typed GraphQL schema of the monitors, `None` when none has anything to serve.
+/// It has root fields listing the events of every `event` and `factory` monitor, with their
+/// decoded parameters when the monitor has an event ABI, and root fields for the entities
+/// declared by the entity schemas of the monitors.
+pub fn typed_schema(monitors: &[MonitorConfig]) -> Result<Option<Schema>, anyhow::Error> {
+    let mut schema = TypedSchema::new();
+    for monitor in monitors.iter() {
+        let db = Arc::new(MonitorDb::new(&monitor.db_url));
+        events::register(&mut schema, monitor, &db)?;
+        entities::register(&mut schema, monitor, &db)?;
+    }
+    if schema.fields.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = Schema::build("Query", None, None)
+        .register(schema.query)
+        .register(Enum::new("OrderDirection").item("asc").item("desc"))
+        .register(Scalar::new("BigInt").description("An integer of any size, as a decimal string"))
+        .register(Scalar::new("BigDecimal").description("A decimal of any size, as a string"))
+        .register(Scalar::new("Bytes").description("Bytes as a 0x prefixed hex string"))
+        .register(Scalar::new("JSON").description("A decoded value without a GraphQL type"));
+    for ty in schema.types {
+        builder = builder.register(ty);
+    }
+
+    Ok(Some(builder.finish()?))
+}
+
+/// A connection to the db of a monitor, shared by its resolvers
+pub(crate) struct MonitorDb {
+    db_url: String,
+    client: Mutex<Option<Arc<tokio_postgres::Client>>>,
+}
+
+impl MonitorDb {
+    fn new(db_url: &str) -> Self {
+        Self {
+            db_url: db_url.to_string(),
+            client: Mutex::new(None),
+        }
+    }
+
+    /// The connection, opened again once it is closed
+    pub(crate) async fn client(&self) -> Result<Arc<tokio_postgres::Client>, anyhow::Error> {
+        let mut client = self.client.lock().await;
+        match client.as_ref() {
+            Some(open) if !open.is_closed() => Ok(open.clone()),
+            _ => {
+                let open = Arc::new(create_db_instance(&self.db_url).await?);
+                *client = Some(open.clone());
+                Ok(open)
+            }
+        }
+    }
+}
+
+/// The JSON object a field belongs to
+pub(crate) fn parent<'a>(
+    ctx: &'a ResolverContext<'_>,
+) -> async_graphql::Result<&'a serde_json::Value> {
+    ctx.parent_value.try_downcast_ref::<serde_json::Value>()
+}
+
+/// A field reading the key `name` of the JSON object it belongs to
+pub(crate) fn json_field(name: &str, ty: TypeRef) -> Field {
+    let key = name.to_string();
+    Field::new(name, ty, move |ctx| {
+        let key = key.clone();
+        FieldFuture::new(async move {
+            match parent(&ctx)?.get(&key) {
+                None | Some(serde_json::Value::Null) => Ok(None),
+                Some(value) => Ok(Some(FieldValue::value(Value::from_json(value.clone())?))),
+            }
+        })
+    })
+}
+
+/// A field of an object type, read from the JSON object at the key `name`
+pub(crate) fn json_object_field(name: &str, ty: TypeRef) -> Field {
+    let key = name.to_string();
+    Field::new(name, ty, move |ctx| {
+        let key = key.clone();
+        FieldFuture::new(async move {
+            match parent(&ctx)?.get(&key) {
+                None | Some(serde_json::Value::Null) => Ok(None),
+                Some(value) => Ok(Some(FieldValue::owned_any(value.clone()))),
+            }
+        })
+    })
+}
+
+/// `uni_transfers` => `uniTransfers`, `Account` => `account`
+pub(crate) fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for (i, c) in name.chars().enumerate() {
+        match c {
+            '_' | '-' if i > 0 => upper = true,
+            _ if i == 0 => camel.push(c.to_ascii_lowercase()),
+            _ if upper => {
+                camel.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            _ => camel.push(c),
+        }
+    }
+
+    camel
+}
+
+/// `uni_transfers` => `UniTransfers`
+pub(crate) fn pascal_case(name: &str) -> String {
+    let camel = camel_case(name);
+    let mut chars = camel.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_names_are_cased() {
+        assert_eq!(camel_case("uni_transfers"), "uniTransfers");
+        assert_eq!(camel_case("Account"), "account");
+        assert_eq!(camel_case("pool-v3_swaps"), "poolV3Swaps");
+        assert_eq!(pascal_case("uni_transfers"), "UniTransfers");
+    }
+}
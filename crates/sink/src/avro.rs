@@ -0,0 +1,161 @@
+use primitives::monitor::{Event, EventTx};
+
+/// The schema of the Avro messages, in Parsing Canonical Form so that it is also the input of the
+/// fingerprint. Addresses, hashes and selectors are 0x prefixed hex strings.
+pub const EVENT_SCHEMA: &str = concat!(
+    r#"{"name":"nexus.Event","type":"record","fields":["#,
+    r#"{"name":"monitor","type":"string"},"#,
+    r#"{"name":"chain_id","type":"long"},"#,
+    r#"{"name":"address","type":"string"},"#,
+    r#"{"name":"block_number","type":"long"},"#,
+    r#"{"name":"transaction_hash","type":"string"},"#,
+    r#"{"name":"log_index","type":"long"},"#,
+    r#"{"name":"topics","type":{"type":"array","items":"string"}},"#,
+    r#"{"name":"data","type":"bytes"},"#,
+    r#"{"name":"removed","type":"boolean"},"#,
+    r#"{"name":"transaction","type":["null",{"name":"nexus.EventTx","type":"record","fields":["#,
+    r#"{"name":"from","type":"string"},"#,
+    r#"{"name":"to","type":["null","string"]},"#,
+    r#"{"name":"gas_used","type":["null","long"]},"#,
+    r#"{"name":"method_selector","type":["null","string"]}"#,
+    r#"]}]}]}"#,
+);
+
+/// The seed of the CRC-64-AVRO fingerprint
+const EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+/// The CRC-64-AVRO (Rabin) fingerprint of a schema in Parsing Canonical Form
+pub fn fingerprint(schema: &str) -> u64 {
+    let mut table = [0u64; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut fp = i as u64;
+        for _ in 0..8 {
+            fp = (fp >> 1) ^ (EMPTY & (fp & 1).wrapping_neg());
+        }
+        *entry = fp;
+    }
+
+    schema.bytes().fold(EMPTY, |fp, byte| {
+        (fp >> 8) ^ table[((fp ^ byte as u64) & 0xff) as usize]
+    })
+}
+
+/// Encodes an event in the Avro single object encoding: the `C3 01` marker, the little endian
+/// fingerprint of [`EVENT_SCHEMA`], then the binary encoded record
+pub fn encode_event(monitor: &str, event: &Event) -> Vec<u8> {
+    let mut buf = vec![0xc3, 0x01];
+    buf.extend_from_slice(&fingerprint(EVENT_SCHEMA).to_le_bytes());
+
+    write_string(&mut buf, monitor);
+    write_long(&mut buf, event.chain_id as i64);
+    write_string(&mut buf, &event.address.to_string());
+    write_long(&mut buf, event.block_number as i64);
+    write_string(&mut buf, &event.transaction_hash.to_string());
+    write_long(&mut buf, event.log_index as i64);
+    if !event.topics.is_empty() {
+        write_long(&mut buf, event.topics.len() as i64);
+        for topic in event.topics.iter() {
+            write_string(&mut buf, &topic.to_string());
+        }
+    }
+    write_long(&mut buf, 0);
+    write_bytes(&mut buf, &event.data);
+    buf.push(event.removed as u8);
+    match &event.transaction {
+        Some(tx) => {
+            write_long(&mut buf, 1);
+            write_event_tx(&mut buf, tx);
+        }
+        None => write_long(&mut buf, 0),
+    }
+
+    buf
+}
+
+fn write_event_tx(buf: &mut Vec<u8>, tx: &EventTx) {
+    write_string(buf, &tx.from.to_string());
+    write_optional(buf, tx.to.map(|to| to.to_string()), |buf, to| {
+        write_string(buf, &to)
+    });
+    write_optional(buf, tx.gas_used, |buf, gas| write_long(buf, gas as i64));
+    write_optional(
+        buf,
+        tx.method_selector.map(|selector| selector.to_string()),
+        |buf, selector| write_string(buf, &selector),
+    );
+}
+
+/// A `["null", T]` union
+fn write_optional<T>(buf: &mut Vec<u8>, value: Option<T>, write: impl FnOnce(&mut Vec<u8>, T)) {
+    match value {
+        Some(value) => {
+            write_long(buf, 1);
+            write(buf, value);
+        }
+        None => write_long(buf, 0),
+    }
+}
+
+/// Zigzag encoded variable length integer
+fn write_long(buf: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_bytes(buf, s.as_bytes());
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{Bytes, address};
+
+    #[test]
+    pub fn test_write_long_is_zigzag() {
+        let encode = |value| {
+            let mut buf = Vec::new();
+            write_long(&mut buf, value);
+            buf
+        };
+
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(-1), vec![0x01]);
+        assert_eq!(encode(1), vec![0x02]);
+        assert_eq!(encode(-64), vec![0x7f]);
+        assert_eq!(encode(64), vec![0x80, 0x01]);
+    }
+
+    #[test]
+    pub fn test_fingerprint_matches_spec() {
+        assert_eq!(fingerprint(r#""null""#), 7195948357588979594);
+    }
+
+    #[test]
+    pub fn test_encode_event() {
+        let event = Event {
+            address: address!("1f9840a85d5aF5bf1D1762F925BDADdC4201F984"),
+            block_number: 1,
+            data: Bytes::from_static(&[0x69]),
+            chain_id: 1,
+            ..Default::default()
+        };
+        let encoded = encode_event("uni", &event);
+
+        assert_eq!(encoded[..2], [0xc3, 0x01]);
+        assert_eq!(encoded[2..10], fingerprint(EVENT_SCHEMA).to_le_bytes());
+        // The monitor, then the chain id
+        assert_eq!(encoded[10..16], [0x06, b'u', b'n', b'i', 0x02, 0x54]);
+        // No topics, one byte of data, not removed, no transaction
+        assert_eq!(encoded[encoded.len() - 5..], [0x00, 0x02, 0x69, 0x00, 0x00]);
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{
+    batch_events, batch_transactions,
+    table::{Table, events_table, transactions_table},
+};
+use alloy::json_abi;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use primitives::{FileConfig, FileFormat, monitor::OutboxEntry, traits::EventSink};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Collects the batches of the file being written, one outbox entry per line
+const STAGING_FILE: &str = ".staging.jsonl";
+
+/// Lists the closed files of a monitor
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// A closed file and the blocks its rows come from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFile {
+    /// Relative to the directory of the monitor
+    pub file: String,
+    /// `events` or `transactions`
+    pub kind: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub rows: usize,
+}
+
+/// The content of `manifest.json`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The outbox id of the last batch in a closed file, redeliveries up to it are dropped
+    pub last_batch: u64,
+    pub files: Vec<ManifestFile>,
+}
+
+impl Manifest {
+    fn read(dir: &Path) -> Result<Self, anyhow::Error> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Replaces the manifest at once, readers never see a partial one
+    fn write(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        let tmp = dir.join(format!(".{MANIFEST_FILE}.tmp"));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, dir.join(MANIFEST_FILE))?;
+
+        Ok(())
+    }
+
+    /// Adds a closed file, a file written again after a crash replaces its entry
+    fn record(&mut self, file: ManifestFile) {
+        self.files.retain(|entry| entry.file != file.file);
+        self.files.push(file);
+    }
+}
+
+/// The lowest and highest block of the rows of a batch
+fn batch_blocks(batch: &OutboxEntry) -> Result<Option<(u64, u64)>, anyhow::Error> {
+    let blocks = batch_events(batch)?
+        .iter()
+        .map(|event| event.block_number)
+        .chain(batch_transactions(batch)?.iter().map(|tx| tx.block_number))
+        .fold(None, |range: Option<(u64, u64)>, block| match range {
+            Some((from, to)) => Some((from.min(block), to.max(block))),
+            None => Some((block, block)),
+        });
+
+    Ok(blocks)
+}
+
+/// The file being written, kept as staged batches until it is closed
+struct Staging {
+    dir: PathBuf,
+    /// The outbox id of the first staged batch, which keeps apart the files of the same blocks
+    first_batch: Option<u64>,
+    last_batch: u64,
+    blocks: Option<(u64, u64)>,
+    bytes: u64,
+}
+
+impl Staging {
+    /// Resumes the file left by a previous run, if any
+    fn open(dir: PathBuf) -> Result<Self, anyhow::Error> {
+        fs::create_dir_all(&dir)?;
+        let mut staging = Self {
+            last_batch: Manifest::read(&dir)?.last_batch,
+            dir,
+            first_batch: None,
+            blocks: None,
+            bytes: 0,
+        };
+        let (batches, bytes) = staging.batches()?;
+        for batch in batches.iter() {
+            staging.track(batch)?;
+        }
+        staging.bytes = bytes;
+
+        Ok(staging)
+    }
+
+    /// The staged batches and the length of their lines
+    fn batches(&self) -> Result<(Vec<OutboxEntry>, u64), anyhow::Error> {
+        let path = self.dir.join(STAGING_FILE);
+        if !path.exists() {
+            return Ok((Vec::new(), 0));
+        }
+
+        // A line cut short by a crash was never acknowledged, its batch comes again
+        let mut batches = Vec::new();
+        let mut bytes = 0;
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            if !line.ends_with('\n') {
+                break;
+            }
+            match serde_json::from_str(&line) {
+                Ok(batch) => batches.push(batch),
+                Err(_) => break,
+            }
+            bytes += line.len() as u64;
+            line.clear();
+        }
+
+        Ok((batches, bytes))
+    }
+
+    fn track(&mut self, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        self.first_batch.get_or_insert(batch.id);
+        self.last_batch = self.last_batch.max(batch.id);
+        if let Some((from, to)) = batch_blocks(batch)? {
+            self.blocks = Some(match self.blocks {
+                Some((staged_from, staged_to)) => (staged_from.min(from), staged_to.max(to)),
+                None => (from, to),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Appends a batch and syncs it to disk before it is acknowledged
+    fn append(&mut self, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let mut line = serde_json::to_vec(batch)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(STAGING_FILE))?;
+        // Drops what a crash may have left after the last complete line
+        file.set_len(self.bytes)?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+
+        self.bytes += line.len() as u64;
+        self.track(batch)
+    }
+
+    /// Writes the staged rows to their final files and records them in the manifest
+    fn close(
+        &mut self,
+        monitor: &str,
+        config: &FileConfig,
+        abi: Option<&json_abi::Event>,
+    ) -> Result<(), anyhow::Error> {
+        let (Some((from_block, to_block)), Some(first_batch)) = (self.blocks, self.first_batch)
+        else {
+            return Ok(());
+        };
+        let (batches, _) = self.batches()?;
+        let mut events = Vec::new();
+        let mut txs = Vec::new();
+        for batch in batches.iter() {
+            events.extend(batch_events(batch)?);
+            txs.extend(batch_transactions(batch)?);
+        }
+
+        let mut manifest = Manifest::read(&self.dir)?;
+        let tables = [
+            ("events", events_table(monitor, &events, abi)?),
+            ("transactions", transactions_table(monitor, &txs)),
+        ];
+        for (kind, table) in tables.iter() {
+            if table.rows.is_empty() {
+                continue;
+            }
+            let file = format!(
+                "{from_block:012}_{to_block:012}_{first_batch:012}.{kind}.{}",
+                extension(config.format)
+            );
+            write_table(&self.dir, &file, table, config.format)?;
+            manifest.record(ManifestFile {
+                file,
+                kind: kind.to_string(),
+                from_block,
+                to_block,
+                rows: table.rows.len(),
+            });
+        }
+        manifest.last_batch = self.last_batch;
+        manifest.write(&self.dir)?;
+
+        fs::remove_file(self.dir.join(STAGING_FILE))?;
+        self.first_batch = None;
+        self.blocks = None;
+        self.bytes = 0;
+
+        Ok(())
+    }
+}
+
+fn extension(format: FileFormat) -> &'static str {
+    match format {
+        FileFormat::Jsonl => "jsonl",
+        FileFormat::Csv => "csv",
+        FileFormat::Parquet => "parquet",
+    }
+}
+
+/// Writes next to the final name first, so that a file only appears once it is complete
+fn write_table(
+    dir: &Path,
+    file: &str,
+    table: &Table,
+    format: FileFormat,
+) -> Result<(), anyhow::Error> {
+    let tmp = dir.join(format!(".{file}.tmp"));
+    let writer = BufWriter::new(File::create(&tmp)?);
+    match format {
+        FileFormat::Jsonl => table.write_jsonl(writer)?,
+        FileFormat::Csv => table.write_csv(writer)?,
+        FileFormat::Parquet => table.write_parquet(writer)?,
+    }
+    fs::rename(tmp, dir.join(file))?;
+
+    Ok(())
+}
+
+struct FileWriter {
+    config: FileConfig,
+    abi: Option<json_abi::Event>,
+    staging: Option<Staging>,
+}
+
+impl FileWriter {
+    fn write(&mut self, monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let staging = match self.staging.as_mut() {
+            Some(staging) => staging,
+            None => self
+                .staging
+                .insert(Staging::open(Path::new(&self.config.path).join(monitor))?),
+        };
+        if batch.id <= staging.last_batch {
+            return Ok(());
+        }
+
+        if let (Some((from, _)), Some((_, to))) = (staging.blocks, batch_blocks(batch)?)
+            && to >= from.saturating_add(self.config.rotate_blocks)
+        {
+            staging.close(monitor, &self.config, self.abi.as_ref())?;
+        }
+        staging.append(batch)?;
+        if staging.bytes >= self.config.rotate_bytes {
+            staging.close(monitor, &self.config, self.abi.as_ref())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the rows of a monitor to rolling files under `<path>/<event_name>/`.
+/// The batches of the open file are staged in a JSON Lines file synced on every batch, the final
+/// file is written once it covers `rotate_blocks` blocks or `rotate_bytes` bytes of batches, and
+/// `manifest.json` then records the blocks it covers.
+pub struct FileSink {
+    name: String,
+    writer: Arc<Mutex<FileWriter>>,
+}
+
+impl FileSink {
+    pub fn new(name: String, config: &FileConfig) -> Result<Self, anyhow::Error> {
+        let abi = config
+            .event
+            .as_deref()
+            .map(json_abi::Event::parse)
+            .transpose()?;
+
+        Ok(Self {
+            name,
+            writer: Arc::new(Mutex::new(FileWriter {
+                config: config.clone(),
+                abi,
+                staging: None,
+            })),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for FileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn deliver(&self, monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let writer = self.writer.clone();
+        let monitor = monitor.to_string();
+        let batch = batch.clone();
+
+        tokio::task::spawn_blocking(move || {
+            writer
+                .lock()
+                .map_err(|_| anyhow!("file sink poisoned by a panic"))?
+                .write(&monitor, &batch)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use primitives::monitor::Event;
+
+    fn batch(id: u64, block_number: u64) -> OutboxEntry {
+        let event = Event {
+            block_number,
+            ..Default::default()
+        };
+        OutboxEntry {
+            id,
+            payload: serde_json::json!({"monitor": "uni", "events": [event]}),
+        }
+    }
+
+    #[test]
+    pub fn test_files_rotate_by_block_range() {
+        let path = std::env::temp_dir().join(format!("nexus_file_sink_{}", std::process::id()));
+        let mut writer = FileWriter {
+            config: FileConfig {
+                path: path.to_string_lossy().to_string(),
+                format: FileFormat::Csv,
+                rotate_blocks: 10,
+                rotate_bytes: u64::MAX,
+                event: None,
+            },
+            abi: None,
+            staging: None,
+        };
+
+        writer.write("uni", &batch(1, 100)).unwrap();
+        writer.write("uni", &batch(2, 105)).unwrap();
+        // A redelivered batch is dropped
+        writer.write("uni", &batch(2, 105)).unwrap();
+        writer.write("uni", &batch(3, 110)).unwrap();
+
+        let dir = path.join("uni");
+        let manifest = Manifest::read(&dir).unwrap();
+        assert_eq!(manifest.last_batch, 2);
+        assert_eq!(
+            manifest.files,
+            vec![ManifestFile {
+                file: "000000000100_000000000105_000000000001.events.csv".to_string(),
+                kind: "events".to_string(),
+                from_block: 100,
+                to_block: 105,
+                rows: 2,
+            }]
+        );
+        let csv = fs::read_to_string(dir.join(&manifest.files[0].file)).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+
+        // The open file is resumed after a restart
+        let staging = Staging::open(dir).unwrap();
+        assert_eq!(staging.last_batch, 3);
+        assert_eq!(staging.blocks, Some((110, 110)));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    pub fn test_files_rotated_over_the_same_block_are_kept_apart() {
+        let path =
+            std::env::temp_dir().join(format!("nexus_file_sink_same_{}", std::process::id()));
+        let mut writer = FileWriter {
+            config: FileConfig {
+                path: path.to_string_lossy().to_string(),
+                format: FileFormat::Jsonl,
+                rotate_blocks: 10,
+                rotate_bytes: 1,
+                event: None,
+            },
+            abi: None,
+            staging: None,
+        };
+
+        // Every batch fills a file on its own, both within block 100
+        writer.write("uni", &batch(1, 100)).unwrap();
+        writer.write("uni", &batch(2, 100)).unwrap();
+
+        let dir = path.join("uni");
+        let manifest = Manifest::read(&dir).unwrap();
+        assert_eq!(manifest.last_batch, 2);
+        let files: Vec<&str> = manifest
+            .files
+            .iter()
+            .map(|file| file.file.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "000000000100_000000000100_000000000001.events.jsonl",
+                "000000000100_000000000100_000000000002.events.jsonl",
+            ]
+        );
+        for file in files {
+            assert_eq!(
+                fs::read_to_string(dir.join(file)).unwrap().lines().count(),
+                1
+            );
+        }
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{batch_events, encode_event, event_id, message_key};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use primitives::{
+    KafkaConfig, MessageEncoding, MessageKey,
+    monitor::{Event, OutboxEntry},
+    traits::EventSink,
+};
+use rdkafka::{
+    ClientConfig,
+    message::{Header, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+};
+
+/// Header naming the monitor of the event
+pub const MONITOR_HEADER: &str = "nexus-monitor";
+
+/// Header carrying `<transaction_hash>:<log_index>`, whatever the key of the message
+pub const EVENT_HEADER: &str = "nexus-event";
+
+/// Header set to `true` on the messages retracting events removed by a reorg
+pub const REMOVED_HEADER: &str = "nexus-removed";
+
+/// Whether the retraction of `event` is a tombstone, only a key unique to the event may be deleted
+fn is_tombstone(key: MessageKey, event: &Event) -> bool {
+    event.removed && key == MessageKey::Event
+}
+
+/// Produces one message per event with an idempotent producer.
+/// An event removed by a reorg is retracted with a message carrying the `nexus-removed: true`
+/// header. With [`MessageKey::Event`] that message is a tombstone, without payload, which also
+/// drops the event from a compacted topic. With the other keys it carries the event, as a tombstone
+/// would drop every other event sharing the key.
+pub struct KafkaSink {
+    name: String,
+    topic: Option<String>,
+    key: MessageKey,
+    encoding: MessageEncoding,
+    producer: FutureProducer,
+}
+
+impl KafkaSink {
+    pub fn new(name: String, config: &KafkaConfig) -> Result<Self, anyhow::Error> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .set("max.in.flight.requests.per.connection", "5")
+            .set("message.timeout.ms", config.timeout_ms.to_string());
+        for (key, value) in config.properties.iter() {
+            client_config.set(key, value);
+        }
+
+        Ok(Self {
+            name,
+            topic: config.topic.clone(),
+            key: config.key,
+            encoding: config.encoding,
+            producer: client_config.create()?,
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for KafkaSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn deliver(&self, monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let topic = self.topic.as_deref().unwrap_or(monitor);
+        let events = batch_events(batch)?;
+
+        // Every message is queued before waiting for any acknowledgement, the producer keeps
+        // their order within a partition
+        let mut deliveries = Vec::with_capacity(events.len());
+        for event in events.iter() {
+            let key = message_key(self.key, event);
+            let id = event_id(event);
+            let mut headers = OwnedHeaders::new()
+                .insert(Header {
+                    key: MONITOR_HEADER,
+                    value: Some(monitor),
+                })
+                .insert(Header {
+                    key: EVENT_HEADER,
+                    value: Some(id.as_str()),
+                });
+
+            if event.removed {
+                headers = headers.insert(Header {
+                    key: REMOVED_HEADER,
+                    value: Some("true"),
+                });
+            }
+            let payload = if is_tombstone(self.key, event) {
+                None
+            } else {
+                Some(encode_event(self.encoding, monitor, event)?)
+            };
+
+            let mut record = FutureRecord::to(topic).key(&key).headers(headers);
+            if let Some(payload) = payload.as_ref() {
+                record = record.payload(payload);
+            }
+            let delivery = self
+                .producer
+                .send_result(record)
+                .map_err(|(err, _)| anyhow!("could not queue message for {topic}: {err}"))?;
+            deliveries.push(delivery);
+        }
+
+        for delivery in deliveries {
+            delivery
+                .await?
+                .map_err(|(err, _)| anyhow!("could not deliver message to {topic}: {err}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{Address, B256};
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_only_event_keys_are_retracted_with_tombstones() {
+        let removed = Event {
+            removed: true,
+            ..Default::default()
+        };
+        assert!(is_tombstone(MessageKey::Event, &removed));
+        assert!(!is_tombstone(MessageKey::TransactionHash, &removed));
+        assert!(!is_tombstone(MessageKey::Address, &removed));
+        assert!(!is_tombstone(MessageKey::Event, &Event::default()));
+    }
+
+    // Needs a broker listening on localhost:9092
+    #[tokio::test]
+    #[ignore]
+    pub async fn test_deliver_to_local_broker() {
+        let sink = KafkaSink::new(
+            "kafka".to_string(),
+            &KafkaConfig {
+                brokers: "localhost:9092".to_string(),
+                topic: Some("nexus_test_events".to_string()),
+                key: MessageKey::Event,
+                encoding: MessageEncoding::Avro,
+                properties: BTreeMap::new(),
+                timeout_ms: 10_000,
+            },
+        )
+        .unwrap();
+
+        let event = Event {
+            address: Address::repeat_byte(0x11),
+            transaction_hash: B256::repeat_byte(0x22),
+            block_number: 1,
+            ..Default::default()
+        };
+        let removed = Event {
+            removed: true,
+            ..event.clone()
+        };
+        let batch = OutboxEntry {
+            id: 1,
+            payload: serde_json::json!({"monitor": "test", "events": [event, removed]}),
+        };
+
+        assert!(sink.deliver("test", &batch).await.is_ok());
+    }
+}
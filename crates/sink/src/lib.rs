@@ -0,0 +1,281 @@
+pub mod avro;
+pub mod file;
+pub mod kafka;
+pub mod nats;
+pub mod redis;
+pub mod table;
+pub mod webhook;
+
+use crate::redis::RedisSink;
+use anyhow::bail;
+use file::FileSink;
+use futures::future::try_join_all;
+use kafka::KafkaSink;
+use nats::NatsSink;
+use primitives::{
+    MessageEncoding, MessageKey, MonitorKind, SinkConfig, SinkKind,
+    db::{
+        advance_sink_cursor, get_undelivered_outbox_entries, register_sink_cursor,
+        remove_stale_sink_cursors,
+    },
+    monitor::{Event, OutboxEntry, Tx},
+    traits::EventSink,
+};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{info, warn};
+use webhook::WebhookSink;
+
+/// Outbox entries read per query
+const BATCHES_PER_READ: i64 = 100;
+
+/// How long an idle sink waits before looking at the outbox again
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Builds the sink described by a config entry, for a monitor of the given kind.
+/// The Kafka, NATS and Redis sinks publish one message per event, they only take event monitors.
+pub fn build_sink(
+    config: &SinkConfig,
+    monitor: MonitorKind,
+) -> Result<Box<dyn EventSink>, anyhow::Error> {
+    let per_event = matches!(
+        config.kind,
+        SinkKind::Kafka(_) | SinkKind::Nats(_) | SinkKind::Redis(_)
+    );
+    if per_event && !monitor.queues_events() {
+        bail!(
+            "sink {}: kafka, nats and redis sinks only publish the events of event, factory and token monitors",
+            config.name
+        );
+    }
+
+    match &config.kind {
+        SinkKind::Webhook(webhook) => Ok(Box::new(WebhookSink::new(config.name.clone(), webhook)?)),
+        SinkKind::Kafka(kafka) => Ok(Box::new(KafkaSink::new(config.name.clone(), kafka)?)),
+        SinkKind::Nats(nats) => Ok(Box::new(NatsSink::new(config.name.clone(), nats))),
+        SinkKind::Redis(redis) => Ok(Box::new(RedisSink::new(config.name.clone(), redis)?)),
+        SinkKind::File(file) => Ok(Box::new(FileSink::new(config.name.clone(), file)?)),
+    }
+}
+
+/// The events of a batch, for the sinks sending one message per event.
+/// Empty for the batches of the transaction monitors.
+pub fn batch_events(batch: &OutboxEntry) -> Result<Vec<Event>, anyhow::Error> {
+    match batch.payload.get("events") {
+        Some(events) => Ok(serde_json::from_value(events.clone())?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The transactions of a batch, empty for the batches of the event monitors
+pub fn batch_transactions(batch: &OutboxEntry) -> Result<Vec<Tx>, anyhow::Error> {
+    match batch.payload.get("transactions") {
+        Some(txs) => Ok(serde_json::from_value(txs.clone())?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `<transaction_hash>:<log_index>`, identifies an event on its chain
+pub fn event_id(event: &Event) -> String {
+    format!("{}:{}", event.transaction_hash, event.log_index)
+}
+
+/// The key of the message of an event
+pub fn message_key(key: MessageKey, event: &Event) -> String {
+    match key {
+        MessageKey::Event => event_id(event),
+        MessageKey::TransactionHash => event.transaction_hash.to_string(),
+        MessageKey::Address => event.address.to_string(),
+    }
+}
+
+/// An event as written in a JSON message
+#[derive(Serialize)]
+struct MonitorEvent<'a> {
+    monitor: &'a str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// The payload of the message of an event
+pub fn encode_event(
+    encoding: MessageEncoding,
+    monitor: &str,
+    event: &Event,
+) -> Result<Vec<u8>, anyhow::Error> {
+    match encoding {
+        MessageEncoding::Json => Ok(serde_json::to_vec(&MonitorEvent { monitor, event })?),
+        MessageEncoding::Avro => Ok(avro::encode_event(monitor, event)),
+    }
+}
+
+/// The delay before the retry following `attempt` failed deliveries, doubled every time
+pub fn backoff(config: &SinkConfig, attempt: u32) -> Duration {
+    Duration::from_millis(
+        config
+            .initial_backoff_ms
+            .saturating_mul(1u64 << attempt.min(32))
+            .min(config.max_backoff_ms),
+    )
+}
+
+/// Feeds the sinks of a monitor from its outbox.
+/// Every sink has its own cursor and delivers the batches one at a time in commit order. A failed
+/// batch is retried with backoff until it succeeds, so a sink never skips a batch and a slow or
+/// unreachable sink does not hold back the others.
+pub struct SinkDispatcher {
+    monitor: String,
+    sinks: Vec<(SinkConfig, Box<dyn EventSink>)>,
+}
+
+impl SinkDispatcher {
+    pub fn new(
+        monitor: String,
+        kind: MonitorKind,
+        configs: &[SinkConfig],
+    ) -> Result<Self, anyhow::Error> {
+        let sinks = configs
+            .iter()
+            .map(|config| Ok((config.clone(), build_sink(config, kind)?)))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        Ok(Self { monitor, sinks })
+    }
+
+    async fn run_sink(
+        &self,
+        config: &SinkConfig,
+        sink: &dyn EventSink,
+        db_client: &tokio_postgres::Client,
+    ) -> Result<(), anyhow::Error> {
+        register_sink_cursor(db_client, &self.monitor, sink.name()).await?;
+        info!("{}: delivering to sink {}", self.monitor, sink.name());
+
+        loop {
+            let batches = get_undelivered_outbox_entries(
+                db_client,
+                &self.monitor,
+                sink.name(),
+                BATCHES_PER_READ,
+            )
+            .await?;
+            if batches.is_empty() {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+
+            for batch in batches.iter() {
+                let mut attempt = 0;
+                while let Err(err) = sink.deliver(&self.monitor, batch).await {
+                    let delay = backoff(config, attempt);
+                    warn!(
+                        "{}: sink {} failed to deliver batch {}, retrying in {delay:?}: {err}",
+                        self.monitor,
+                        sink.name(),
+                        batch.id
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                advance_sink_cursor(db_client, &self.monitor, sink.name(), batch.id).await?;
+            }
+        }
+    }
+
+    /// Delivers the outbox of the monitor to every sink until a db error occurs
+    pub async fn run(&self, db_client: &tokio_postgres::Client) -> Result<(), anyhow::Error> {
+        // The outbox is pruned up to the slowest cursor, a sink renamed or removed from the
+        // config would hold it back forever
+        let sinks: Vec<String> = self
+            .sinks
+            .iter()
+            .map(|(_, sink)| sink.name().to_string())
+            .collect();
+        let removed = remove_stale_sink_cursors(db_client, &self.monitor, &sinks).await?;
+        if removed > 0 {
+            info!(
+                "{}: dropped {removed} cursors of removed sinks",
+                self.monitor
+            );
+        }
+
+        try_join_all(
+            self.sinks
+                .iter()
+                .map(|(config, sink)| self.run_sink(config, sink.as_ref(), db_client)),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{Address, B256};
+    use primitives::{NatsConfig, WebhookConfig};
+
+    #[test]
+    pub fn test_backoff_is_capped() {
+        let config = SinkConfig {
+            name: "alerts".to_string(),
+            initial_backoff_ms: 500,
+            max_backoff_ms: 60_000,
+            kind: SinkKind::Webhook(WebhookConfig {
+                url: "http://localhost:8080".to_string(),
+                secret: None,
+                timeout_ms: 10_000,
+            }),
+        };
+
+        assert_eq!(backoff(&config, 0), Duration::from_millis(500));
+        assert_eq!(backoff(&config, 3), Duration::from_millis(4000));
+        assert_eq!(backoff(&config, 40), Duration::from_millis(60_000));
+    }
+
+    #[test]
+    pub fn test_per_event_sinks_need_an_event_monitor() {
+        let config = SinkConfig {
+            name: "stream".to_string(),
+            initial_backoff_ms: 500,
+            max_backoff_ms: 60_000,
+            kind: SinkKind::Nats(NatsConfig {
+                url: "nats://localhost:4222".to_string(),
+                subject: None,
+                stream: None,
+                encoding: MessageEncoding::Json,
+            }),
+        };
+
+        assert!(build_sink(&config, MonitorKind::Erc20).is_ok());
+        assert!(build_sink(&config, MonitorKind::Transfers).is_err());
+    }
+
+    #[test]
+    pub fn test_batch_events_round_trip() {
+        let event = Event {
+            address: Address::repeat_byte(0x11),
+            transaction_hash: B256::repeat_byte(0x22),
+            log_index: 3,
+            removed: true,
+            ..Default::default()
+        };
+        let batch = OutboxEntry {
+            id: 7,
+            payload: serde_json::json!({"monitor": "uni", "events": [event]}),
+        };
+
+        let events = batch_events(&batch).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].removed);
+        assert_eq!(
+            message_key(MessageKey::Event, &events[0]),
+            format!("{}:3", B256::repeat_byte(0x22))
+        );
+        assert_eq!(
+            message_key(MessageKey::Address, &events[0]),
+            Address::repeat_byte(0x11).to_string()
+        );
+    }
+}
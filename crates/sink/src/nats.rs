@@ -0,0 +1,93 @@
+use crate::{
+    batch_events, encode_event, event_id,
+    kafka::{EVENT_HEADER, MONITOR_HEADER, REMOVED_HEADER},
+};
+use anyhow::anyhow;
+use async_nats::{
+    HeaderMap,
+    jetstream::{self, context::Publish, stream},
+};
+use async_trait::async_trait;
+use primitives::{NatsConfig, monitor::OutboxEntry, traits::EventSink};
+use tokio::sync::OnceCell;
+
+/// Publishes one message per event to a JetStream subject and waits for the stream to store it.
+/// An event removed by a reorg is published again with the `nexus-removed: true` header. Every
+/// message carries a `Nats-Msg-Id` made of the outbox batch id and the position of the event in the
+/// batch, so the stream drops the copies of a redelivered batch that arrive within its duplicate
+/// window, while an event removed and mined again is a new batch and goes through.
+pub struct NatsSink {
+    name: String,
+    config: NatsConfig,
+    context: OnceCell<jetstream::Context>,
+}
+
+impl NatsSink {
+    pub fn new(name: String, config: &NatsConfig) -> Self {
+        Self {
+            name,
+            config: config.clone(),
+            context: OnceCell::new(),
+        }
+    }
+
+    /// Connects on the first delivery, a failed connection is retried with the batch
+    async fn context(&self, subject: &str) -> Result<&jetstream::Context, anyhow::Error> {
+        self.context
+            .get_or_try_init(|| async {
+                let client = async_nats::connect(&self.config.url).await?;
+                let context = jetstream::new(client);
+                if let Some(stream) = &self.config.stream {
+                    context
+                        .get_or_create_stream(stream::Config {
+                            name: stream.clone(),
+                            subjects: vec![subject.to_string()],
+                            ..Default::default()
+                        })
+                        .await?;
+                }
+
+                Ok(context)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl EventSink for NatsSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn deliver(&self, monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let subject = match &self.config.subject {
+            Some(subject) => subject.clone(),
+            None => format!("nexus.{monitor}"),
+        };
+        let context = self.context(&subject).await?;
+
+        let mut acks = Vec::new();
+        for (position, event) in batch_events(batch)?.iter().enumerate() {
+            let id = event_id(event);
+            let mut headers = HeaderMap::new();
+            headers.insert(MONITOR_HEADER, monitor);
+            headers.insert(EVENT_HEADER, id.as_str());
+            if event.removed {
+                headers.insert(REMOVED_HEADER, "true");
+            }
+
+            let publish = Publish::build()
+                .payload(encode_event(self.config.encoding, monitor, event)?.into())
+                .headers(headers)
+                .message_id(format!("{monitor}:{}:{position}", batch.id));
+            acks.push(context.send_publish(subject.clone(), publish).await?);
+        }
+
+        for ack in acks {
+            ack.await
+                .map_err(|err| anyhow!("{subject} did not store the message: {err}"))?;
+        }
+
+        Ok(())
+    }
+}
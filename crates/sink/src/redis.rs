@@ -0,0 +1,113 @@
+use crate::{batch_events, encode_event, event_id};
+use async_trait::async_trait;
+use primitives::{RedisConfig, monitor::OutboxEntry, traits::EventSink};
+use redis::{Client, aio::ConnectionManager};
+use tokio::sync::OnceCell;
+
+/// Appends one entry per event to a capped stream, the entries of a batch in one `MULTI`.
+/// Every entry has the fields `monitor`, `event` (`<transaction_hash>:<log_index>`), `removed` and
+/// `payload`. An event removed by a reorg is appended again with `removed` set to `true`.
+pub struct RedisSink {
+    name: String,
+    config: RedisConfig,
+    client: Client,
+    connection: OnceCell<ConnectionManager>,
+}
+
+impl RedisSink {
+    pub fn new(name: String, config: &RedisConfig) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            name,
+            config: config.clone(),
+            client: Client::open(config.url.as_str())?,
+            connection: OnceCell::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for RedisSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn deliver(&self, monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let stream = match &self.config.stream {
+            Some(stream) => stream.clone(),
+            None => format!("nexus:{monitor}"),
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for event in batch_events(batch)?.iter() {
+            pipe.cmd("XADD")
+                .arg(&stream)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(self.config.max_len)
+                .arg("*")
+                .arg("monitor")
+                .arg(monitor)
+                .arg("event")
+                .arg(event_id(event))
+                .arg("removed")
+                .arg(event.removed.to_string())
+                .arg("payload")
+                .arg(encode_event(self.config.encoding, monitor, event)?)
+                .ignore();
+        }
+
+        // The connection manager reconnects by itself once created
+        let mut connection = self
+            .connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await?
+            .clone();
+        pipe.query_async::<()>(&mut connection).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::B256;
+    use primitives::{MessageEncoding, monitor::Event};
+    use redis::AsyncCommands;
+
+    // Needs a Redis server listening on localhost:6379
+    #[tokio::test]
+    #[ignore]
+    pub async fn test_deliver_to_local_redis() {
+        let config = RedisConfig {
+            url: "redis://localhost:6379".to_string(),
+            stream: Some("nexus:test".to_string()),
+            max_len: 1000,
+            encoding: MessageEncoding::Json,
+        };
+        let sink = RedisSink::new("redis".to_string(), &config).unwrap();
+
+        let event = Event {
+            transaction_hash: B256::repeat_byte(0x22),
+            ..Default::default()
+        };
+        let removed = Event {
+            removed: true,
+            ..event.clone()
+        };
+        let batch = OutboxEntry {
+            id: 1,
+            payload: serde_json::json!({"monitor": "test", "events": [event, removed]}),
+        };
+        sink.deliver("test", &batch).await.unwrap();
+
+        let mut connection = Client::open(config.url.as_str())
+            .unwrap()
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        let len: u64 = connection.xlen("nexus:test").await.unwrap();
+        assert!(len >= 2);
+    }
+}
@@ -0,0 +1,430 @@
+use alloy::{
+    dyn_abi::{DynSolType, DynSolValue, Specifier},
+    json_abi,
+};
+use anyhow::anyhow;
+use arrow_array::{
+    ArrayRef, BinaryArray, BooleanArray, Int64Array, RecordBatch, StringArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use monitor::{calls::format_value, events::evm::decode_event_params};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use primitives::monitor::{Event, Tx};
+use std::{io::Write, sync::Arc};
+
+/// The type of a column, integers wider than 64 bits are written in decimal text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    UInt64,
+    Int64,
+    Boolean,
+    Utf8,
+    Binary,
+}
+
+/// A value of a row, `None` for a null of the type of its column
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    UInt64(Option<u64>),
+    Int64(Option<i64>),
+    Boolean(Option<bool>),
+    Utf8(Option<String>),
+    Binary(Option<Vec<u8>>),
+}
+
+impl Cell {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Cell::UInt64(value) => serde_json::json!(value),
+            Cell::Int64(value) => serde_json::json!(value),
+            Cell::Boolean(value) => serde_json::json!(value),
+            Cell::Utf8(value) => serde_json::json!(value),
+            Cell::Binary(value) => {
+                serde_json::json!(value.as_ref().map(alloy::hex::encode_prefixed))
+            }
+        }
+    }
+
+    /// Nulls are empty fields
+    fn to_csv(&self) -> String {
+        match self {
+            Cell::UInt64(value) => value.map(|value| value.to_string()),
+            Cell::Int64(value) => value.map(|value| value.to_string()),
+            Cell::Boolean(value) => value.map(|value| value.to_string()),
+            Cell::Utf8(value) => value.clone(),
+            Cell::Binary(value) => value.as_ref().map(alloy::hex::encode_prefixed),
+        }
+        .unwrap_or_default()
+    }
+}
+
+/// Rows of the same columns, written to a file in one of the export formats
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    pub columns: Vec<(String, ColumnType)>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+const EVENT_COLUMNS: [(&str, ColumnType); 16] = [
+    ("chain_id", ColumnType::UInt64),
+    ("block_number", ColumnType::UInt64),
+    ("transaction_hash", ColumnType::Utf8),
+    ("log_index", ColumnType::UInt64),
+    ("address", ColumnType::Utf8),
+    ("topic0", ColumnType::Utf8),
+    ("topic1", ColumnType::Utf8),
+    ("topic2", ColumnType::Utf8),
+    ("topic3", ColumnType::Utf8),
+    ("data", ColumnType::Binary),
+    ("removed", ColumnType::Boolean),
+    ("tx_from", ColumnType::Utf8),
+    ("tx_to", ColumnType::Utf8),
+    ("tx_gas_used", ColumnType::UInt64),
+    ("tx_method_selector", ColumnType::Utf8),
+    ("monitor", ColumnType::Utf8),
+];
+
+const TX_COLUMNS: [(&str, ColumnType); 18] = [
+    ("chain_id", ColumnType::UInt64),
+    ("block_number", ColumnType::UInt64),
+    ("block_hash", ColumnType::Utf8),
+    ("hash", ColumnType::Utf8),
+    ("nonce", ColumnType::UInt64),
+    ("from", ColumnType::Utf8),
+    ("to", ColumnType::Utf8),
+    ("contract_address", ColumnType::Utf8),
+    ("value", ColumnType::Utf8),
+    ("gas_price", ColumnType::Utf8),
+    ("gas_limit", ColumnType::UInt64),
+    ("max_fee_per_gas", ColumnType::Utf8),
+    ("data", ColumnType::Binary),
+    ("tx_type", ColumnType::UInt64),
+    ("l1_fee", ColumnType::Utf8),
+    ("l1_gas_used", ColumnType::Utf8),
+    ("l1_block_number", ColumnType::UInt64),
+    ("monitor", ColumnType::Utf8),
+];
+
+/// The column of a decoded parameter, integers up to 64 bits keep a numeric type
+fn param_column_type(ty: &DynSolType) -> ColumnType {
+    match ty {
+        DynSolType::Uint(bits) if *bits <= 64 => ColumnType::UInt64,
+        DynSolType::Int(bits) if *bits <= 64 => ColumnType::Int64,
+        DynSolType::Bool => ColumnType::Boolean,
+        _ => ColumnType::Utf8,
+    }
+}
+
+fn param_cell(ty: ColumnType, value: Option<&DynSolValue>) -> Cell {
+    match ty {
+        ColumnType::UInt64 => Cell::UInt64(
+            value
+                .and_then(DynSolValue::as_uint)
+                .map(|(value, _)| value.saturating_to()),
+        ),
+        ColumnType::Int64 => Cell::Int64(
+            value
+                .and_then(DynSolValue::as_int)
+                .and_then(|(value, _)| i64::try_from(value).ok()),
+        ),
+        ColumnType::Boolean => Cell::Boolean(value.and_then(DynSolValue::as_bool)),
+        _ => Cell::Utf8(value.map(format_value)),
+    }
+}
+
+/// The events of a monitor, with a column per parameter of `abi` when it is given.
+/// A parameter named like a fixed column, or unnamed, becomes `param_<position>`.
+pub fn events_table(
+    monitor: &str,
+    events: &[Event],
+    abi: Option<&json_abi::Event>,
+) -> Result<Table, anyhow::Error> {
+    let mut columns: Vec<(String, ColumnType)> = EVENT_COLUMNS
+        .iter()
+        .map(|(name, ty)| (name.to_string(), *ty))
+        .collect();
+    let mut params = Vec::new();
+    for (position, input) in abi
+        .map(|abi| abi.inputs.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let ty = param_column_type(&input.resolve()?);
+        let name =
+            if input.name.is_empty() || columns.iter().any(|(column, _)| *column == input.name) {
+                format!("param_{position}")
+            } else {
+                input.name.clone()
+            };
+        columns.push((name, ty));
+        params.push(ty);
+    }
+
+    let rows = events
+        .iter()
+        .map(|event| {
+            let topic =
+                |index: usize| Cell::Utf8(event.topics.get(index).map(|topic| topic.to_string()));
+            let tx = event.transaction.as_ref();
+            let mut row = vec![
+                Cell::UInt64(Some(event.chain_id)),
+                Cell::UInt64(Some(event.block_number)),
+                Cell::Utf8(Some(event.transaction_hash.to_string())),
+                Cell::UInt64(Some(event.log_index)),
+                Cell::Utf8(Some(event.address.to_string())),
+                topic(0),
+                topic(1),
+                topic(2),
+                topic(3),
+                Cell::Binary(Some(event.data.to_vec())),
+                Cell::Boolean(Some(event.removed)),
+                Cell::Utf8(tx.map(|tx| tx.from.to_string())),
+                Cell::Utf8(tx.and_then(|tx| tx.to).map(|to| to.to_string())),
+                Cell::UInt64(tx.and_then(|tx| tx.gas_used)),
+                Cell::Utf8(
+                    tx.and_then(|tx| tx.method_selector)
+                        .map(|selector| selector.to_string()),
+                ),
+                Cell::Utf8(Some(monitor.to_string())),
+            ];
+
+            // Logs of another event leave the parameters null
+            let decoded = abi
+                .and_then(|abi| decode_event_params(abi, &event.topics, &event.data).ok())
+                .unwrap_or_default();
+            for (position, ty) in params.iter().enumerate() {
+                row.push(param_cell(
+                    *ty,
+                    decoded.get(position).map(|(_, value)| value),
+                ));
+            }
+            row
+        })
+        .collect();
+
+    Ok(Table { columns, rows })
+}
+
+/// The transactions of a monitor
+pub fn transactions_table(monitor: &str, txs: &[Tx]) -> Table {
+    let columns = TX_COLUMNS
+        .iter()
+        .map(|(name, ty)| (name.to_string(), *ty))
+        .collect();
+    let rows = txs
+        .iter()
+        .map(|tx| {
+            vec![
+                Cell::UInt64(Some(tx.chain_id)),
+                Cell::UInt64(Some(tx.block_number)),
+                Cell::Utf8(Some(tx.block_hash.to_string())),
+                Cell::Utf8(Some(tx.hash.to_string())),
+                Cell::UInt64(Some(tx.nonce)),
+                Cell::Utf8(Some(tx.from.to_string())),
+                Cell::Utf8(tx.to.map(|to| to.to_string())),
+                Cell::Utf8(tx.contract_address.map(|address| address.to_string())),
+                Cell::Utf8(Some(tx.value.to_string())),
+                Cell::Utf8(Some(tx.gas_price.to_string())),
+                Cell::UInt64(Some(tx.gas_limit)),
+                Cell::Utf8(Some(tx.max_fee_per_gas.to_string())),
+                Cell::Binary(Some(tx.data.to_vec())),
+                Cell::UInt64(Some(tx.tx_type as u64)),
+                Cell::Utf8(tx.l1_fee.map(|fee| fee.to_string())),
+                Cell::Utf8(tx.l1_gas_used.map(|gas| gas.to_string())),
+                Cell::UInt64(tx.l1_block_number),
+                Cell::Utf8(Some(monitor.to_string())),
+            ]
+        })
+        .collect();
+
+    Table { columns, rows }
+}
+
+impl Table {
+    /// One JSON object per line
+    pub fn write_jsonl(&self, mut writer: impl Write) -> Result<(), anyhow::Error> {
+        for row in self.rows.iter() {
+            let object: serde_json::Map<String, serde_json::Value> = self
+                .columns
+                .iter()
+                .zip(row.iter())
+                .map(|((name, _), cell)| (name.clone(), cell.to_json()))
+                .collect();
+            serde_json::to_writer(&mut writer, &object)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// A header line, then one record per row
+    pub fn write_csv(&self, writer: impl Write) -> Result<(), anyhow::Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(self.columns.iter().map(|(name, _)| name))?;
+        for row in self.rows.iter() {
+            writer.write_record(row.iter().map(Cell::to_csv))?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// A single row group compressed with snappy
+    pub fn write_parquet(&self, writer: impl Write + Send) -> Result<(), anyhow::Error> {
+        let batch = self.record_batch()?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(writer, batch.schema(), Some(properties))?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        Ok(())
+    }
+
+    fn record_batch(&self) -> Result<RecordBatch, anyhow::Error> {
+        let fields: Vec<Field> = self
+            .columns
+            .iter()
+            .map(|(name, ty)| {
+                let data_type = match ty {
+                    ColumnType::UInt64 => DataType::UInt64,
+                    ColumnType::Int64 => DataType::Int64,
+                    ColumnType::Boolean => DataType::Boolean,
+                    ColumnType::Utf8 => DataType::Utf8,
+                    ColumnType::Binary => DataType::Binary,
+                };
+                Field::new(name, data_type, true)
+            })
+            .collect();
+
+        let arrays = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, (name, ty))| {
+                let cells = self.rows.iter().map(|row| &row[index]);
+                let mismatch = || anyhow!("column {name} holds a value of another type");
+                let array: ArrayRef = match ty {
+                    ColumnType::UInt64 => Arc::new(UInt64Array::from(
+                        cells
+                            .map(|cell| match cell {
+                                Cell::UInt64(value) => Ok(*value),
+                                _ => Err(mismatch()),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )),
+                    ColumnType::Int64 => Arc::new(Int64Array::from(
+                        cells
+                            .map(|cell| match cell {
+                                Cell::Int64(value) => Ok(*value),
+                                _ => Err(mismatch()),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )),
+                    ColumnType::Boolean => Arc::new(BooleanArray::from(
+                        cells
+                            .map(|cell| match cell {
+                                Cell::Boolean(value) => Ok(*value),
+                                _ => Err(mismatch()),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )),
+                    ColumnType::Utf8 => Arc::new(StringArray::from(
+                        cells
+                            .map(|cell| match cell {
+                                Cell::Utf8(value) => Ok(value.clone()),
+                                _ => Err(mismatch()),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )),
+                    ColumnType::Binary => Arc::new(BinaryArray::from_opt_vec(
+                        cells
+                            .map(|cell| match cell {
+                                Cell::Binary(value) => Ok(value.as_deref()),
+                                _ => Err(mismatch()),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )),
+                };
+                Ok(array)
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloy::primitives::{Address, B256, Bytes, U256};
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn transfer() -> (json_abi::Event, Event) {
+        let abi = json_abi::Event::parse(
+            "Transfer(address indexed from, address indexed to, uint256 value)",
+        )
+        .unwrap();
+        let event = Event {
+            address: Address::repeat_byte(0x11),
+            block_number: 42,
+            transaction_hash: B256::repeat_byte(0x22),
+            topics: vec![
+                abi.selector(),
+                Address::repeat_byte(0xaa).into_word(),
+                Address::repeat_byte(0xbb).into_word(),
+            ],
+            data: Bytes::from(U256::from(1000).to_be_bytes::<32>().to_vec()),
+            chain_id: 1,
+            ..Default::default()
+        };
+        (abi, event)
+    }
+
+    #[test]
+    pub fn test_events_table_decodes_params() {
+        let (abi, event) = transfer();
+        let table = events_table("uni", &[event], Some(&abi)).unwrap();
+
+        let names: Vec<&str> = table
+            .columns
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names[names.len() - 3..], ["from", "to", "value"]);
+        assert_eq!(
+            table.rows[0][table.rows[0].len() - 1],
+            Cell::Utf8(Some("1000".to_string()))
+        );
+
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("chain_id,block_number,transaction_hash,"));
+        assert!(csv.lines().nth(1).unwrap().starts_with("1,42,"));
+    }
+
+    #[test]
+    pub fn test_parquet_keeps_column_types() {
+        let (abi, event) = transfer();
+        let table = events_table("uni", &[event], Some(&abi)).unwrap();
+        let mut parquet = Vec::new();
+        table.write_parquet(&mut parquet).unwrap();
+
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(parquet))
+            .unwrap()
+            .build()
+            .unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        let block_numbers = batch
+            .column_by_name("block_number")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(block_numbers.len(), 1);
+        assert_eq!(block_numbers.value(0), 42);
+    }
+}
@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use primitives::{WebhookConfig, monitor::OutboxEntry, traits::EventSink};
+use reqwest::header::CONTENT_TYPE;
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Header carrying the HMAC-SHA256 of the body, as `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "X-Nexus-Signature";
+
+/// Header carrying the outbox id of the batch, the same on every redelivery
+pub const DELIVERY_HEADER: &str = "X-Nexus-Delivery";
+
+/// The value of the signature header for `body`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// POSTs every batch as JSON, a batch is delivered once the endpoint answers with a 2xx status
+pub struct WebhookSink {
+    name: String,
+    url: String,
+    secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl WebhookSink {
+    pub fn new(name: String, config: &WebhookConfig) -> Result<Self, anyhow::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+
+        Ok(Self {
+            name,
+            url: config.url.clone(),
+            secret: config.secret.clone(),
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn deliver(&self, _monitor: &str, batch: &OutboxEntry) -> Result<(), anyhow::Error> {
+        let body = serde_json::to_vec(&batch.payload)?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(DELIVERY_HEADER, batch.id.to_string());
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        request.body(body).send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_sign_matches_rfc_4231() {
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
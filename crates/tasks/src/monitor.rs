@@ -1,66 +1,83 @@
 use crate::Task;
-use alloy::{
-    providers::{Provider, ProviderBuilder},
-    rpc::client::WsConnect,
-};
-use anyhow::bail;
+use anyhow::Context;
 use async_trait::async_trait;
-use monitor::events::EventMonitorTable;
-use primitives::{MonitorConfig, db::create_db_instance, traits::EventMonitor};
-use tokio::{select, try_join};
+use monitor::adapter::AdapterRegistry;
+use primitives::{
+    MonitorConfig,
+    db::{create_db_instance, create_outbox_db_table},
+};
+use sink::SinkDispatcher;
+use tokio::select;
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
 #[derive(Debug)]
 pub struct MonitorTask {
     config: MonitorConfig,
+    registry: AdapterRegistry,
 }
 
 #[async_trait]
 impl Task for MonitorTask {
     async fn run(mut self: Box<Self>, shutdown_token: CancellationToken) -> anyhow::Result<()> {
-        let mut client = create_db_instance(&self.config.db_url)
-            .await
-            .expect("Could not create db instance");
-        let ws = WsConnect::new(self.config.rpc_url.clone());
-        let provider = ProviderBuilder::new().connect_ws(ws).await?;
-        let evm_event_indexer = EventMonitorTable::new(self.config.event_name.clone());
+        let adapter = self.registry.get(&self.config.state_machine)?;
+        let mut client = create_db_instance(&self.config.db_url).await?;
 
-        // This queries events that have happened from this block number and stores them in the database
-        // It also subscribes to new events and stores them in the database
-        let evm_indexer_handle = tokio::spawn(async move {
-            select! {
-                event_n_sub = evm_event_indexer.query_and_subscribe_to_events(
-                    provider.root().clone(),
-                    self.config.address.clone().parse().expect("CONFIG address could not be parsed"),
-                    self.config.event_signature.clone().parse().expect("CONFIG event signature is missing"),
-                    self.config.block_number.into(),
-                    &mut client,
-                ) => {
-                    // Want this indexing to halt before
-                    if event_n_sub.is_err() {
-                        info!("Event subscription error, exitting now. ERROR: {:?}", event_n_sub.err().unwrap());
-                    }
-                }
-                _ = shutdown_token.cancelled() => {
-                    info!("Shutting down indexer");
-                }
+        // The sinks read the outbox on their own connection, while the indexer fills it
+        let dispatcher = if self.config.sinks.is_empty() {
+            None
+        } else {
+            create_outbox_db_table(&mut client, &self.config.event_name).await?;
+            let dispatcher = SinkDispatcher::new(
+                self.config.event_name.clone(),
+                self.config.kind,
+                &self.config.sinks,
+            )?;
+            let sink_client = create_db_instance(&self.config.db_url).await?;
+            Some((dispatcher, sink_client))
+        };
+        let name = &self.config.event_name;
+        let sinks = async {
+            match &dispatcher {
+                Some((dispatcher, sink_client)) => dispatcher.run(sink_client).await,
+                // Without sinks only the indexer runs
+                None => std::future::pending().await,
             }
-        });
+        };
 
-        match try_join!(evm_indexer_handle) {
-            Ok(_) => {
-                info!("Server task completed");
+        // The indexer queries the events that have happened from the configured block number and
+        // then follows the new ones, while the sinks deliver what it commits. When either of them
+        // stops the other one is dropped with it, so that the task never runs half of a monitor.
+        select! {
+            indexed = adapter.run(&self.config, &mut client) => {
+                indexed.with_context(|| format!("{name}: indexer failed"))?;
+                info!("{name}: indexer stopped");
+            }
+            delivered = sinks => {
+                delivered.with_context(|| format!("{name}: sink delivery failed"))?;
+                info!("{name}: sinks stopped");
+            }
+            _ = shutdown_token.cancelled() => {
+                info!("Shutting down {name}");
             }
-            Err(e) => bail!("Error running server: {:?}", e),
         }
+
         Ok(())
     }
 }
 
 impl MonitorTask {
     pub fn new(config: MonitorConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            registry: AdapterRegistry::default(),
+        }
+    }
+
+    /// Runs the monitor with the adapters of `registry` instead of the default ones
+    pub fn with_registry(mut self, registry: AdapterRegistry) -> Self {
+        self.registry = registry;
+        self
     }
 
     /// Converts the task into a boxed trait object.
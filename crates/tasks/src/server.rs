@@ -1,5 +1,5 @@
-use primitives::ServerConfig;
-use server::{db_query::QueryRoot, run_server};
+use primitives::{MonitorConfig, ServerConfig};
+use server::{db_query::QueryRoot, run_server, typed::typed_schema};
 
 use crate::Task;
 use anyhow::bail;
@@ -11,14 +11,17 @@ use tracing::info;
 #[derive(Debug)]
 pub struct ServerTask {
     pub config: ServerConfig,
+    /// The monitors served by the typed schema
+    pub monitors: Vec<MonitorConfig>,
 }
 
 #[async_trait]
 impl Task for ServerTask {
     async fn run(mut self: Box<Self>, shutdown_token: CancellationToken) -> anyhow::Result<()> {
+        let typed = typed_schema(&self.monitors)?;
         let server_handle = tokio::spawn(async move {
             select! {
-                server = run_server(self.config, QueryRoot) => {
+                server = run_server(self.config, QueryRoot, typed) => {
                     // Want this indexing to halt before
                     if server.is_err() {
                         info!("GraphQL server failed to start");
@@ -42,7 +45,16 @@ impl Task for ServerTask {
 
 impl ServerTask {
     pub fn new(config: ServerConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            monitors: Vec::new(),
+        }
+    }
+
+    /// Serves the events and entities of `monitors` on `/typed`
+    pub fn with_monitors(mut self, monitors: Vec<MonitorConfig>) -> Self {
+        self.monitors = monitors;
+        self
     }
 
     /// Converts the task into a boxed trait object.